@@ -58,13 +58,62 @@ const ALL_WORDS: [&[Word];3] = [&VERBS,&NOUNS,&TARGETS];
 ///////////////////////////////////////////////////////
 // VERBS 
 ///////////////////////////////////////////////////////
-pub const VERBS: [Word; 4] = [
+pub const VERBS: [Word; 30] = [
     Word{
         term: "add",
         kind: WordType::Verb,
         short_help: "Add a new <noun>",
-        long_help: "Use the add command to add a new <noun>, such as an enemy, or something like an effect to a player. Most nouns are supported, but consult the help pages for each for specifics about adding them.",
-        usage_examples: "!add player Plunk\n!add enemy Slurk",
+        long_help: "Use the add command to add a new <noun>, such as an enemy, or something like an effect to a player. Most nouns are supported, but consult the help pages for each for specifics about adding them. Characters can be given stats as they're added, and each character needs a name of their own. If there's a GM role, only GMs can add enemies.",
+        usage_examples: "!add player Plunk\n!add player Plunk HP:30/30 AC:18\n!add enemy Slurk\n!add stat Plunk FP:1/2",
+    },
+    Word{
+        term: "check",
+        kind: WordType::Verb,
+        short_help: "Roll a check against a DC",
+        long_help: "Rolls a check just like `!roll`, then compares it to the DC given after `vs` or `dc` to find the degree of success. Beating the DC by 10 or more is a critical success, meeting it a success, missing it a failure, and missing it by 10 or more a critical failure. A natural 20 makes the result one degree better, and a natural 1 one degree worse. Everyone sees the degree of success, even when the roll is an enemy's.",
+        usage_examples: "!check Plunk athletics vs 18\n!check reflex dc 20\n!check 1d20+7 dc 20",
+    },
+    Word{
+        term: "damage",
+        kind: WordType::Verb,
+        short_help: "Take HP away from a character",
+        long_help: "Damage is taken from a character's temporary HP first, then from their HP stat, which never drops below 0. The amount can be a number or a dice roll, followed by the type of damage. Damage of several types can be separated by commas, and precision or splash damage marked as such. The character's immunities, weaknesses and resistances are applied to typed damage. A player brought to 0 HP falls unconscious and is dying, while an enemy is usually dead. Only a character's owner or a GM can damage them.",
+        usage_examples: "!damage Plunk 12\n!damage Slurk 10 fire\n!damage \"War Boss\" 2d6+4 slashing, 1d6 precision\n!damage Slurk 1 fire splash",
+    },
+    Word{
+        term: "delay",
+        kind: WordType::Verb,
+        short_help: "Delay your turn in an encounter",
+        long_help: "Delaying ends the current combatant's turn and takes them out of the turn order until they choose to come back in with `!next <name>`, which gives them the next turn and puts them at that spot in the order from then on. Only whoever's turn it is can delay. Their owner or a GM has to be the one to do it.",
+        usage_examples: "!delay\n!delay Plunk\n!next Plunk",
+    },
+    Word{
+        term: "end",
+        kind: WordType::Verb,
+        short_help: "End an encounter",
+        long_help: "Ends the encounter that's going on. Effects on characters carry on as they were. If there's a GM role, only GMs can end encounters.",
+        usage_examples: "!end encounter",
+    },
+    Word{
+        term: "export",
+        kind: WordType::Verb,
+        short_help: "Export a character to a virtual tabletop",
+        long_help: "Sends a character back as a file you can load into a virtual tabletop. Right now Foundry VTT's pf2e system is supported: create an actor, then use Import Data on its sheet with the attached file. Foundry only holds finished numbers, so formulas are worked out first, and stats it has nowhere to put are listed so you can add them by hand. Only GMs can export enemies while enemy stats are hidden. GMs can also give a file name to save it in the bot's files directory instead.",
+        usage_examples: "!export foundry Plunk\n!export foundry \"Goblin Warrior\" goblin.json",
+    },
+    Word{
+        term: "give",
+        kind: WordType::Verb,
+        short_help: "Give a character to someone else",
+        long_help: "Hands a player or enemy over to someone else, who becomes their owner. Only a character's owner or a GM can change their stats, rename or remove them, so use this when a player joins partway or a GM takes over an NPC. Mention the new owner. Only the current owner or a GM can give a character away.",
+        usage_examples: "!give player Plunk @Bob\n!give enemy Slurk @GM",
+    },
+    Word{
+        term: "heal",
+        kind: WordType::Verb,
+        short_help: "Give HP back to a character",
+        long_help: "Healing adds to a character's HP stat, but never takes it above the stat's maximum. The amount can be a number or a dice roll. Only the owner of the character or a GM can heal them.",
+        usage_examples: "!heal Plunk 2d8+4\n!heal Plunk 5",
     },
     Word{
         term: "help",
@@ -73,6 +122,34 @@ pub const VERBS: [Word; 4] = [
         long_help: "Use the help command to get detailed help about any command word the bot recognizes. Which you probably already knew, since you just typed `!help help`. Clever girl.",
         usage_examples: "!help roll\n!help effect\n!help lookup",
     },
+    Word{
+        term: "history",
+        kind: WordType::Verb,
+        short_help: "See recent changes to the tracker",
+        long_help: "Every command that changes the tracker is saved as a new version of it, along with the command that made the change. The history command lists the most recent ones, newest first, along with the `!undo` or `!redo` command that will take the tracker back to each one.",
+        usage_examples: "!history",
+    },
+    Word{
+        term: "import",
+        kind: WordType::Verb,
+        short_help: "Import a character from a character builder",
+        long_help: "Creates a player from a character builder's export, instead of adding each stat by hand. Pathbuilder 2e and Foundry VTT's pf2e system are supported: use Pathbuilder's Export JSON option or Export Data on a Foundry actor's sheet, and attach the file to the command or paste it after. GMs can also give the name of a file in the bot's files directory. Foundry NPCs become enemies, so only GMs can import them. The character gets their level, ability modifiers, HP, AC, saves, perception, skills and lores, each weapon's attack and damage, spell attack and DC, and focus points. Their level, ability scores and proficiencies go on a sheet, which works out their skills and saves and keeps them right if any of those change later. See `!help proficiency`. The character belongs to whoever imported them.",
+        usage_examples: "!import pathbuilder (with the JSON file attached)\n!import pathbuilder {\"success\":true,\"build\":{...}}\n!import foundry goblin.json",
+    },
+    Word{
+        term: "init",
+        kind: WordType::Verb,
+        short_help: "Set or roll initiative",
+        long_help: "Sets a character's initiative in the current encounter, adding them to it if they weren't already in it. Without a number, it's rolled from their Initiative or Perception stat. Enemies go before players when initiatives tie. With no character at all, it shows the turn order. Anyone can see the turn order, but only a character's owner or a GM can set their initiative.",
+        usage_examples: "!init Plunk 17\n!init Slurk\n!init",
+    },
+    Word{
+        term: "list",
+        kind: WordType::Verb,
+        short_help: "List the characters the bot knows about",
+        long_help: "The list command shows the names of every player and enemy being tracked. To only see one kind, give the noun as well. Use `!show` to see everything about a specific character.",
+        usage_examples: "!list\n!list players\n!list enemies",
+    },
     Word{
         term: "lookup",
         kind: WordType::Verb,
@@ -80,32 +157,197 @@ pub const VERBS: [Word; 4] = [
         long_help: "The lookup command can look up the definitions of just about any Pathfinder thing there is, using the power of the Pathfinder 2 Easy Library. Feats, skills, spells, creatures, gods, you name it. If searching terns up more than one result, a list of options will be presented to you as reaction buttons to click. Simply click the correct button to select your choice.",
         usage_examples: "!lookup mage hand\n!lookup goblin dog\n!lookup cast a spell",
     },
+    Word{
+        term: "next",
+        kind: WordType::Verb,
+        short_help: "Move on to the next turn",
+        long_help: "Ends the current turn and starts the next one in initiative order. Effects count down and conditions like frightened wear off as turns end and begin, and dying characters are reminded to make their recovery check. Name someone who is delaying to give them the next turn instead. Only the owner of whoever's turn it is, or a GM, can end it.",
+        usage_examples: "!next\n!next Plunk",
+    },
+    Word{
+        term: "odds",
+        kind: WordType::Verb,
+        short_help: "Work out the odds of a roll without rolling it",
+        long_help: "The odds command takes anything `!roll` can, including a character's stats with their bonuses and penalties, and shows the exact chance of each total instead of rolling it. Rolls that can come to a lot of different totals have them grouped into ranges.\n\nWith a DC after `vs` or `dc`, it shows the chance of each degree of success instead. Add `crit` and a number for a natural that turns a success into a critical success, like `crit 19` for a keen weapon. Add `damage` and what to roll for it last to see the average damage of a Strike, which is doubled on a critical hit.",
+        usage_examples: "!odds 4d6kh3
+!odds Plunk athletics vs 18
+!odds fortune(1d20+7) dc 25
+!odds 1d20+12 vs 22 crit 19 damage 2d8+4",
+    },
+    Word{
+        term: "ready",
+        kind: WordType::Verb,
+        short_help: "Ready an action for later",
+        long_help: "Notes that a combatant has readied an action and what will trigger it, so it shows up in the turn order. Readied actions that haven't been used by the start of the character's next turn are dropped. Leave off the trigger to clear it once it's been used. Players can only ready actions for their own characters.",
+        usage_examples: "!ready Plunk Slurk comes through the door\n!ready Plunk",
+    },
+    Word{
+        term: "redo",
+        kind: WordType::Verb,
+        short_help: "Bring back changes that were undone",
+        long_help: "Redo brings back the most recent change that was taken away with `!undo`, or several changes if you give a number. Changes can only be redone until something else changes the tracker, at which point the undone changes are gone for good. If there's a GM role, only GMs can redo changes.",
+        usage_examples: "!redo\n!redo 2",
+    },
+    Word{
+        term: "remove",
+        kind: WordType::Verb,
+        short_help: "Remove a <noun>",
+        long_help: "Use the remove command to get rid of a character along with everything about them, or just some of a character's stats. Only the owner of a character, usually whoever added them, or a GM can remove them or their stats.",
+        usage_examples: "!remove enemy Goblin\n!remove stat Plunk FP\n!remove stat Plunk HP AC",
+    },
+    Word{
+        term: "rename",
+        kind: WordType::Verb,
+        short_help: "Give a character or stat a new name",
+        long_help: "The rename command changes the name of a character, or of one of a character's stats, without changing anything else about it. Put the new name last, in quotes if it has spaces in it. Only the owner of a character or a GM can rename them or their stats.",
+        usage_examples: "!rename player Plunk \"Plunk the Great\"\n!rename stat Plunk FP Focus",
+    },
     Word{
         term: "roll",
         kind: WordType::Verb,
         short_help: "Roll some dice",
-        long_help: "The roll command can be used to roll discrete dice and/or a stored property on a character. If a character is not supplied, it defaults to the character owned by you. If you own multiple, and you're in combaat, it defaults to the active character.",
-        usage_examples: "!roll 3d6+5\n!roll perception",
+        long_help: "The roll command can be used to roll discrete dice and/or a stored property on a character. If a character is not supplied, it defaults to the character owned by you. If you're in an encounter, it defaults to your character whose turn it is. Otherwise, if you own multiple, it uses whichever one has the stats being rolled, and asks you to name one if several do. Stats can be mixed in with dice, just like a dynamic stat.\n\nDice can keep only their highest or lowest rolls with `kh` or `kl` (`4d6kh3`), reroll low numbers once with `r` (`2d6r1`), and explode on their highest number with `x` (`1d6x`). Wrapping a roll in `fortune(...)` or `misfortune(...)` rolls it twice and uses the higher or lower result.\n\nEnding the roll with `dc` or `vs` and a number shows its degree of success against that DC, just like `!check`. Start with `secret` to send the result to the GMs instead, like `!sroll`.",
+        usage_examples: "!roll 3d6+5\n!roll perception\n!roll 1d20+STR\n!roll Plunk athletics+2\n!roll 4d6kh3\n!roll fortune(1d20+7)\n!roll 1d20+7 dc 20",
+    },
+    Word{
+        term: "rolls",
+        kind: WordType::Verb,
+        short_help: "Show the recent rolls, or prove they were fair",
+        long_help: "Every die the bot rolls comes from a seed that's kept secret until it's revealed. `!rolls` lists the recent rolls in this server along with a SHA-1 commitment to the seed they came from, so anyone can note it down before knowing the seed.\n\n`!rolls reveal` publishes the seed, checks it against the commitment and rolls every logged roll again from it to show they come out the same, then starts a new seed. Only GMs can reveal, since it also shows the result of secret rolls. The bot's seeds come from the `DICE_SECRET` environment variable, or a random secret each time it starts if that isn't set. The log is kept apart from the tracker's history, so `!undo` never takes back a roll or an old seed.",
+        usage_examples: "!rolls\n!rolls reveal",
+    },
+    Word{
+        term: "set",
+        kind: WordType::Verb,
+        short_help: "Change the value of a stat",
+        long_help: "Use the set command to change the value of stats a character already has. A stat's maximum is only changed if a new one is given after a forward slash, so setting HP after taking damage keeps the maximum as it was. Only the owner of a character or a GM can change their stats. Characters with a PF2e sheet have their proficiencies and ability scores changed with `!set proficiency` and `!set ability` instead.\n\n`!set gm` chooses the role your GMs have, given as a mention or the role's name. Once there's a GM role, enemies' stats are hidden from everyone else, and only GMs can change it. Use `!set gm off` to stop hiding anything.",
+        usage_examples: "!set stat Bob HP:20\n!set stat Bob HP:40/40 Level:5\n!set proficiency Bob Athletics:expert\n!set ability Bob STR:18\n!set gm @GM\n!set gm off",
+    },
+    Word{
+        term: "show",
+        kind: WordType::Verb,
+        short_help: "Show everything about a character",
+        long_help: "The show command replies with a character's name, whether they're a player or enemy, and all of their stats. The noun is optional, so giving just the character's name works too.",
+        usage_examples: "!show Plunk\n!show enemy Slurk",
+    },
+    Word{
+        term: "sroll",
+        kind: WordType::Verb,
+        short_help: "Roll in secret, for the GM's eyes only",
+        long_help: "Some rolls, like Recall Knowledge, Stealth or Sense Motive, are made in secret so players can't tell how well they did. `!sroll` (or `!roll secret`) rolls anything `!roll` can, for any character, and sends the full result to everyone with the GM role by DM. The channel is only told that a secret roll was made. Without a GM role, the result is sent to whoever rolled it.",
+        usage_examples: "!sroll Plunk society\n!sroll Slurk stealth\n!roll secret Plunk perception dc 20",
+    },
+    Word{
+        term: "start",
+        kind: WordType::Verb,
+        short_help: "Start an encounter",
+        long_help: "Starts an encounter with every character, or just the ones named. Initiative is rolled for anyone with an Initiative or Perception stat, and everyone else needs one set with `!init` before the first turn. Use `!next` to start the first turn. If there's a GM role, only GMs can start encounters.",
+        usage_examples: "!start encounter\n!start encounter Plunk Frank \"War Boss\"",
+    },
+    Word{
+        term: "sustain",
+        kind: WordType::Verb,
+        short_help: "Keep a sustained effect going",
+        long_help: "Effects added with a sustained duration end at the end of their creator's next turn, unless that character sustains them during the turn. Only a GM, or the owner of the character the effect is on or of its creator, can sustain it.",
+        usage_examples: "!sustain Plunk Light\n!sustain \"War Boss\" \"Spiritual Weapon\"",
+    },
+    Word{
+        term: "temphp",
+        kind: WordType::Verb,
+        short_help: "Give a character temporary HP",
+        long_help: "Temporary HP is lost before a character's real HP when they take damage. Temporary HP doesn't stack, so a character keeps whichever is higher, the temporary HP they already have or the new amount. Give them 0 to take it all away. Only a character's owner or a GM can give them temporary HP.",
+        usage_examples: "!temphp Plunk 5\n!temphp Plunk 0",
+    },
+    Word{
+        term: "tracker",
+        kind: WordType::Verb,
+        short_help: "Show a tracker that keeps itself up to date",
+        long_help: "Posts a tracker in this channel that's edited in place after every change, instead of the bot posting a new one each time. It shows the turn order during an encounter, and each character's tracker stats and conditions. Stats are shown on the tracker if they were added with a `!` in front of their name, like `!HP:30/30`. Each channel can have one tracker, so using this again moves it to the bottom of the channel. If there's a GM role, enemies only show a rough idea of their health instead of their stats.",
+        usage_examples: "!tracker\n!tracker off",
+    },
+    Word{
+        term: "undo",
+        kind: WordType::Verb,
+        short_help: "Take back the last change to the tracker",
+        long_help: "Undo puts the tracker back the way it was before the most recent change, such as a mistyped `!add`. Give a number to undo several changes at once. Undone changes can be brought back with `!redo`, at least until something else changes the tracker. Use `!history` to see what can be undone. If there's a GM role, only GMs can undo changes, though anyone can look at the history.",
+        usage_examples: "!undo\n!undo 3",
     },
 ];
 
 ///////////////////////////////////////////////////////
 // Nouns 
 ///////////////////////////////////////////////////////
-pub const NOUNS: [Word; 2] = [
+pub const NOUNS: [Word; 10] = [
+    Word{
+        term: "ability",
+        kind: WordType::Noun,
+        short_help: "Ability scores on a character's sheet, like STR 18",
+        long_help: "Setting an ability score gives the character a PF2e sheet if they don't have one yet. The sheet works out their ability modifiers from the scores, so `STR:18` makes their STR stat 4, and anything that uses STR follows along. Abilities can be given as STR, DEX, CON, INT, WIS and CHA, or in full. See `!help proficiency` for more about sheets.",
+        usage_examples: "!set ability Plunk STR:18 DEX:14
+!set ability Plunk Wisdom:12",
+    },
+    Word{
+        term: "condition",
+        kind: WordType::Noun,
+        short_help: "Pathfinder conditions like frightened 2 or prone",
+        long_help: "Conditions are the ones from the Pathfinder rules, such as blinded, clumsy, doomed, drained, dying, enfeebled, flat-footed (or off-guard), frightened, grabbed, paralyzed, prone, restrained, sickened, slowed, stunned, stupefied, unconscious and wounded. Some have a value, which defaults to 1. Giving a character a condition they already have keeps the higher value.\n\nConditions that bring others with them, like dying making a character unconscious, apply those too for as long as they last. Penalties from conditions are added to that character's rolls automatically, and frightened goes down by 1 at the end of each of their turns. Players who drop to 0 HP start dying, and are left wounded once healed or once dying is removed. Only the owner of a character or a GM can change their conditions.",
+        usage_examples: "!add condition Plunk frightened 2\n!add condition Slurk prone\n!remove condition Plunk frightened",
+    },
+    Word{
+        term: "effect",
+        kind: WordType::Noun,
+        short_help: "Something temporary affecting a character, like Bless",
+        long_help: "Effects are named and last for a number of rounds or minutes, until the end of the next turn of whoever created them, while sustained, or until removed. Durations count down on the turns of the effect's creator, so effects end on their own as the encounter goes on. Say who created the effect with `from`, otherwise it's your character if you only have one. Anyone can add an effect, but only a GM or the owner of the character it's on or of its creator can remove it. Adding an effect a character already has replaces it. Effects can give bonuses and penalties like `+1 status to attack`, which are added to rolls of any stat with one of those words in its name (or every check, without a `to`). Only the best bonus and worst penalty of each type apply, except untyped ones which all do.",
+        usage_examples: "!add effect Plunk Bless 1 minute +1 status to attack from Frank\n!add effect Plunk Frightened 2 rounds\n!add effect Slurk Shield until end of next turn\n!add effect Plunk Light sustained\n!remove effect Plunk Bless",
+    },
+    Word{
+        term: "encounter",
+        kind: WordType::Noun,
+        short_help: "A fight, or anything else that happens in turns",
+        long_help: "Encounters keep track of initiative, whose turn it is and what round it is. Moving from turn to turn counts down effects, and during an encounter `!roll` defaults to whichever of your characters is taking their turn.",
+        usage_examples: "!start encounter\n!init Plunk 17\n!next\n!end encounter",
+    },
     Word{
         term: "enemy",
         kind: WordType::Noun,
         short_help: "Enemies are characters whose stats are hidden from players",
-        long_help: "Enemies are typically GM controlled characters and serve as things for a <player> for fight. They behave much like player characters in that they have stats, can roll dice, take damage, etc. Where they differ is that their stats are automatically hidden or obfuscated from those without the GM role.",
+        long_help: "Enemies are typically GM controlled characters and serve as things for a <player> for fight. They behave much like player characters in that they have stats, can roll dice, take damage, etc. Where they differ is that their stats are automatically hidden or obfuscated from those without the GM role, once one has been chosen with `!set gm`. Everyone sees the total of an enemy's rolls and a rough idea of their health, like Hurt or Bloodied, and GMs are sent the exact numbers in a direct message.",
         usage_examples: "!remove enemy Goblin\n!add enemy Slurk",
     },
+    Word{
+        term: "immunity",
+        kind: WordType::Noun,
+        short_help: "Damage a character ignores completely",
+        long_help: "A character with an immunity takes no damage of that type. Immunity to precision means precision damage, like a sneak attack, is ignored while the rest of the attack still hits. Damage types are matched by name, by category (physical or energy), or with `all`. When damage is taken, immunities apply first, then the single highest weakness, then the highest resistance to each type of damage.",
+        usage_examples: "!add immunity Slurk poison\n!remove immunity Slurk poison",
+    },
+    Word{
+        term: "proficiency",
+        kind: WordType::Noun,
+        short_help: "Proficiency ranks on a character's sheet, like expert in Athletics",
+        long_help: "A character can have a PF2e sheet holding their level, ability scores and proficiency ranks: untrained, trained, expert, master or legendary, or just the first letter. Setting a proficiency gives them a sheet if they don't have one yet, taking their level and ability modifiers from their Level, STR, DEX, CON, INT, WIS and CHA stats.\n\nThe sheet makes stats for every save, skill and perception, like `Athletics_Prof:7` and `Athletics:=1d20+STR+Athletics_Prof`, and works them out again whenever their level is set. Lores end in _Lore, weapon categories (Simple, Martial, Advanced, Unarmed) make stats like `Martial_Prof` for strikes to use, traditions (Arcane, Divine, Occult, Primal) make spell attack and DC stats, and Class makes a class DC. Stats from the sheet can only be changed through it, while every other stat works as usual. Imported characters come with a sheet.",
+        usage_examples: "!set proficiency Plunk Athletics:expert Martial:trained\n!set proficiency Plunk Warfare_Lore:t\n!set proficiency Tilly Divine:expert\n!set stat Plunk Level:4",
+    },
+    Word{
+        term: "resistance",
+        kind: WordType::Noun,
+        short_help: "Damage a character takes less of",
+        long_help: "A character with a resistance takes that much less damage of the type, but never less than 0. Damage types are matched by name, by category (physical or energy), or with `all`. When damage is taken, immunities apply first, then the single highest weakness, then the highest resistance to each type of damage.",
+        usage_examples: "!add resistance Slurk fire 5\n!add resistance Slurk physical 3\n!remove resistance Slurk fire",
+    },
     Word{
         term: "stat",
         kind: WordType::Noun,
         short_help: "Information about a character like HP or attacks",
         long_help: "A stat can be almost anything. Use stats to remember your HP, level, focus points, or store complex dice rolls. A stat can either be static or dynamic.\n\nStatic stats are those with a value that only changes when you tell it to change, such as your level or hero points. When creating a static stat, simply give the name and the value seperated by a colon. If dice notation or references are included, they are resolved immediately and only the final result is stored.\n\nDynamic stats are a whole different beast. Their value is stored as a dice roll and can reference other stats. When you ask the bot to roll or otherwise return the value, it will *dynamically* compute it, rolling any dice and resolving any references needed. These are often used for things like attack rolls or saves. Dynamic stats are created like static, only prefix an equal sign before the value, like an Excel formula.\n\nStats can also be ranges with a maximum value, such as HP. To give a stat a maximum value, include a forward slash after the value, followed by the maximum. The maximum is only adjusted when the bot is asked to, so future set commands will only adjust the value unless the maximum is also given.",
-        usage_examples: "!add stat Bob HP:35/35\n!set stat Bob HP:20\n!add stat Frank Reflex:=1d20+DEX\n!set stat Bob Level:5\n!roll stat Frank Reflex",
+        usage_examples: "!add stat Bob HP:35/35\n!set stat Bob HP:20\n!add stat Frank Gold:2d6*10\n!set stat Bob HP:HP-5\n!add stat Frank Reflex:=1d20+DEX\n!set stat Bob Level:5\n!roll stat Frank Reflex",
+    },
+    Word{
+        term: "weakness",
+        kind: WordType::Noun,
+        short_help: "Damage a character takes extra from",
+        long_help: "A character with a weakness takes that much extra damage when hit by the type. Only the highest weakness applies to each hit, even if it has several types of damage. Damage types are matched by name, by category (physical or energy), or with `all`. When damage is taken, immunities apply first, then the single highest weakness, then the highest resistance to each type of damage.",
+        usage_examples: "!add weakness Slurk cold 5\n!add weakness Slurk splash 3\n!remove weakness Slurk cold",
     }
 ];
 
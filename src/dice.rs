@@ -1,17 +1,349 @@
-use std::sync::Arc;
-
-use roll_rs::roll_inline;
-use futures::lock::Mutex;
-use crate::discord::{DiscordReferences};
-use anyhow::{Result, anyhow};
-use crate::omni::Omnidata;
-
-pub async fn handle_command(
-    discord_refs: &DiscordReferences<'_>, 
-    omnidata_cache: Arc<Mutex<Option<Omnidata>>>,
-    arguments: &str,
-) -> Result<()> {
-    let roll = roll_inline(arguments, true).unwrap();
-    discord_refs.send_message_reply(&format!("{}", &roll.string_result)).await?;
-    Ok(())
-}
\ No newline at end of file
+//! Rolling dice. Expressions are parsed into an `Expression`, then rolled with every die kept track of,
+//! so callers can look at the natural d20 of a check as well as the total.
+//! The numbers on the dice come from a `Source`, which is random unless another one is swapped in with `with_source`.
+
+mod degree;
+mod expression;
+mod odds;
+mod parser;
+
+pub use degree::Degree;
+pub use odds::Odds;
+
+use std::{cell::RefCell, fmt};
+use anyhow::Result;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+/// Where the numbers on dice come from.
+pub trait Source {
+    /// A number from 1 to `sides`.
+    fn roll_die(&mut self, sides: i64) -> i64;
+}
+
+/// Dice from the thread's random number generator, which is seeded by the operating system.
+pub struct Random;
+
+impl Source for Random {
+    fn roll_die(&mut self, sides: i64) -> i64 {
+        rand::thread_rng().gen_range(1..=sides)
+    }
+}
+
+/// Dice from ChaCha20, so the same seed and stream always roll the same numbers. Each stream is a separate
+/// sequence of rolls from the same seed.
+pub struct Seeded(ChaCha20Rng);
+
+impl Seeded {
+    pub fn new(seed: [u8; 32], stream: u64) -> Self {
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        rng.set_stream(stream);
+        Seeded(rng)
+    }
+}
+
+impl Source for Seeded {
+    fn roll_die(&mut self, sides: i64) -> i64 {
+        self.0.gen_range(1..=sides)
+    }
+}
+
+/// Dice that roll the given numbers in turn, for tests that need to know exactly what comes up.
+#[cfg(test)]
+pub struct Loaded(pub Vec<i64>);
+
+#[cfg(test)]
+impl Source for Loaded {
+    fn roll_die(&mut self, sides: i64) -> i64 {
+        assert!(!self.0.is_empty(), "Loaded dice ran out of numbers");
+        let value = self.0.remove(0);
+        assert!((1..=sides).contains(&value), "Loaded dice can't roll {} on a d{}", value, sides);
+        value
+    }
+}
+
+/// Roll with loaded dice, like `loaded(&[20, 3], || ...)`.
+#[cfg(test)]
+pub fn loaded<T>(values: &[i64], rolling: impl FnOnce() -> T) -> T {
+    with_source(Box::new(Loaded(values.to_vec())), rolling).0
+}
+
+/// A source swapped in by `with_source`, and everything rolled with it so far.
+struct Swapped {
+    source: Box<dyn Source>,
+    rolled: Vec<DiceRoll>,
+}
+
+thread_local! {
+    static SOURCE: RefCell<Option<Swapped>> = const { RefCell::new(None) };
+}
+
+/// Roll every die `rolling` needs with the given source instead of a random one. Returns whatever `rolling` did,
+/// and each expression it rolled, in order.
+pub fn with_source<T>(source: Box<dyn Source>, rolling: impl FnOnce() -> T) -> (T, Vec<DiceRoll>) {
+    let previous = SOURCE.with(|current| current.replace(Some(Swapped { source, rolled: Vec::new() })));
+    let result = rolling();
+    let rolled = SOURCE.with(|current| current.replace(previous)).map(|swapped| swapped.rolled).unwrap_or_default();
+    (result, rolled)
+}
+
+/// A single die that was rolled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Die {
+    pub sides: i64,
+    pub value: i64,
+    /// Whether the die counts towards the total. Dice dropped by keep highest or lowest, rerolled, or on the
+    /// unused side of a fortune roll aren't.
+    pub kept: bool,
+    /// Whether the die rolled its highest number and added another die.
+    pub exploded: bool,
+    /// Whether the die was rolled again, with the new roll following it.
+    pub rerolled: bool,
+}
+
+/// Shows the value, with a `!` if it exploded and in parentheses if it doesn't count.
+impl fmt::Display for Die {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let exploded = if self.exploded { "!" } else { "" };
+        match self.kept {
+            true => write!(f, "{}{}", self.value, exploded),
+            false => write!(f, "({}{})", self.value, exploded),
+        }
+    }
+}
+
+/// The outcome of rolling a dice expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiceRoll {
+    /// The expression as it was understood, like `1d20+3`. Rolling it again with the same source rolls the same dice.
+    pub expression: String,
+    /// The expression, what each die rolled, and the total. For example `1d20+3 = [14] + 3 = 17`
+    pub breakdown: String,
+    /// The total, rounded down as Pathfinder always does.
+    pub total: i64,
+    /// Every die rolled, in the order they appear in the breakdown.
+    pub dice: Vec<Die>,
+}
+
+impl DiceRoll {
+    /// What the first counted die with this many sides rolled, like the d20 of a check.
+    pub fn natural(&self, sides: i64) -> Option<i64> {
+        self.dice.iter().find(|die| die.kept && die.sides == sides).map(|die| die.value)
+    }
+
+    /// How well the roll did as a check against the DC, counting a natural 20 or 1.
+    pub fn degree(&self, dc: i64) -> Degree {
+        Degree::of(self.total, dc, self.natural(20))
+    }
+
+    /// The degree of success against the DC, like `**Success** against DC 18`, noting a natural 20 or 1 if it changed it.
+    pub fn describe_degree(&self, dc: i64) -> String {
+        let degree = self.degree(dc);
+        let mut description = format!("**{}** against DC {}", degree, dc);
+        if degree != Degree::of(self.total, dc, None) {
+            description.push_str(&format!(" (natural {})", self.natural(20).unwrap_or_default()));
+        }
+        description
+    }
+}
+
+/// Roll a dice expression like `2d6+4`. Expressions without dice are fine too, and just do the math.
+pub fn roll(expression: &str) -> Result<DiceRoll> {
+    let parsed = parser::parse(expression)?;
+    let mut dice = Vec::new();
+    SOURCE.with(|current| {
+        let mut current = current.borrow_mut();
+        let outcome = match current.as_mut() {
+            Some(swapped) => parsed.evaluate(swapped.source.as_mut(), &mut dice)?,
+            None => parsed.evaluate(&mut Random, &mut dice)?,
+        };
+        let total = outcome.value.floor() as i64;
+        let roll = DiceRoll {
+            expression: parsed.to_string(),
+            breakdown: format!("{} = {} = {}", parsed, outcome.shown, total),
+            total,
+            dice,
+        };
+        if let Some(swapped) = current.as_mut() {
+            swapped.rolled.push(roll.clone());
+        }
+        Ok(roll)
+    })
+}
+
+/// Work out the chance of every total a dice expression can roll, without rolling it.
+pub fn odds(expression: &str) -> Result<Odds> {
+    Odds::of(&parser::parse(expression)?)
+}
+
+/// Whether the word is some dice on its own, like `2d6` or `4d6kh3`.
+pub fn is_dice(word: &str) -> bool {
+    parser::is_dice(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roll_seeded(expression: &str, seed: u64) -> (i64, Vec<Die>) {
+        let rolled = with_source(Box::new(Seeded::new([0; 32], seed)), || roll(expression).unwrap()).0;
+        (rolled.total, rolled.dice)
+    }
+
+    #[test]
+    fn parsing() {
+        let shown = |text: &str| parser::parse(text).unwrap().to_string();
+        assert_eq!(shown("1d20 + 5"), "1d20+5");
+        assert_eq!(shown("D20"), "1d20");
+        assert_eq!(shown("d%"), "1d100");
+        assert_eq!(shown("4d6k3"), "4d6kh3");
+        assert_eq!(shown("2d20kl"), "2d20kl1");
+        assert_eq!(shown("3d6r1x"), "3d6r1x");
+        assert_eq!(shown("2d6!"), "2d6x");
+        assert_eq!(shown("Fortune(1d20+5)"), "fortune(1d20+5)");
+        assert_eq!(shown("-(2+3)*4"), "-(2+3)*4");
+        assert!(matches!(parser::parse("1+2*3").unwrap(), expression::Expression::Add(_, _)));
+    }
+
+    #[test]
+    fn bad_expressions_are_errors() {
+        for text in &["", "2d", "d0", "0d6", "501d6", "1d1001", "1d6r6", "1d1x", "(1d6", "1d6)", "1d6+", "fortune 1d20",
+                      "2d6q", "99999999999999999999d6", "1/0"] {
+            assert!(roll(text).is_err(), "{} should be an error", text);
+        }
+    }
+
+    #[test]
+    fn math() {
+        assert_eq!(roll("1+2*3").unwrap().total, 7);
+        assert_eq!(roll("(1+2)*3").unwrap().total, 9);
+        assert_eq!(roll("7/2").unwrap().total, 3);
+        assert_eq!(roll("-7/2").unwrap().total, -4);
+        assert_eq!(roll("2--3").unwrap().total, 5);
+        assert_eq!(roll("3").unwrap().breakdown, "3 = 3 = 3");
+    }
+
+    #[test]
+    fn dice_stay_in_range() {
+        for seed in 0..50 {
+            let (total, dice) = roll_seeded("3d6+1", seed);
+            assert!((4..=19).contains(&total));
+            assert_eq!(dice.len(), 3);
+            assert!(dice.iter().all(|die| die.sides == 6 && (1..=6).contains(&die.value) && die.kept));
+        }
+    }
+
+    #[test]
+    fn keeping() {
+        for seed in 0..50 {
+            let (total, dice) = roll_seeded("4d6kh3", seed);
+            let mut values: Vec<i64> = dice.iter().map(|die| die.value).collect();
+            values.sort_unstable();
+            assert_eq!(total, values[1..].iter().sum::<i64>());
+            assert_eq!(dice.iter().filter(|die| die.kept).count(), 3);
+
+            let (total, dice) = roll_seeded("2d20kl1", seed);
+            assert_eq!(total, dice.iter().map(|die| die.value).min().unwrap());
+        }
+    }
+
+    #[test]
+    fn rerolling() {
+        for seed in 0..50 {
+            let (total, dice) = roll_seeded("2d6r2", seed);
+            let counted: Vec<&Die> = dice.iter().filter(|die| die.kept).collect();
+            assert_eq!(counted.len(), 2);
+            assert_eq!(total, counted.iter().map(|die| die.value).sum::<i64>());
+            assert!(dice.iter().filter(|die| die.rerolled).all(|die| die.value <= 2 && !die.kept));
+        }
+    }
+
+    #[test]
+    fn exploding() {
+        let mut exploded = false;
+        for seed in 0..50 {
+            let (total, dice) = roll_seeded("1d4x", seed);
+            assert_eq!(dice.len(), dice.iter().filter(|die| die.exploded).count() + 1);
+            assert!(!dice.last().unwrap().exploded);
+            assert_eq!(total, dice.iter().map(|die| die.value).sum::<i64>());
+            exploded |= dice.len() > 1;
+        }
+        assert!(exploded);
+    }
+
+    #[test]
+    fn fortune() {
+        for seed in 0..50 {
+            let (total, dice) = roll_seeded("fortune(1d20)", seed);
+            assert_eq!(dice.len(), 2);
+            assert_eq!(total, dice.iter().map(|die| die.value).max().unwrap());
+            assert_eq!(dice.iter().filter(|die| die.kept).count(), 1);
+
+            let (total, dice) = roll_seeded("misfortune(1d20)+3", seed);
+            assert_eq!(total, dice.iter().map(|die| die.value).min().unwrap() + 3);
+        }
+        let rolled = roll("fortune(1d20+5)").unwrap();
+        assert!(rolled.breakdown.starts_with("fortune(1d20+5) = fortune(["));
+        assert_eq!(rolled.natural(20), Some(rolled.total - 5));
+    }
+
+    #[test]
+    fn dice_words() {
+        assert!(is_dice("2d6"));
+        assert!(is_dice("d20"));
+        assert!(is_dice("4d6kh3"));
+        assert!(is_dice("2d6x"));
+        assert!(!is_dice("2d6q"));
+        assert!(!is_dice("2"));
+        assert!(!is_dice("dex"));
+        assert!(!is_dice("2d6+1"));
+    }
+
+    #[test]
+    fn degrees() {
+        let roll = |total: i64, natural: i64| DiceRoll {
+            expression: String::from("1d20+5"),
+            breakdown: String::new(),
+            total,
+            dice: vec![Die { sides: 20, value: natural, kept: true, exploded: false, rerolled: false }],
+        };
+        assert_eq!(roll(25, 18).describe_degree(18), "**Success** against DC 18");
+        assert_eq!(roll(25, 20).describe_degree(18), "**Critical Success** against DC 18 (natural 20)");
+        assert_eq!(roll(30, 20).describe_degree(18), "**Critical Success** against DC 18");
+        assert_eq!(roll(19, 1).describe_degree(18), "**Failure** against DC 18 (natural 1)");
+        assert_eq!(roll(12, 1).degree(18), Degree::CriticalFailure);
+    }
+
+    #[test]
+    fn seeded_dice_repeat() {
+        let rolls = |stream: u64| with_source(Box::new(Seeded::new([7; 32], stream)), || {
+            roll("4d6kh3").unwrap();
+            roll("fortune(1d20+5)").unwrap().breakdown
+        });
+        let (breakdown, rolled) = rolls(1);
+        assert_eq!(rolls(1), (breakdown.clone(), rolled.clone()));
+        assert_eq!(rolled.len(), 2);
+        assert_eq!(rolled[0].expression, "4d6kh3");
+        assert_eq!(rolled[1].breakdown, breakdown);
+        let other_streams: Vec<String> = (2..10).map(|stream| rolls(stream).0).collect();
+        assert!(other_streams.iter().any(|other| *other != breakdown));
+    }
+
+    #[test]
+    fn loaded_dice() {
+        let (rolled, logged) = with_source(Box::new(Loaded(vec![3, 5, 20])), || (roll("2d6+1").unwrap(), roll("1d20").unwrap()));
+        assert_eq!(rolled.0.breakdown, "2d6+1 = [3, 5] + 1 = 9");
+        assert_eq!(rolled.1.total, 20);
+        assert_eq!(logged.len(), 2);
+        // Outside of with_source, dice are random again and nothing is kept.
+        assert!(roll("1d4").unwrap().total <= 4);
+        assert_eq!(loaded(&[1], || roll("1d20").unwrap().natural(20)), Some(1));
+    }
+
+    #[test]
+    fn shown_dice() {
+        let die = Die { sides: 6, value: 6, kept: true, exploded: true, rerolled: false };
+        assert_eq!(die.to_string(), "6!");
+        assert_eq!(Die { kept: false, exploded: false, ..die }.to_string(), "(6)");
+    }
+}
@@ -0,0 +1,93 @@
+//! Pathfinder's four degrees of success, for checks rolled against a DC.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Degree {
+    CriticalFailure,
+    Failure,
+    Success,
+    CriticalSuccess,
+}
+
+impl Degree {
+    /// Every degree, best first, in the order spells and abilities list their outcomes.
+    pub const ALL: [Degree; 4] = [Degree::CriticalSuccess, Degree::Success, Degree::Failure, Degree::CriticalFailure];
+
+    /// Beating the DC by 10 or more is a critical success, and missing it by 10 or more a critical failure.
+    /// A natural 20 then makes the result one degree better, and a natural 1 one degree worse.
+    pub fn of(total: i64, dc: i64, natural: Option<i64>) -> Self {
+        let degree = if total >= dc + 10 {
+            Degree::CriticalSuccess
+        } else if total >= dc {
+            Degree::Success
+        } else if total <= dc - 10 {
+            Degree::CriticalFailure
+        } else {
+            Degree::Failure
+        };
+        match natural {
+            Some(20) => degree.better(),
+            Some(1) => degree.worse(),
+            _ => degree,
+        }
+    }
+
+    fn better(self) -> Self {
+        match self {
+            Degree::CriticalFailure => Degree::Failure,
+            Degree::Failure => Degree::Success,
+            Degree::Success | Degree::CriticalSuccess => Degree::CriticalSuccess,
+        }
+    }
+
+    fn worse(self) -> Self {
+        match self {
+            Degree::CriticalSuccess => Degree::Success,
+            Degree::Success => Degree::Failure,
+            Degree::Failure | Degree::CriticalFailure => Degree::CriticalFailure,
+        }
+    }
+
+    /// How the degree is written in the rules, like `Critical Success`.
+    pub fn heading(self) -> &'static str {
+        match self {
+            Degree::CriticalSuccess => "Critical Success",
+            Degree::Success => "Success",
+            Degree::Failure => "Failure",
+            Degree::CriticalFailure => "Critical Failure",
+        }
+    }
+}
+
+impl fmt::Display for Degree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.heading())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degrees() {
+        assert_eq!(Degree::of(28, 18, None), Degree::CriticalSuccess);
+        assert_eq!(Degree::of(27, 18, None), Degree::Success);
+        assert_eq!(Degree::of(18, 18, None), Degree::Success);
+        assert_eq!(Degree::of(17, 18, None), Degree::Failure);
+        assert_eq!(Degree::of(9, 18, None), Degree::Failure);
+        assert_eq!(Degree::of(8, 18, None), Degree::CriticalFailure);
+    }
+
+    #[test]
+    fn naturals() {
+        assert_eq!(Degree::of(25, 18, Some(20)), Degree::CriticalSuccess);
+        assert_eq!(Degree::of(30, 18, Some(20)), Degree::CriticalSuccess);
+        assert_eq!(Degree::of(5, 18, Some(20)), Degree::Failure);
+        assert_eq!(Degree::of(20, 18, Some(1)), Degree::Failure);
+        assert_eq!(Degree::of(28, 18, Some(1)), Degree::Success);
+        assert_eq!(Degree::of(2, 18, Some(1)), Degree::CriticalFailure);
+        assert_eq!(Degree::of(20, 18, Some(7)), Degree::Success);
+    }
+}
@@ -0,0 +1,208 @@
+//! Parsed dice expressions, and rolling them.
+
+use std::fmt;
+use anyhow::{Result, anyhow};
+use super::{Die, Source};
+
+/// The most dice that can explode out of a single roll, so exploding dice always come to an end.
+pub(super) const MAX_EXPLOSIONS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(i64),
+    Dice(Dice),
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+    Multiply(Box<Expression>, Box<Expression>),
+    Divide(Box<Expression>, Box<Expression>),
+    Negate(Box<Expression>),
+    /// Parentheses, kept so the expression can be shown the way it was written.
+    Group(Box<Expression>),
+    /// Roll the whole expression twice and use the higher or lower total.
+    Fortune(Fortune, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fortune {
+    /// Roll twice and take the higher result.
+    Fortune,
+    /// Roll twice and take the lower result.
+    Misfortune,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keep {
+    Highest(i64),
+    Lowest(i64),
+}
+
+/// Some dice, like `4d6kh3`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dice {
+    pub count: i64,
+    pub sides: i64,
+    pub keep: Option<Keep>,
+    /// Each die that rolls this or lower is rerolled once, and the new roll is used instead.
+    pub reroll: Option<i64>,
+    /// Each die that rolls its highest number adds another die, which can explode too.
+    pub explode: bool,
+}
+
+/// What rolling part of an expression came to, and how it's shown in a roll's breakdown.
+pub(super) struct Outcome {
+    pub value: f64,
+    pub shown: String,
+}
+
+impl Expression {
+    /// Roll every die in the expression and work out its value. Each die rolled is added to `dice`.
+    pub(super) fn evaluate(&self, source: &mut dyn Source, dice: &mut Vec<Die>) -> Result<Outcome> {
+        let binary = |left: &Expression, right: &Expression, symbol: &str, dice: &mut Vec<Die>, source: &mut dyn Source| -> Result<(f64, f64, String)> {
+            let left = left.evaluate(source, dice)?;
+            let right = right.evaluate(source, dice)?;
+            Ok((left.value, right.value, format!("{} {} {}", left.shown, symbol, right.shown)))
+        };
+        let (value, shown) = match self {
+            Expression::Number(number) => (*number as f64, number.to_string()),
+            Expression::Dice(rolled) => rolled.roll(source, dice)?,
+            Expression::Add(left, right) => {
+                let (left, right, shown) = binary(left, right, "+", dice, source)?;
+                (left + right, shown)
+            },
+            Expression::Subtract(left, right) => {
+                let (left, right, shown) = binary(left, right, "-", dice, source)?;
+                (left - right, shown)
+            },
+            Expression::Multiply(left, right) => {
+                let (left, right, shown) = binary(left, right, "*", dice, source)?;
+                (left * right, shown)
+            },
+            Expression::Divide(left, right) => {
+                let (left, right, shown) = binary(left, right, "/", dice, source)?;
+                if right == 0.0 {
+                    return Err(anyhow!("Can't divide by zero."));
+                }
+                (left / right, shown)
+            },
+            Expression::Negate(inner) => {
+                let inner = inner.evaluate(source, dice)?;
+                (-inner.value, format!("-{}", inner.shown))
+            },
+            Expression::Group(inner) => {
+                let inner = inner.evaluate(source, dice)?;
+                (inner.value, format!("({})", inner.shown))
+            },
+            Expression::Fortune(fortune, inner) => {
+                let mut first_dice = Vec::new();
+                let first = inner.evaluate(source, &mut first_dice)?;
+                let mut second_dice = Vec::new();
+                let second = inner.evaluate(source, &mut second_dice)?;
+                let first_is_used = match fortune {
+                    Fortune::Fortune => first.value >= second.value,
+                    Fortune::Misfortune => first.value <= second.value,
+                };
+                let (used, unused, mut used_dice, mut unused_dice) = match first_is_used {
+                    true => (first, second, first_dice, second_dice),
+                    false => (second, first, second_dice, first_dice),
+                };
+                for die in unused_dice.iter_mut() {
+                    die.kept = false;
+                }
+                dice.append(&mut used_dice);
+                dice.append(&mut unused_dice);
+                (used.value, format!("{}({} = {}, not {} = {})", fortune, used.shown, used.value, unused.shown, unused.value))
+            },
+        };
+        Ok(Outcome { value, shown })
+    }
+}
+
+impl Dice {
+    /// Roll the dice, returning their total and how they're shown, like `[6, 4, (1)]` where the 1 was dropped.
+    fn roll(&self, source: &mut dyn Source, dice: &mut Vec<Die>) -> Result<(f64, String)> {
+        let mut rolled = Vec::new();
+        let mut explosions = 0;
+        let mut remaining = self.count;
+        while remaining > 0 {
+            remaining -= 1;
+            let mut die = Die { sides: self.sides, value: source.roll_die(self.sides), kept: true, exploded: false, rerolled: false };
+            if self.reroll.is_some_and(|reroll| die.value <= reroll) {
+                rolled.push(Die { kept: false, rerolled: true, ..die.clone() });
+                die.value = source.roll_die(self.sides);
+            }
+            if self.explode && die.value == self.sides {
+                if explosions == MAX_EXPLOSIONS {
+                    return Err(anyhow!("The dice kept exploding more than {} times, so the roll was stopped.", MAX_EXPLOSIONS));
+                }
+                explosions += 1;
+                remaining += 1;
+                die.exploded = true;
+            }
+            rolled.push(die);
+        }
+
+        if let Some(keep) = self.keep {
+            let mut order: Vec<usize> = (0..rolled.len()).filter(|index| rolled[*index].kept).collect();
+            order.sort_by_key(|index| rolled[*index].value);
+            let count = match keep {
+                Keep::Highest(count) => {
+                    order.reverse();
+                    count
+                },
+                Keep::Lowest(count) => count,
+            };
+            for index in order.into_iter().skip(count as usize) {
+                rolled[index].kept = false;
+            }
+        }
+
+        let total: i64 = rolled.iter().filter(|die| die.kept).map(|die| die.value).sum();
+        let shown: Vec<String> = rolled.iter().map(Die::to_string).collect();
+        dice.extend(rolled);
+        Ok((total as f64, format!("[{}]", shown.join(", "))))
+    }
+}
+
+impl fmt::Display for Fortune {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fortune::Fortune => write!(f, "fortune"),
+            Fortune::Misfortune => write!(f, "misfortune"),
+        }
+    }
+}
+
+/// Shows the expression in its simplest form, like `fortune(1d20+5)`.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Number(number) => write!(f, "{}", number),
+            Expression::Dice(dice) => write!(f, "{}", dice),
+            Expression::Add(left, right) => write!(f, "{}+{}", left, right),
+            Expression::Subtract(left, right) => write!(f, "{}-{}", left, right),
+            Expression::Multiply(left, right) => write!(f, "{}*{}", left, right),
+            Expression::Divide(left, right) => write!(f, "{}/{}", left, right),
+            Expression::Negate(inner) => write!(f, "-{}", inner),
+            Expression::Group(inner) => write!(f, "({})", inner),
+            Expression::Fortune(fortune, inner) => write!(f, "{}({})", fortune, inner),
+        }
+    }
+}
+
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.keep {
+            Some(Keep::Highest(count)) => write!(f, "kh{}", count)?,
+            Some(Keep::Lowest(count)) => write!(f, "kl{}", count)?,
+            None => {},
+        }
+        if let Some(reroll) = self.reroll {
+            write!(f, "r{}", reroll)?;
+        }
+        if self.explode {
+            write!(f, "x")?;
+        }
+        Ok(())
+    }
+}
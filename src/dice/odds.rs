@@ -0,0 +1,569 @@
+//! Working out the exact chance of every total a dice expression can roll, without rolling it. Each part of the expression
+//! becomes the chance of each value it can come to, and those are combined the same way rolling combines the values.
+
+use std::{cmp::Ordering, collections::{BTreeMap, btree_map::Entry}};
+use anyhow::{Result, anyhow};
+use super::Degree;
+use super::expression::{Dice, Expression, Fortune, Keep, MAX_EXPLOSIONS};
+
+/// Working out more possibilities than this would keep the bot busy for far too long.
+const MAX_WORK: usize = 20_000_000;
+/// Exploding dice could in theory keep going forever, so chains of explosions less likely than this are left out.
+const NEGLIGIBLE: f64 = 1e-12;
+/// Rolls that can come to more totals than this have them grouped into ranges, so the table stays readable.
+const MAX_ROWS: i64 = 20;
+
+/// A value part of an expression can come to. Division means it isn't always a whole number until the end.
+#[derive(Debug, Clone, Copy)]
+struct Value(f64);
+
+impl Value {
+    fn new(value: f64) -> Self {
+        // Adding zero turns -0 into 0, so they count as the same value.
+        Value(value + 0.0)
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// The chance of each value part of an expression can come to, along with the natural d20 rolled with it, if any.
+type Chances = BTreeMap<(Value, Option<i64>), f64>;
+
+/// The chance of each total dice can come to, along with the natural d20 rolled with it, if any.
+type Totals = Vec<((i64, Option<i64>), f64)>;
+
+/// The chance of each whole number from `lowest` up, for the totals of dice.
+#[derive(Debug, Clone)]
+struct Spread {
+    lowest: i64,
+    chances: Vec<f64>,
+}
+
+impl Spread {
+    fn certain(value: i64) -> Self {
+        Spread { lowest: value, chances: vec![1.0] }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (i64, f64)> + '_ {
+        self.chances.iter().enumerate().filter(|(_, chance)| **chance > 0.0).map(move |(offset, chance)| (self.lowest + offset as i64, *chance))
+    }
+
+    /// The chance of each total of rolling both.
+    fn add(&self, other: &Spread, calculation: &mut Calculation) -> Result<Spread> {
+        calculation.spend(self.chances.len() * other.chances.len())?;
+        let mut chances = vec![0.0; self.chances.len() + other.chances.len() - 1];
+        for (offset, chance) in self.chances.iter().enumerate().filter(|(_, chance)| **chance > 0.0) {
+            for (other_offset, other_chance) in other.chances.iter().enumerate() {
+                chances[offset + other_offset] += chance * other_chance;
+            }
+        }
+        Ok(Spread { lowest: self.lowest + other.lowest, chances })
+    }
+
+    /// Add `chance` of rolling `value`, making room for it if needed.
+    fn include(&mut self, value: i64, chance: f64) {
+        if value < self.lowest {
+            let mut chances = vec![0.0; (self.lowest - value) as usize];
+            chances.append(&mut self.chances);
+            self.chances = chances;
+            self.lowest = value;
+        }
+        let offset = (value - self.lowest) as usize;
+        if offset >= self.chances.len() {
+            self.chances.resize(offset + 1, 0.0);
+        }
+        self.chances[offset] += chance;
+    }
+}
+
+/// Keeps track of how much work has gone into the odds so far, and what couldn't be worked out.
+struct Calculation {
+    work: usize,
+    /// Which d20 counts as the natural one when more than one is kept depends on the order they were rolled in, so it's unknown.
+    naturals_known: bool,
+}
+
+impl Calculation {
+    fn spend(&mut self, work: usize) -> Result<()> {
+        self.work = self.work.saturating_add(work);
+        match self.work > MAX_WORK {
+            true => Err(anyhow!("There are too many ways those dice could land to work out the odds exactly. Try fewer or smaller dice.")),
+            false => Ok(()),
+        }
+    }
+
+    fn chances(&mut self, expression: &Expression) -> Result<Chances> {
+        let chances = match expression {
+            Expression::Number(number) => Chances::from([((Value::new(*number as f64), None), 1.0)]),
+            Expression::Dice(dice) => self.dice(dice)?,
+            Expression::Add(left, right) => self.combine(left, right, |left, right| Ok(left + right))?,
+            Expression::Subtract(left, right) => self.combine(left, right, |left, right| Ok(left - right))?,
+            Expression::Multiply(left, right) => self.combine(left, right, |left, right| Ok(left * right))?,
+            Expression::Divide(left, right) => self.combine(left, right, |left, right| match right == 0.0 {
+                true => Err(anyhow!("Can't divide by zero.")),
+                false => Ok(left / right),
+            })?,
+            Expression::Negate(inner) => self.chances(inner)?.into_iter().map(|((value, natural), chance)| ((Value::new(-value.0), natural), chance)).collect(),
+            Expression::Group(inner) => self.chances(inner)?,
+            Expression::Fortune(fortune, inner) => {
+                let chances = self.chances(inner)?;
+                self.fortune(*fortune, &chances)?
+            },
+        };
+        Ok(chances)
+    }
+
+    /// Every value of the left side with every value of the right. The left side's dice are rolled first, so its natural d20 counts if it has one.
+    fn combine(&mut self, left: &Expression, right: &Expression, operation: impl Fn(f64, f64) -> Result<f64>) -> Result<Chances> {
+        let (left, right) = (self.chances(left)?, self.chances(right)?);
+        self.spend(left.len() * right.len())?;
+        let mut combined = Chances::new();
+        for ((left_value, left_natural), left_chance) in left.iter() {
+            for ((right_value, right_natural), right_chance) in right.iter() {
+                let value = Value::new(operation(left_value.0, right_value.0)?);
+                *combined.entry((value, left_natural.or(*right_natural))).or_default() += left_chance * right_chance;
+            }
+        }
+        Ok(combined)
+    }
+
+    /// Rolling twice and using the higher or lower value. On a tie the first roll is used, along with its natural d20.
+    fn fortune(&mut self, fortune: Fortune, chances: &Chances) -> Result<Chances> {
+        self.spend(chances.len() * 2)?;
+        let mut values: BTreeMap<Value, f64> = BTreeMap::new();
+        for ((value, _), chance) in chances.iter() {
+            *values.entry(*value).or_default() += chance;
+        }
+        // The chance of rolling below each value, and then above it.
+        let mut below = BTreeMap::new();
+        let mut so_far = 0.0;
+        for (value, chance) in values.iter() {
+            below.insert(*value, so_far);
+            so_far += chance;
+        }
+
+        let mut used = Chances::new();
+        for ((value, natural), chance) in chances.iter() {
+            let (equal, below) = (values[value], below[value]);
+            let above = (so_far - below - equal).max(0.0);
+            // Either this is the first roll and the second doesn't beat it, or this is the second and the first was worse.
+            let weight = match fortune {
+                Fortune::Fortune => below + equal + below,
+                Fortune::Misfortune => above + equal + above,
+            };
+            used.insert((*value, *natural), chance * weight);
+        }
+        Ok(used)
+    }
+
+    fn dice(&mut self, dice: &Dice) -> Result<Chances> {
+        let sides = dice.sides;
+        self.spend(sides as usize)?;
+        // A single die, rolled again if it comes up low enough to be rerolled.
+        let rerolled = dice.reroll.unwrap_or(0).clamp(0, sides);
+        let die = Spread {
+            lowest: 1,
+            chances: (1..=sides).map(|value| {
+                let first = if value <= rerolled { 0.0 } else { 1.0 / sides as f64 };
+                first + rerolled as f64 / (sides * sides) as f64
+            }).collect(),
+        };
+        let totals = match dice.keep {
+            None => self.unkept_dice(dice, &die)?,
+            Some(keep) => self.kept_dice(dice, &die, keep)?,
+        };
+        let mut chances = Chances::new();
+        for ((total, natural), chance) in totals {
+            *chances.entry((Value::new(total as f64), natural)).or_default() += chance;
+        }
+        Ok(chances)
+    }
+
+    /// Dice where every one counts. Each die, along with any it explodes into, is rolled on its own and added to the rest.
+    /// For a d20, the first die is the natural one.
+    fn unkept_dice(&mut self, dice: &Dice, die: &Spread) -> Result<Totals> {
+        let chain = match dice.explode {
+            false => die.clone(),
+            true => {
+                let mut chain = Spread { lowest: 1, chances: Vec::new() };
+                let highest = die.chances[die.chances.len() - 1];
+                let mut exploding = 1.0;
+                let mut explosions = 0;
+                while exploding >= NEGLIGIBLE && explosions <= MAX_EXPLOSIONS {
+                    for (value, chance) in die.iter().filter(|(value, _)| *value < dice.sides) {
+                        chain.include(explosions as i64 * dice.sides + value, exploding * chance);
+                    }
+                    exploding *= highest;
+                    explosions += 1;
+                }
+                chain
+            },
+        };
+        let mut rest = Spread::certain(0);
+        for _ in 1..dice.count {
+            rest = rest.add(&chain, self)?;
+        }
+        self.spend(chain.chances.len() * rest.chances.len())?;
+
+        // The first die's natural is whatever it rolled, or its highest number if it exploded.
+        let mut totals = Vec::new();
+        for (first, first_chance) in chain.iter() {
+            let natural = match dice.sides {
+                20 => Some(first.min(20)),
+                _ => None,
+            };
+            for (others, others_chance) in rest.iter() {
+                totals.push(((first + others, natural), first_chance * others_chance));
+            }
+        }
+        Ok(totals)
+    }
+
+    /// Dice where only some of the highest or lowest count. Exploded dice always roll the highest number, so only how many
+    /// of them there are matters, and the rest of the dice are the ones that didn't explode.
+    fn kept_dice(&mut self, dice: &Dice, die: &Spread, keep: Keep) -> Result<Totals> {
+        let count = dice.count as usize;
+        let (unexploded, explosions) = match dice.explode {
+            false => (die.clone(), Spread::certain(0)),
+            true => {
+                let highest = die.chances[die.chances.len() - 1];
+                let mut unexploded = die.clone();
+                unexploded.chances.pop();
+                unexploded.chances.iter_mut().for_each(|chance| *chance /= 1.0 - highest);
+                // How many times a single die explodes, and then how many times they all do.
+                let mut per_die = Spread { lowest: 0, chances: Vec::new() };
+                let mut exploding = 1.0;
+                while exploding >= NEGLIGIBLE && per_die.chances.len() <= MAX_EXPLOSIONS {
+                    per_die.chances.push(exploding * (1.0 - highest));
+                    exploding *= highest;
+                }
+                let mut explosions = Spread::certain(0);
+                for _ in 0..count {
+                    explosions = explosions.add(&per_die, self)?;
+                }
+                (unexploded, explosions)
+            },
+        };
+
+        let mut kept_by_count: BTreeMap<usize, Spread> = BTreeMap::new();
+        let mut totals = Spread { lowest: 0, chances: Vec::new() };
+        for (exploded, exploded_chance) in explosions.iter() {
+            let exploded = exploded as usize;
+            let (from_exploded, from_unexploded, highest) = match keep {
+                Keep::Highest(keep) => {
+                    let from_exploded = exploded.min(keep as usize);
+                    (from_exploded, (keep as usize - from_exploded).min(count), true)
+                },
+                Keep::Lowest(keep) => {
+                    let from_unexploded = (keep as usize).min(count);
+                    (exploded.min(keep as usize - from_unexploded), from_unexploded, false)
+                },
+            };
+            let kept = match kept_by_count.entry(from_unexploded) {
+                Entry::Occupied(kept) => kept.into_mut(),
+                Entry::Vacant(entry) => entry.insert(self.keep(&unexploded, count, from_unexploded, highest)?),
+            };
+            for (total, chance) in kept.iter() {
+                totals.include(total + from_exploded as i64 * dice.sides, chance * exploded_chance);
+            }
+        }
+
+        // Keeping a single d20 makes it the natural one. With more than one kept, it's whichever was rolled first.
+        let kept = match keep {
+            Keep::Highest(kept) | Keep::Lowest(kept) => kept,
+        };
+        if dice.sides == 20 && kept > 1 {
+            self.naturals_known = false;
+        }
+        let natural = |total: i64| match dice.sides == 20 && kept == 1 {
+            true => Some(total),
+            false => None,
+        };
+        Ok(totals.iter().map(|(total, chance)| ((total, natural(total)), chance)).collect())
+    }
+
+    /// The chance of each total from keeping the `keep` highest or lowest of `count` dice that each roll like `die`.
+    /// Goes through what the dice can roll from the end being kept, choosing how many dice rolled each number.
+    fn keep(&mut self, die: &Spread, count: usize, keep: usize, highest: bool) -> Result<Spread> {
+        let most = die.lowest + die.chances.len() as i64 - 1;
+        let totals = keep.saturating_mul(most.max(0) as usize).saturating_add(1);
+        let mut values: Vec<(i64, f64)> = die.iter().collect();
+        if highest {
+            values.reverse();
+        }
+        // Paid for up front, since just making room for the totals of a lot of big dice would take ages.
+        self.spend(values.len().saturating_mul((count + 1) * (count + 1)).saturating_mul(totals) / 2)?;
+        // The chance of each total so far, for each number of dice chosen so far.
+        let mut chosen = vec![vec![0.0; totals]; count + 1];
+        chosen[0][0] = 1.0;
+        for (value, chance) in values {
+            let mut next = vec![vec![0.0; totals]; count + 1];
+            for (so_far, totals_so_far) in chosen.iter().enumerate() {
+                let remaining = count - so_far;
+                let kept_remaining = keep.saturating_sub(so_far);
+                for (total, weight) in totals_so_far.iter().enumerate().filter(|(_, weight)| **weight > 0.0) {
+                    // The number of ways to pick which of the remaining dice roll this value, times the chance they all do.
+                    let mut ways = 1.0;
+                    for rolling in 0..=remaining {
+                        let added = rolling.min(kept_remaining) as i64 * value;
+                        next[so_far + rolling][total + added as usize] += weight * ways * chance.powi(rolling as i32);
+                        ways = ways * (remaining - rolling) as f64 / (rolling + 1) as f64;
+                    }
+                }
+            }
+            chosen = next;
+        }
+        Ok(Spread { lowest: 0, chances: chosen.pop().unwrap_or_default() })
+    }
+}
+
+/// The chance of every total a dice expression can roll.
+#[derive(Debug, Clone)]
+pub struct Odds {
+    /// The expression as it was understood, like `1d20+3`.
+    pub expression: String,
+    /// The chance of each total, rounded down as rolling does, along with the natural d20 rolled with it.
+    chances: BTreeMap<(i64, Option<i64>), f64>,
+    naturals_known: bool,
+}
+
+impl Odds {
+    pub(super) fn of(expression: &Expression) -> Result<Self> {
+        let mut calculation = Calculation { work: 0, naturals_known: true };
+        let mut chances = BTreeMap::new();
+        for ((value, natural), chance) in calculation.chances(expression)? {
+            *chances.entry((value.0.floor() as i64, natural)).or_default() += chance;
+        }
+        Ok(Odds { expression: expression.to_string(), chances, naturals_known: calculation.naturals_known })
+    }
+
+    /// The chance of each total, lowest first.
+    pub fn totals(&self) -> BTreeMap<i64, f64> {
+        let mut totals = BTreeMap::new();
+        for ((total, _), chance) in self.chances.iter() {
+            *totals.entry(*total).or_default() += chance;
+        }
+        totals
+    }
+
+    pub fn average(&self) -> f64 {
+        self.chances.iter().map(|((total, _), chance)| *total as f64 * chance).sum()
+    }
+
+    /// The average as damage, which never goes below 0 just like with `!damage`.
+    pub fn average_damage(&self) -> f64 {
+        self.chances.iter().map(|((total, _), chance)| (*total).max(0) as f64 * chance).sum()
+    }
+
+    /// The chance of each degree of success against the DC, best first. If `crit` is given, a natural of at least that
+    /// turns a success into a critical success, like a keen weapon does on a 19.
+    pub fn degrees(&self, dc: i64, crit: Option<i64>) -> Result<[(Degree, f64); 4]> {
+        if !self.naturals_known {
+            return Err(anyhow!("`{}` keeps more than one d20, so there's no telling which one is the natural d20 for a degree of success.", self.expression));
+        }
+        let mut degrees = Degree::ALL.map(|degree| (degree, 0.0));
+        for ((total, natural), chance) in self.chances.iter() {
+            let mut degree = Degree::of(*total, dc, *natural);
+            if degree == Degree::Success && crit.is_some_and(|crit| natural.is_some_and(|natural| natural >= crit)) {
+                degree = Degree::CriticalSuccess;
+            }
+            if let Some((_, total_chance)) = degrees.iter_mut().find(|(each, _)| *each == degree) {
+                *total_chance += chance;
+            }
+        }
+        Ok(degrees)
+    }
+
+    /// The average and range of totals, followed by a table of each total (or range of them) with the chance of rolling it
+    /// and of rolling at least that much.
+    pub fn describe(&self) -> String {
+        let totals = self.totals();
+        let (lowest, highest) = match (totals.keys().next(), totals.keys().last()) {
+            (Some(lowest), Some(highest)) => (*lowest, *highest),
+            _ => return String::from("It can't roll anything."),
+        };
+        let width = ((highest - lowest) / MAX_ROWS + 1).max(1);
+        let mut table = format!("{:>13} {:>7} {:>9}\n", "Total", "Chance", "At least");
+        let mut at_least = 1.0;
+        let mut start = lowest;
+        while start <= highest {
+            let end = (start + width - 1).min(highest);
+            let chance: f64 = totals.range(start..=end).map(|(_, chance)| chance).sum();
+            let label = match start == end {
+                true => start.to_string(),
+                false => format!("{} to {}", start, end),
+            };
+            table.push_str(&format!("{:>13} {:>7} {:>9}\n", label, percent(chance), percent(at_least)));
+            at_least -= chance;
+            start = end + 1;
+        }
+        format!("average {}, from {} to {}\n```\n{}```", number(self.average()), lowest, highest, table)
+    }
+
+    /// A table of the chance of each degree of success against the DC.
+    pub fn describe_degrees(&self, dc: i64, crit: Option<i64>) -> Result<String> {
+        let mut table = String::new();
+        for (degree, chance) in self.degrees(dc, crit)?.iter() {
+            table.push_str(&format!("{:<16} {:>6}\n", degree.heading(), percent(*chance)));
+        }
+        Ok(format!("```\n{}```", table))
+    }
+
+    /// The average damage of a Strike that rolls this to hit, where a success deals the damage and a critical success deals double.
+    pub fn describe_damage(&self, damage: &Odds, dc: i64, crit: Option<i64>) -> Result<String> {
+        let degrees = self.degrees(dc, crit)?;
+        let chance = |degree: Degree| degrees.iter().find(|(each, _)| *each == degree).map_or(0.0, |(_, chance)| *chance);
+        let average = damage.average_damage();
+        let overall = chance(Degree::Success) * average + chance(Degree::CriticalSuccess) * average * 2.0;
+        Ok(format!("Damage `{}` averages {} on a hit and {} on a critical hit, for {} per attack.",
+            damage.expression, number(average), number(average * 2.0), number(overall)))
+    }
+}
+
+/// A chance as a percentage, like `12.5%`. Chances too small to show as one are still shown as possible.
+fn percent(chance: f64) -> String {
+    match chance > 0.0 && chance < 0.0005 {
+        true => String::from("<0.1%"),
+        false => format!("{:.1}%", chance * 100.0),
+    }
+}
+
+/// A number to one decimal place, leaving it off whole numbers.
+fn number(value: f64) -> String {
+    let shown = format!("{:.1}", value);
+    match shown.strip_suffix(".0") {
+        Some(whole) if whole != "-0" => whole.to_string(),
+        Some(_) => String::from("0"),
+        None => shown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dice::odds;
+
+    fn close(actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() < 1e-9
+    }
+
+    fn chance(expression: &str, total: i64) -> f64 {
+        odds(expression).unwrap().totals().get(&total).copied().unwrap_or_default()
+    }
+
+    #[test]
+    fn sums() {
+        assert!(close(chance("2d6", 7), 6.0 / 36.0));
+        assert!(close(chance("2d6", 2), 1.0 / 36.0));
+        assert!(close(odds("2d8+4").unwrap().average(), 13.0));
+        assert!(close(odds("3d6*2-1").unwrap().average(), 20.0));
+        assert!(close(odds("1d6/2").unwrap().average(), 1.5));
+        assert!(close(odds("-1d4").unwrap().average(), -2.5));
+        assert!(close(odds("1d4-3").unwrap().average_damage(), 0.25));
+        assert_eq!(odds("5").unwrap().totals().len(), 1);
+        assert!(odds("1d6/(1d2-1)").is_err());
+        assert!(odds("500d1000").is_err());
+        // Too big to keep track of every total, so it has to be turned down before making room for them.
+        let started = std::time::Instant::now();
+        assert!(odds("500d1000kh500").is_err());
+        assert!(started.elapsed() < std::time::Duration::from_millis(100));
+    }
+
+    #[test]
+    fn options() {
+        assert!(close(odds("2d6r1").unwrap().average(), 2.0 * 141.0 / 36.0));
+        assert!(close(odds("1d6x").unwrap().average(), 4.2));
+        assert!(close(chance("1d6x", 6), 0.0));
+        assert!(close(chance("1d6x", 8), 1.0 / 36.0));
+        assert!(close(odds("fortune(1d20)").unwrap().average(), 13.825));
+        assert!(close(odds("misfortune(1d20)").unwrap().average(), 7.175));
+        assert!(close(odds("2d20kh1").unwrap().average(), 13.825));
+        assert!(close(odds("4d6kh3").unwrap().average(), 15869.0 / 1296.0));
+    }
+
+    #[test]
+    fn keeping_matches_every_roll() {
+        // Every way 3d4 can land, kept the same way rolling keeps them. Rerolled 1s come up a sixteenth of the time,
+        // and every other number five sixteenths.
+        for (expression, highest, reroll) in [("3d4kl2", false, false), ("3d4kh2", true, false), ("3d4r1kh2", true, true)].iter() {
+            let weight = |value: i64| match (reroll, value) {
+                (false, _) => 1.0 / 4.0,
+                (true, 1) => 1.0 / 16.0,
+                (true, _) => 5.0 / 16.0,
+            };
+            let mut expected: BTreeMap<i64, f64> = BTreeMap::new();
+            for first in 1..=4 {
+                for second in 1..=4 {
+                    for third in 1..=4 {
+                        let mut dice = [first, second, third];
+                        dice.sort_unstable();
+                        let total = if *highest { dice[1] + dice[2] } else { dice[0] + dice[1] };
+                        *expected.entry(total).or_default() += weight(first) * weight(second) * weight(third);
+                    }
+                }
+            }
+            let totals = odds(expression).unwrap().totals();
+            assert_eq!(totals.len(), expected.len(), "{}", expression);
+            for (total, chance) in expected.iter() {
+                assert!(close(totals[total], *chance), "{} rolling {}", expression, total);
+            }
+        }
+        // Exploded dice rolled their highest number, so keeping the highest takes them first, and the lowest takes them last.
+        assert!(close(chance("2d4xkh1", 4), 7.0 / 16.0));
+        assert!(close(chance("2d4xkh1", 8), 0.0));
+        assert!(close(chance("2d4xkl1", 1), 5.0 / 9.0));
+    }
+
+    #[test]
+    fn degrees() {
+        let degrees = |expression: &str, dc: i64, crit: Option<i64>| -> Vec<f64> {
+            odds(expression).unwrap().degrees(dc, crit).unwrap().iter().map(|(_, chance)| *chance).collect()
+        };
+        for (actual, expected) in degrees("1d20+10", 20, None).iter().zip([0.05, 0.5, 0.4, 0.05].iter()) {
+            assert!(close(*actual, *expected));
+        }
+        for (actual, expected) in degrees("1d20+10", 20, Some(19)).iter().zip([0.1, 0.45, 0.4, 0.05].iter()) {
+            assert!(close(*actual, *expected));
+        }
+        // Natural 20s and 1s come from the d20, not the dice added to it.
+        for (actual, expected) in degrees("1d20+1d4", 30, None).iter().zip([0.0, 0.05, 0.075, 0.875].iter()) {
+            assert!(close(*actual, *expected));
+        }
+        let fortune = degrees("fortune(1d20)", 11, None);
+        assert!(close(fortune[0], 39.0 / 400.0));
+        assert!(odds("3d20kh2").unwrap().degrees(15, None).is_err());
+    }
+
+    #[test]
+    fn descriptions() {
+        let described = odds("2d6").unwrap().describe();
+        assert!(described.starts_with("average 7, from 2 to 12\n```\n"));
+        assert!(described.contains("\n            7   16.7%     58.3%\n"));
+        assert_eq!(odds("10d10").unwrap().describe().lines().count(), 23);
+
+        let check = odds("1d20+10").unwrap();
+        assert_eq!(check.describe_degrees(20, None).unwrap(), "```\nCritical Success   5.0%\nSuccess           50.0%\nFailure           40.0%\nCritical Failure   5.0%\n```");
+        assert_eq!(check.describe_damage(&odds("2d8+4").unwrap(), 20, None).unwrap(),
+            "Damage `2d8+4` averages 13 on a hit and 26 on a critical hit, for 7.8 per attack.");
+        assert_eq!(percent(0.0001), "<0.1%");
+        assert_eq!(number(-0.01), "0");
+    }
+}
@@ -0,0 +1,181 @@
+//! Turns text like `4d6kh3 + 2` into an `Expression`. Multiplication and division come before addition and subtraction,
+//! as usual, and parentheses can be used to change that.
+
+use anyhow::{Result, anyhow};
+use super::expression::{Dice, Expression, Fortune, Keep};
+
+/// More dice than this in one go is surely a typo, and would make for an unreadable breakdown anyway.
+const MAX_DICE: i64 = 500;
+const MAX_SIDES: i64 = 1000;
+
+pub fn parse(text: &str) -> Result<Expression> {
+    let mut parser = Parser {
+        text,
+        characters: text.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_ascii_lowercase()).collect(),
+        position: 0,
+    };
+    if parser.characters.is_empty() {
+        return Err(anyhow!("There's nothing to roll."));
+    }
+    let expression = parser.sum()?;
+    match parser.peek() {
+        None => Ok(expression),
+        Some(')') => Err(parser.error("there's a `)` without a `(` to match it")),
+        Some(unexpected) => Err(parser.error(&format!("didn't expect '{}' there", unexpected))),
+    }
+}
+
+/// Whether the word is some dice on its own, like `2d6` or `4d6kh3`.
+pub fn is_dice(word: &str) -> bool {
+    let mut parser = Parser {
+        text: word,
+        characters: word.chars().map(|c| c.to_ascii_lowercase()).collect(),
+        position: 0,
+    };
+    matches!(parser.primary(), Ok(Expression::Dice(_))) && parser.peek().is_none()
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    characters: Vec<char>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.characters.get(self.position).copied()
+    }
+
+    fn next_is(&mut self, expected: &str) -> bool {
+        let matches = expected.chars().enumerate().all(|(offset, c)| self.characters.get(self.position + offset) == Some(&c));
+        if matches {
+            self.position += expected.chars().count();
+        }
+        matches
+    }
+
+    fn error(&self, problem: &str) -> anyhow::Error {
+        anyhow!("Couldn't roll `{}`: {}. Try something like `2d6+4`, `4d6kh3` or `fortune(1d20+5)`.", self.text.trim(), problem)
+    }
+
+    /// Terms added or subtracted, like `1d20+5-1`.
+    fn sum(&mut self) -> Result<Expression> {
+        let mut expression = self.product()?;
+        loop {
+            if self.next_is("+") {
+                expression = Expression::Add(Box::new(expression), Box::new(self.product()?));
+            } else if self.next_is("-") {
+                expression = Expression::Subtract(Box::new(expression), Box::new(self.product()?));
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+
+    /// Factors multiplied or divided, like `2d6*10`.
+    fn product(&mut self) -> Result<Expression> {
+        let mut expression = self.factor()?;
+        loop {
+            if self.next_is("*") {
+                expression = Expression::Multiply(Box::new(expression), Box::new(self.factor()?));
+            } else if self.next_is("/") {
+                expression = Expression::Divide(Box::new(expression), Box::new(self.factor()?));
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Result<Expression> {
+        if self.next_is("-") {
+            return Ok(Expression::Negate(Box::new(self.factor()?)));
+        }
+        if self.next_is("+") {
+            return self.factor();
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expression> {
+        for (word, fortune) in [("fortune", Fortune::Fortune), ("misfortune", Fortune::Misfortune)].iter() {
+            if self.next_is(word) {
+                if !self.next_is("(") {
+                    return Err(self.error(&format!("put what to roll in brackets after {}, like `{}(1d20+5)`", word, word)));
+                }
+                return Ok(Expression::Fortune(*fortune, Box::new(self.closed()?)));
+            }
+        }
+        if self.next_is("(") {
+            return Ok(Expression::Group(Box::new(self.closed()?)));
+        }
+
+        let count = self.number();
+        if self.next_is("d") {
+            return self.dice(count.unwrap_or(1));
+        }
+        match count {
+            Some(number) => Ok(Expression::Number(number)),
+            None => match self.peek() {
+                Some(unexpected) => Err(self.error(&format!("didn't expect '{}' there", unexpected))),
+                None => Err(self.error("it ends too soon")),
+            },
+        }
+    }
+
+    /// The rest of an expression in brackets, after the opening one.
+    fn closed(&mut self) -> Result<Expression> {
+        let expression = self.sum()?;
+        if !self.next_is(")") {
+            return Err(self.error("there's a `(` without a `)` to match it"));
+        }
+        Ok(expression)
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        if start == self.position {
+            return None;
+        }
+        // Numbers too big for an i64 are far too big for dice anyway, so they're capped and caught by the limits on dice.
+        Some(self.characters[start..self.position].iter().collect::<String>().parse().unwrap_or(i64::MAX))
+    }
+
+    /// Dice after the `d`, with any of their options, like `6kh3` or `6x`.
+    fn dice(&mut self, count: i64) -> Result<Expression> {
+        let sides = match self.next_is("%") {
+            true => 100,
+            false => self.number().ok_or_else(|| self.error("dice need a number of sides, like `d6`"))?,
+        };
+        if !(1..=MAX_DICE).contains(&count) {
+            return Err(self.error(&format!("only 1 to {} dice can be rolled at once", MAX_DICE)));
+        }
+        if !(1..=MAX_SIDES).contains(&sides) {
+            return Err(self.error(&format!("dice can only have 1 to {} sides", MAX_SIDES)));
+        }
+
+        let mut dice = Dice { count, sides, keep: None, reroll: None, explode: false };
+        loop {
+            if self.next_is("kl") {
+                dice.keep = Some(Keep::Lowest(self.number().unwrap_or(1)));
+            } else if self.next_is("kh") || self.next_is("k") {
+                dice.keep = Some(Keep::Highest(self.number().unwrap_or(1)));
+            } else if self.next_is("r") {
+                let reroll = self.number().unwrap_or(1);
+                if reroll >= sides {
+                    return Err(self.error(&format!("rerolling {} or lower on a d{} would reroll every die", reroll, sides)));
+                }
+                dice.reroll = Some(reroll);
+            } else if self.next_is("x") || self.next_is("!") {
+                if sides == 1 {
+                    return Err(self.error("a d1 would explode forever"));
+                }
+                dice.explode = true;
+            } else {
+                return Ok(Expression::Dice(dice));
+            }
+        }
+    }
+}
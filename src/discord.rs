@@ -4,14 +4,14 @@
 //! This way, if the library ever needed to be switched, or if a breaking change was introduced, we could simply
 //! update the code here and all of the calling functions would be ignorant.
 
-use omni::{Omnidata};
+use std::{collections::HashMap, sync::{Mutex, MutexGuard}};
 use twilight_http::Client as HttpClient;
-use twilight_model::{channel::{ChannelType::GuildCategory, GuildChannel}, gateway::{payload::MessageCreate}, guild::{Emoji}, id::MessageId};
+use twilight_model::{channel::{Attachment, ChannelType::GuildCategory, GuildChannel, Message}, gateway::{payload::MessageCreate}, guild::{Emoji}, id::{ChannelId, GuildId, MessageId, RoleId, UserId}};
 use anyhow::{Context, Result, anyhow};
-use crate::{command_words::Word, omni};
+use crate::command_words::Word;
+use crate::omni::storage::{OmniStore, Revision, StoreFuture};
 use reqwest;
 use futures;
-use core::mem::size_of_val;
 use twilight_embed_builder::{EmbedBuilder, EmbedFieldBuilder};
 
 pub const BOT_DATA_CHANNEL_CATEGORY_NAME: &str = "rust-monster-bot-data";
@@ -43,6 +43,107 @@ impl DiscordReferences<'_> {
         }
     }
 
+    /// Replies with a file attached, like a character exported as JSON.
+    pub async fn send_file_reply<Text: AsRef<str>>(&self, file_name: String, contents: String, text: Text) -> Result<()> {
+        match self.http.create_message(self.msg.channel_id).reply(self.msg.id).attachment(file_name, contents.into_bytes()).content(text.as_ref())?.await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!(e.to_string()))
+        }
+    }
+
+    /// Sends a text message to the same guild/channel, returning the new message's ID so it can be edited later.
+    pub async fn send_tracked_message<Text: AsRef<str>>(&self, text: Text) -> Result<u64> {
+        let message = self.http.create_message(self.msg.channel_id).content(text.as_ref())?.await?;
+        Ok(message.id.0)
+    }
+
+    /// Replaces the text of a message the bot sent earlier. Returns false if the message doesn't exist anymore.
+    pub async fn edit_message<Text: AsRef<str>>(&self, channel_id: u64, message_id: u64, text: Text) -> Result<bool> {
+        match self.http.update_message(ChannelId(channel_id), MessageId(message_id)).content(text.as_ref().to_string())?.await {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e) => Ok(false),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    /// Deletes a message the bot sent earlier. Messages that are already gone are fine.
+    pub async fn delete_message(&self, channel_id: u64, message_id: u64) -> Result<()> {
+        match self.http.delete_message(ChannelId(channel_id), MessageId(message_id)).await {
+            Err(e) if !is_not_found(&e) => Err(anyhow!(e.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sends a private DM to the user, for things that shouldn't be shown in the channel.
+    pub async fn send_dm<Text: AsRef<str>>(&self, text: Text) -> Result<()> {
+        self.send_dm_to(self.msg.author.id, text.as_ref()).await
+    }
+
+    /// Sends the same private DM to each of the users. Everyone is tried even if some can't be sent to, like those who don't allow DMs.
+    pub async fn send_dms<Text: AsRef<str>>(&self, user_ids: &[u64], text: Text) -> Result<()> {
+        let mut failed = 0;
+        for user_id in user_ids {
+            if let Err(e) = self.send_dm_to(UserId(*user_id), text.as_ref()).await {
+                println!("Couldn't DM {}: {:?}", user_id, e.to_string());
+                failed += 1;
+            }
+        }
+        match failed {
+            0 => Ok(()),
+            _ => Err(anyhow!("Couldn't send a DM to {} of {} users.", failed, user_ids.len())),
+        }
+    }
+
+    async fn send_dm_to(&self, user_id: UserId, text: &str) -> Result<()> {
+        let private_channel = self.http.create_private_channel(user_id).await?;
+        match self.http.create_message(private_channel.id).content(text)?.await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!(e.to_string()))
+        }
+    }
+
+    /// The contents of the first file attached to the message, if there is one.
+    pub async fn attachment_text(&self) -> Result<Option<String>> {
+        match self.msg.attachments.first() {
+            Some(attachment) => Ok(Some(reqwest::get(&attachment.url).await?.text().await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The IDs of the roles the user has in the guild.
+    pub fn author_roles(&self) -> Vec<u64> {
+        self.msg.member.as_ref().map(|member| member.roles.iter().map(|role| role.0).collect()).unwrap_or_default()
+    }
+
+    /// The IDs of every member of the guild with the role, leaving out bots.
+    pub async fn members_with_role(&self, guild_id: GuildId, role: u64) -> Result<Vec<u64>> {
+        // Discord hands out members a page at a time, each starting after the last one seen.
+        const PAGE_SIZE: u64 = 1000;
+        let mut found = Vec::new();
+        let mut after = None;
+        loop {
+            let mut request = self.http.guild_members(guild_id).limit(PAGE_SIZE)?;
+            if let Some(after) = after {
+                request = request.after(after);
+            }
+            let members = request.await.map_err(|error| match is_forbidden(&error) {
+                true => anyhow!("Discord won't list this server's members, so the GMs can't be found. Whoever hosts the bot needs to turn on the Server Members intent for it in the Discord Developer Portal."),
+                false => anyhow!(error),
+            })?;
+            found.extend(members.iter().filter(|member| !member.user.bot && member.roles.contains(&RoleId(role))).map(|member| member.user.id.0));
+            match members.last() {
+                Some(last) if members.len() as u64 == PAGE_SIZE => after = Some(last.user.id),
+                _ => return Ok(found),
+            }
+        }
+    }
+
+    /// Find one of the guild's roles by name, ignoring case.
+    pub async fn find_role(&self, guild_id: GuildId, name: &str) -> Result<Option<u64>> {
+        let roles = self.http.roles(guild_id).await?;
+        Ok(roles.iter().find(|role| role.name.to_lowercase() == name.to_lowercase()).map(|role| role.id.0))
+    }
+
     /// Sends a private DM to the user containing help about a bot command or keyword
     pub async fn dm_help_message(&self, help_word: &Word<'_>) -> Result<()> {
         let embed = EmbedBuilder::new()
@@ -61,8 +162,18 @@ impl DiscordReferences<'_> {
     
 }
 
+/// Whether Discord said the thing a request was about doesn't exist, like a message someone deleted.
+fn is_not_found(error: &twilight_http::Error) -> bool {
+    matches!(error.kind(), twilight_http::error::ErrorType::Response { status, .. } if status.as_u16() == 404)
+}
+
+/// Whether Discord refused the request, like listing members without the Server Members intent.
+fn is_forbidden(error: &twilight_http::Error) -> bool {
+    matches!(error.kind(), twilight_http::error::ErrorType::Response { status, .. } if status.as_u16() == 403)
+}
+
 /// This is an idempotent function that will create the channels to house all bot data and a category to contain them.
-pub async fn create_omni_data_channel(DiscordReferences { http, msg }: &DiscordReferences<'_>, guild_channels: &Vec<GuildChannel>) -> Result<GuildChannel> {
+pub async fn create_omni_data_channel(http: &HttpClient, guild_id: GuildId, guild_channels: &[GuildChannel]) -> Result<GuildChannel> {
     //Usually we want to make the channel in a category to make things easier for the server owner to manage, so find/make that first.
     let channel_category;
     match guild_channels.iter().find(|&channel| channel.name() == BOT_DATA_CHANNEL_CATEGORY_NAME) {
@@ -70,7 +181,7 @@ pub async fn create_omni_data_channel(DiscordReferences { http, msg }: &DiscordR
             channel_category = category.clone();
         }
         None => {
-            channel_category = http.create_guild_channel(msg.guild_id.expect("Could not find guild ID when creating bot category!"), BOT_DATA_CHANNEL_CATEGORY_NAME)?
+            channel_category = http.create_guild_channel(guild_id, BOT_DATA_CHANNEL_CATEGORY_NAME)?
                 .kind(GuildCategory)
                 .position(999)
                 .await
@@ -85,9 +196,7 @@ pub async fn create_omni_data_channel(DiscordReferences { http, msg }: &DiscordR
             bot_data_channel = channel.clone();
         }
         None => {
-            //Let the user know that we are getting Discord set up for the bot.
-            http.create_message(msg.channel_id).reply(msg.id).content(format!("Getting Discord set up."))?.await?;
-            bot_data_channel = http.create_guild_channel(msg.guild_id.expect("Could not find guild ID when creating bot category!"), BOT_DATA_CHANNEL_NAME)?
+            bot_data_channel = http.create_guild_channel(guild_id, BOT_DATA_CHANNEL_NAME)?
                 .parent_id(channel_category.id())
                 .await
                 .context("Could not create channel for bot data. Does the bot have the correct permissions?")?;
@@ -96,8 +205,8 @@ pub async fn create_omni_data_channel(DiscordReferences { http, msg }: &DiscordR
     return Ok(bot_data_channel.clone());
 }
 
-pub async fn get_omni_data_channel(discord_references: &DiscordReferences<'_>) -> Result<GuildChannel> {
-    let guild_channels = discord_references.http.guild_channels(discord_references.msg.guild_id.expect("Could not get guild ID!")).await?;
+pub async fn get_omni_data_channel(http: &HttpClient, guild_id: GuildId) -> Result<GuildChannel> {
+    let guild_channels = http.guild_channels(guild_id).await?;
 
     match guild_channels.iter().find(|&channel| channel.name() == BOT_DATA_CHANNEL_NAME) {
         Some(channel) => {
@@ -105,9 +214,7 @@ pub async fn get_omni_data_channel(discord_references: &DiscordReferences<'_>) -
         }
         None => {
             //Do setup
-            let new_channel = create_omni_data_channel(&discord_references, &guild_channels).await?;
-            discord_references.http.create_message(discord_references.msg.channel_id).reply(discord_references.msg.id).content(format!("Bot setup complete."))?.await?;
-            Ok(new_channel)
+            create_omni_data_channel(http, guild_id, &guild_channels).await
         }
     }
 }
@@ -168,50 +275,153 @@ pub async fn construct_emoji(discord_references: &DiscordReferences<'_>, emoji_n
     return Ok(result_string.to_string());
 }
 
-/// Save the omni data to the discord guild to preserve state between bot commands.
-/// This also takes care of pinning the new message and unpinning all others.
-/// Will only do anything if the omnidata object is dirty.
-pub async fn omni_data_save(discord_references: &DiscordReferences<'_>, omnidata: &omni::Omnidata) -> Result<()> {
-    if omnidata.is_dirty {
-        let serialized = serde_json::to_vec(&omnidata)?;
-        println!("Size of vec is: {:?}", size_of_val(&*serialized));
-        let data_channel = get_omni_data_channel(&discord_references).await?;
-        match discord_references.http.create_message(data_channel.id()).attachment("state", serialized).content(format!("'{}'", &discord_references.msg.content))?.await {
-            Err(error) => {
-                println!("Error when saving bot data. {:?}", error);
-                discord_references.http.create_message(discord_references.msg.channel_id).content("Something went wrong saving the bot data. Rolling back the previous command!")?.await?;
-                return Err(anyhow!(error.to_string()));
-            },
-            Ok(new_message) => {
-                // The bot relies on a message being pinned in the data channel to know which one is the 'active' one. Unpin the old one, then pin the new one.
-                // TODO: Pinning API is STUPID SLOW. Find a better way, like using the newest message.
-                let mut pin_jobs = Vec::new();
-                let old_pins = discord_references.http.pins(new_message.channel_id).await?;
-                for old_pin in old_pins.iter() {
-                    pin_jobs.push(discord_references.http.delete_pin(old_pin.channel_id, old_pin.id));
-                }
-                let delete_jobs = futures::future::join_all(pin_jobs);
-                let foo = discord_references.http.create_pin(new_message.channel_id, new_message.id);
-                futures::join!(delete_jobs, foo);
-                return Ok(());
-             },
+/// Stores omnidata as message attachments in a channel on the guild itself, so the bot needs no storage of its own.
+/// The pinned message is the current state, and every save posts a new message with the originating command as its content.
+/// Messages newer than the pinned one are revisions that were undone and can still be redone.
+/// The roll log rides along with each save as a second attachment, rather than costing several more requests for every roll.
+/// Rolls made since the last save are only kept in memory until the next one, and the newest save's roll log is the one
+/// that counts, even if that save has since been undone.
+pub struct DiscordPinStore {
+    http: HttpClient,
+    /// The latest roll log of each guild, waiting to go out with its next save.
+    roll_logs: Mutex<HashMap<GuildId, String>>,
+}
+
+impl DiscordPinStore {
+    pub fn new(http: HttpClient) -> Self {
+        DiscordPinStore { http, roll_logs: Mutex::new(HashMap::new()) }
+    }
+
+    /// Find the current omni tracker data, and return it still serialized.
+    async fn load_pinned(&self, guild_id: GuildId) -> Result<Option<String>> {
+        let data_channel = get_omni_data_channel(&self.http, guild_id).await?;
+        let pins = self.http.pins(data_channel.id()).await?;
+
+        match pins.first().and_then(|pin| find_attachment(pin, STATE_ATTACHMENT)) {
+            None => Ok(None),
+            Some(attachment) => Ok(Some(reqwest::get(&attachment.url).await?.text().await?)),
+        }
+    }
+
+    /// Save the omni data to the discord guild to preserve state between bot commands.
+    /// This also takes care of pinning the new message and unpinning all others.
+    async fn save_pinned(&self, guild_id: GuildId, data: String, reason: String) -> Result<()> {
+        println!("Size of save is: {:?}", data.len());
+        let data_channel = get_omni_data_channel(&self.http, guild_id).await?;
+
+        // Anything newer than the pinned message was undone, and saving over it means it can't be redone anymore.
+        if let Some(current) = self.http.pins(data_channel.id()).await?.first() {
+            let undone = self.http.channel_messages(data_channel.id()).after(current.id).limit(100)?.await?;
+            let delete_jobs = undone.iter().map(|message| self.http.delete_message(message.channel_id, message.id));
+            for deleted in futures::future::join_all(delete_jobs).await {
+                deleted?;
+            }
+        }
+
+        let mut attachments = vec![(STATE_ATTACHMENT, data.into_bytes())];
+        if let Some(roll_log) = self.roll_logs()?.get(&guild_id) {
+            attachments.push((ROLL_LOG_ATTACHMENT, roll_log.clone().into_bytes()));
+        }
+        let new_message = self.http.create_message(data_channel.id())
+            .attachments(attachments)
+            .content(format!("'{}'", reason))?
+            .await?;
+        self.move_pin(new_message.channel_id, new_message.id).await
+    }
+
+    /// The bot relies on a message being pinned in the data channel to know which one is the 'active' one.
+    /// Pin the new one first, then unpin the old ones, so there's never a moment without a pinned message.
+    async fn move_pin(&self, channel_id: ChannelId, message_id: MessageId) -> Result<()> {
+        // TODO: Pinning API is STUPID SLOW. Find a better way, like using the newest message.
+        let old_pins = self.http.pins(channel_id).await?;
+        self.http.create_pin(channel_id, message_id).await?;
+        let unpin_jobs = old_pins.iter()
+            .filter(|old_pin| old_pin.id != message_id)
+            .map(|old_pin| self.http.delete_pin(old_pin.channel_id, old_pin.id));
+        futures::future::join_all(unpin_jobs).await;
+        Ok(())
+    }
+
+    /// Every save in the data channel, newest first.
+    async fn list_saves(&self, guild_id: GuildId, limit: usize) -> Result<Vec<Revision>> {
+        let data_channel = get_omni_data_channel(&self.http, guild_id).await?;
+        let messages = self.http.channel_messages(data_channel.id()).limit(limit.min(100) as u64)?.await?;
+        Ok(messages.iter()
+            .filter(|message| !message.attachments.is_empty())
+            .map(|message| Revision {
+                id: message.id.0,
+                reason: message.content.trim_matches('\'').to_string(),
+                is_current: message.pinned,
+            })
+            .collect())
+    }
+
+    async fn restore_save(&self, guild_id: GuildId, id: u64) -> Result<String> {
+        let data_channel = get_omni_data_channel(&self.http, guild_id).await?;
+        let message = self.http.message(data_channel.id(), MessageId(id)).await?
+            .ok_or_else(|| anyhow!("There is no revision {} to restore", id))?;
+        let attachment = find_attachment(&message, STATE_ATTACHMENT).ok_or_else(|| anyhow!("Revision {} has no saved data", id))?;
+        let data = reqwest::get(&attachment.url).await?.text().await?;
+        self.move_pin(message.channel_id, message.id).await?;
+        Ok(data)
+    }
+
+    /// The roll log from the newest save that has one, undone or not, unless a newer one is still waiting to be saved.
+    async fn load_newest_roll_log(&self, guild_id: GuildId) -> Result<Option<String>> {
+        if let Some(roll_log) = self.roll_logs()?.get(&guild_id) {
+            return Ok(Some(roll_log.clone()));
+        }
+        let data_channel = get_omni_data_channel(&self.http, guild_id).await?;
+        let messages = self.http.channel_messages(data_channel.id()).limit(100)?.await?;
+        let roll_log = match messages.iter().find_map(|message| find_attachment(message, ROLL_LOG_ATTACHMENT)) {
+            None => return Ok(None),
+            Some(attachment) => reqwest::get(&attachment.url).await?.text().await?,
         };
-    } else {
-        return Ok(());
+        self.remember_roll_log(guild_id, roll_log.clone())?;
+        Ok(Some(roll_log))
+    }
+
+    fn roll_logs(&self) -> Result<MutexGuard<'_, HashMap<GuildId, String>>> {
+        self.roll_logs.lock().map_err(|_| anyhow!("Roll logs were poisoned by an earlier panic"))
+    }
+
+    /// Hold on to the roll log until the guild's next save.
+    fn remember_roll_log(&self, guild_id: GuildId, data: String) -> Result<()> {
+        self.roll_logs()?.insert(guild_id, data);
+        Ok(())
     }
 }
 
-/// Given a discord ref struct, find the current omni tracker data, deserialize it, and return a usable object
-pub async fn get_tracker(discord_refs: &DiscordReferences<'_>) -> Result<Omnidata> {
-    let data_channel = get_omni_data_channel(discord_refs).await?;
-    let pins = discord_refs.http.pins(data_channel.id()).await?;
+/// The names of the attachments on each save.
+const STATE_ATTACHMENT: &str = "state";
+const ROLL_LOG_ATTACHMENT: &str = "rolls";
+
+fn find_attachment<'a>(message: &'a Message, name: &str) -> Option<&'a Attachment> {
+    message.attachments.iter().find(|attachment| attachment.filename == name)
+}
+
+impl OmniStore for DiscordPinStore {
+    fn load(&self, guild_id: GuildId) -> StoreFuture<'_, Option<String>> {
+        Box::pin(self.load_pinned(guild_id))
+    }
 
-    match pins.len() {
-        0 => return Ok(omni::Omnidata::new()),
-        _ => {
-            let data = reqwest::get(&pins[0].attachments[0].url).await?.text().await?;
-            let omnidata: Omnidata = serde_json::from_str(&data)?;
-            return Ok(omnidata);
-        },
+    fn save(&self, guild_id: GuildId, data: String, reason: String) -> StoreFuture<'_, ()> {
+        Box::pin(self.save_pinned(guild_id, data, reason))
     }
-}
\ No newline at end of file
+
+    fn history(&self, guild_id: GuildId, limit: usize) -> StoreFuture<'_, Vec<Revision>> {
+        Box::pin(self.list_saves(guild_id, limit))
+    }
+
+    fn restore(&self, guild_id: GuildId, id: u64) -> StoreFuture<'_, String> {
+        Box::pin(self.restore_save(guild_id, id))
+    }
+
+    fn load_roll_log(&self, guild_id: GuildId) -> StoreFuture<'_, Option<String>> {
+        Box::pin(self.load_newest_roll_log(guild_id))
+    }
+
+    fn save_roll_log(&self, guild_id: GuildId, data: String) -> StoreFuture<'_, ()> {
+        Box::pin(futures::future::ready(self.remember_roll_log(guild_id, data)))
+    }
+}
@@ -4,6 +4,7 @@ use anyhow::{Result};
 use convert_case::{Case, Casing};
 use tokio::time::{sleep, Duration};
 use reqwest;
+use crate::dice::Degree;
 use crate::discord::{DiscordReferences, create_custom_emojis, construct_emoji};
 
 const MAX_RESULTS: i8 = 9; //Number of ambiguous results to show: up to 9
@@ -178,10 +179,10 @@ async fn sanitize(sanitize_me: &str) -> Result<String, Box<dyn std::error::Error
 /// It does things liks split up types of items and add some line breaks to make spell outcomes easier to read
 async fn pretty_format(mut string_to_format: String) -> Result<String, Box<dyn std::error::Error>> {
     string_to_format = str::replace(&string_to_format, "\nType", "\n\nType");
-    string_to_format = str::replace(&string_to_format, "**Critical Success**", "\n\n**Critical Success**");
-    string_to_format = str::replace(&string_to_format, "**Success**", "\n\n**Success**");
-    string_to_format = str::replace(&string_to_format, "**Failure**", "\n\n**Failure**");
-    string_to_format = str::replace(&string_to_format, "**Critical Failure**", "\n\n**Critical Failure**");
+    for degree in Degree::ALL.iter() {
+        let heading = format!("**{}**", degree);
+        string_to_format = str::replace(&string_to_format, &heading, &format!("\n\n{}", heading));
+    }
     return Ok(string_to_format)
 }
 
@@ -1,7 +1,7 @@
 use std::{env, error::Error};
 use discord::DiscordReferences;
 use futures::{lock::Mutex, stream::StreamExt};
-use omni::Omnidata;
+use omni::{Omnidata, storage::{self, OmniStore}};
 use twilight_cache_inmemory::{InMemoryCache, ResourceType};
 use twilight_gateway::{cluster::{Cluster, ShardScheme}, Event};
 use twilight_http::Client as HttpClient;
@@ -62,6 +62,9 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         .timeout(Duration::from_secs(300))   
         .build();
 
+    // Where each guild's tracker data is kept. Defaults to pinned messages on the guild itself.
+    let store = storage::open(&env::var("OMNI_STORAGE").unwrap_or_default(), &http)?;
+
     // Since we only care about new messages, make the cache only
     // cache new messages.
     let cache = InMemoryCache::builder()
@@ -81,7 +84,7 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                 if !omnidata_cache.contains_key(&guild_id) {
                     omnidata_cache.insert(guild_id, Arc::new(Mutex::new(None)));
                 }
-                tokio::spawn(handle_message(http.clone(),Arc::clone(omnidata_cache.get(&guild_id).expect("Expected to find omnidata in hash!")), msg, parser.clone()));
+                tokio::spawn(handle_message(http.clone(),Arc::clone(omnidata_cache.get(&guild_id).expect("Expected to find omnidata in hash!")), Arc::clone(&store), msg, parser.clone()));
             }
             Event::ShardConnected(_) => {
                 println!("Connected on shard {}", shard_id);
@@ -97,6 +100,7 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
 async fn handle_message(
     http: HttpClient,
     omnidata_cache: Arc<Mutex<Option<Omnidata>>>,
+    store: Arc<dyn OmniStore>,
     msg: Box<MessageCreate>,
     parser: Parser<'_>,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
@@ -110,11 +114,8 @@ async fn handle_message(
         Some(Command { name: "help", arguments, .. }) => {
             command_words::handle_help_command(&discord_refs, arguments.as_str()).await;
         },
-        Some(Command { name: "roll", arguments, .. }) => {
-            omni::handle_command(&discord_refs, Arc::clone(&omnidata_cache), "roll", arguments.as_str()).await;
-        },
-        Some(Command { name: "add", arguments, .. }) => {
-            omni::handle_command(&discord_refs, Arc::clone(&omnidata_cache), "add", arguments.as_str()).await;
+        Some(Command { name, arguments, .. }) if omni::COMMANDS.contains(&name) => {
+            omni::handle_command(&discord_refs, Arc::clone(&omnidata_cache), store.as_ref(), name, arguments.as_str()).await;
         },
         //Ignore anything that doesn't match the commands above.
         Some(_) => {},
@@ -1,25 +1,66 @@
 mod character;
-use crate::{discord, omni::character::Character};
+pub mod effect;
+pub mod encounter;
+mod gm;
+mod history;
+mod import;
+mod migration;
+mod roll_log;
+pub mod storage;
+mod tracker;
+use crate::omni::character::{Character, HitPointsReply};
 use serde::{Deserialize, Serialize};
 use crate::discord::{DiscordReferences};
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
 use std::{pin::Pin, sync::Arc, u16};
 use futures::{Future, TryFutureExt, lock::Mutex};
-use roll_rs::roll_inline;
+use crate::dice;
 use pest::Parser;
-use crate::omni::character::add_character;
+use crate::omni::character::defense::DamagePart;
+use crate::omni::character::formula;
+use crate::omni::effect::{Duration, Effect, EffectModifier, is_signed_number};
+use crate::omni::encounter::Encounter;
+use crate::omni::gm::{Author, Redacted};
+use crate::omni::tracker::Tracker;
+use crate::omni::character::{add_character, parse_noun_target_stats_command, list_characters, remove_character, rename_character, give_character};
+use crate::omni::migration::MigrationRecord;
+use crate::omni::roll_log::RollLog;
+use crate::omni::storage::OmniStore;
+use twilight_model::id::GuildId;
 
 #[derive(Parser)]
 #[grammar = "omni_commands.pest"]
 pub struct OmniCommandParser;
 
-const OMNI_VERSION: u16 = 0;
+const OMNI_VERSION: u16 = 12;
+
+/// Every command verb that is handled by handle_command.
+pub const COMMANDS: [&str; 28] = ["add", "remove", "set", "rename", "give", "import", "export", "list", "show", "roll", "sroll", "check", "odds", "rolls", "damage", "heal", "temphp", "sustain",
+    "start", "end", "init", "next", "delay", "ready", "tracker", "undo", "redo", "history"];
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Omnidata {
     pub version: u16,
+    #[serde(skip)]
     pub is_dirty: bool,
     pub characters: Vec<Character>,
+    /// The ID the next new character will get. IDs are never reused, even after a character is removed.
+    pub next_character_id: u64,
+    pub effects: Vec<Effect>,
+    /// The encounter going on right now, if there is one.
+    pub encounter: Option<Encounter>,
+    /// The tracker message in each channel that has one.
+    pub trackers: Vec<Tracker>,
+    /// The ID of the Discord role for GMs. Enemy stats are hidden from everyone without it, if there is one.
+    pub gm_role: Option<u64>,
+    /// Where the guild's dice come from, and what they've rolled. It's saved on its own rather than in each revision, so
+    /// it's only read from older data that still has it.
+    #[serde(default, skip_serializing)]
+    pub roll_log: RollLog,
+    /// True when the roll log has changed since it was last saved.
+    #[serde(skip)]
+    pub is_roll_log_dirty: bool,
+    pub migrations: Vec<MigrationRecord>,
 }
 
 impl Omnidata {
@@ -28,27 +69,100 @@ impl Omnidata {
             version: OMNI_VERSION, 
             characters: Vec::new(), 
             is_dirty: false,
+            is_roll_log_dirty: false,
+            next_character_id: 1,
+            effects: Vec::new(),
+            encounter: None,
+            trackers: Vec::new(),
+            gm_role: None,
+            roll_log: RollLog::default(),
+            migrations: Vec::new(),
         }
     }
 
+    /// Deserialize omnidata that was saved by this or any older version of the bot.
+    pub fn load(data: &str) -> Result<Self> {
+        migration::load(data)
+    }
+
     fn dirty(&mut self) {
         self.is_dirty = true;
     }
+
+    /// Everything that changed has been saved.
+    fn saved(&mut self) {
+        self.is_dirty = false;
+        self.is_roll_log_dirty = false;
+    }
+
+    /// Roll any dice a command needs from the guild's seed, and log them along with who asked.
+    /// The log is saved even when nothing else changed, so rolls made by commands like `!roll` aren't lost.
+    fn log_rolls<T>(&mut self, guild_id: GuildId, message_id: u64, author: u64, rolling: impl FnOnce(&mut Self) -> T) -> T {
+        let seed = (self.roll_log.epoch, self.roll_log.commitment.clone());
+        let source = self.roll_log.source(guild_id.0, message_id);
+        let (result, rolled) = dice::with_source(source, || rolling(self));
+        // A seed that's used or revealed for the first time has to be remembered too, or it could be used again later.
+        if !rolled.is_empty() || seed != (self.roll_log.epoch, self.roll_log.commitment.clone()) {
+            self.is_roll_log_dirty = true;
+        }
+        self.roll_log.record(message_id, author, rolled);
+        result
+    }
+
+    /// Find where a character is by name, ignoring case and any quotes.
+    fn character_index(&self, name: &str) -> Option<usize> {
+        let name = name.replace("\"", "").to_lowercase();
+        self.characters.iter().position(|character| character.name.to_lowercase() == name)
+    }
+
+    pub fn find_character(&self, name: &str) -> Option<&Character> {
+        self.character_index(name).map(move |index| &self.characters[index])
+    }
+
+    pub fn find_character_mut(&mut self, name: &str) -> Option<&mut Character> {
+        self.character_index(name).map(move |index| &mut self.characters[index])
+    }
+
+    pub fn find_character_by_id(&self, id: u64) -> Option<&Character> {
+        self.characters.iter().find(|character| character.id == id)
+    }
+
+    /// The name of a character, for messages about things that only know their ID.
+    fn character_name(&self, id: u64) -> String {
+        self.find_character_by_id(id).map(|character| character.name.clone()).unwrap_or_else(|| String::from("someone who has left"))
+    }
+
+    /// Add a new character, giving them the next ID.
+    pub fn insert_character(&mut self, mut character: Character) -> u64 {
+        character.id = self.next_character_id;
+        self.next_character_id += 1;
+        self.characters.push(character);
+        self.next_character_id - 1
+    }
+}
+
+/// The words of a verb-noun-target command, with any quotes stripped.
+struct GenericCommand {
+    noun: String,
+    target: String,
+    extras: Vec<String>,
 }
 
 /// Entry point for all bot commands that deal with the tracker data and characters
 pub async fn handle_command(
     discord_refs: &DiscordReferences<'_>, 
     omnidata_cache: Arc<Mutex<Option<Omnidata>>>,
+    store: &dyn OmniStore,
     command: &str,
     arguments: &str,
 ) -> Result<()> {
+    let guild_id = discord_refs.msg.guild_id.expect("Could not get guild ID!");
 
     // Lock the cached botdata. This should prevent any other commands from being run on this guild
     // If it doesn't exist, get the data from the guild and cache it
     let mut omnidata_guard = omnidata_cache.lock().await;
     if omnidata_guard.is_none() {
-        *omnidata_guard = match discord::get_tracker(&discord_refs).await {
+        *omnidata_guard = match load_omnidata(store, guild_id).await {
             Ok(v) => Some(v),
             Err(e) => {
                 println!("Error setting up bot. {:?}", e);
@@ -57,68 +171,143 @@ pub async fn handle_command(
             }
         }
     }
+
+    let (command, arguments) = secret_roll_command(command, arguments);
+    let is_gm = omnidata_guard.as_ref().unwrap().is_gm(&discord_refs.author_roles());
+    let author = Author { id: discord_refs.msg.author.id.0, is_gm };
+    if let Err(error) = gm::ensure_allowed(&author, command, arguments) {
+        return discord_refs.send_message_reply(error.to_string()).await;
+    }
+
+    // Stepping through history swaps out the whole omnidata rather than changing it, so it's handled on its own.
+    if let "undo" | "redo" | "history" = command {
+        history::handle_history_command(discord_refs, &mut omnidata_guard, store, guild_id, command, arguments).await?;
+        if let Some(omnidata) = omnidata_guard.as_mut().filter(|_| command != "history") {
+            tracker::refresh_trackers(discord_refs, omnidata).await;
+        }
+        return Ok(());
+    }
     let omnidata: &mut Omnidata = omnidata_guard.as_mut().unwrap();
+    let message_id = discord_refs.msg.id.0;
+    if command == "tracker" {
+        return tracker::handle_tracker_command(discord_refs, omnidata, store, guild_id, arguments).await;
+    }
+    if command == "set" && arguments.split_whitespace().next().is_some_and(|noun| noun.eq_ignore_ascii_case("gm")) {
+        return gm::handle_set_gm_command(discord_refs, omnidata, store, guild_id, arguments).await;
+    }
+    // Imports are usually attached rather than pasted, and need downloading before they can be handled like any other command.
+    let attachment = match command {
+        "import" => discord_refs.attachment_text().await?,
+        _ => None,
+    };
 
     // Do whatever the user requested us to do. First we'll match by verb and let the following function handle the rest.
-    let response = match command {
-        "add" => Some(handle_add_command(discord_refs, omnidata, arguments)),
-        "roll" => Some(handle_roll_command(discord_refs, omnidata, arguments)),
+    let response = omnidata.log_rolls(guild_id, message_id, author.id, |omnidata| match command {
+        "add" => Some(handle_add_command(discord_refs, omnidata, author, arguments)),
+        "remove" => Some(handle_remove_command(discord_refs, omnidata, author, arguments)),
+        "set" => Some(handle_set_command(discord_refs, omnidata, author, arguments)),
+        "rename" => Some(handle_rename_command(discord_refs, omnidata, author, arguments)),
+        "give" => Some(reply(discord_refs, give(omnidata, &author, arguments))),
+        "import" => Some(reply(discord_refs, import::import_character(omnidata, &author, arguments, attachment))),
+        "export" => Some(import::handle_export_command(discord_refs, omnidata, &author, arguments)),
+        "list" => Some(reply(discord_refs, list_characters(omnidata, arguments))),
+        "show" => Some(reply_redacted(discord_refs, is_gm, show_character(omnidata, arguments))),
+        "roll" => Some(reply_redacted(discord_refs, is_gm, roll(omnidata, author.id, arguments))),
+        // Secret rolls go to the GMs instead of being a reply.
+        "sroll" => Some(gm::send_secret_roll(discord_refs, guild_id, omnidata.gm_role, roll(omnidata, author.id, arguments))),
+        "check" => Some(reply_redacted(discord_refs, is_gm, check(omnidata, author.id, arguments))),
+        "odds" => Some(reply_redacted(discord_refs, is_gm, odds(omnidata, author.id, arguments))),
+        "rolls" => Some(reply_redacted(discord_refs, is_gm, roll_log::handle_rolls_command(omnidata, guild_id.0, arguments))),
+        "damage" => Some(reply_redacted(discord_refs, is_gm, damage_character(omnidata, &author, arguments))),
+        "heal" => Some(reply_redacted(discord_refs, is_gm, change_hit_points(omnidata, &author, arguments, Character::heal))),
+        "temphp" => Some(reply_redacted(discord_refs, is_gm, change_hit_points(omnidata, &author, arguments, Character::gain_temp_hp))),
+        "sustain" => Some(reply(discord_refs, sustain_effect(omnidata, &author, arguments))),
+        "start" => Some(reply(discord_refs, start_encounter(omnidata, arguments))),
+        "end" => Some(reply(discord_refs, end_encounter(omnidata, arguments))),
+        "init" => Some(reply(discord_refs, set_initiative(omnidata, &author, arguments))),
+        "next" => Some(reply(discord_refs, next_turn(omnidata, &author, arguments))),
+        "delay" => Some(reply(discord_refs, delay_turn(omnidata, &author, arguments))),
+        "ready" => Some(reply(discord_refs, ready_action(omnidata, &author, arguments))),
         _ => None
-    };
+    });
     
     //Save the data and send the reply returned from the function that handled the command. These both happen at the same time to make things snappier.
     let reply_msg = response.unwrap().map_err(|e| anyhow!("Problem creating reply! {:?}", e.to_string()));
-    let save = discord::omni_data_save(&discord_refs, &omnidata);
+    let changed = omnidata.is_dirty;
+    let save = save_omnidata(store, guild_id, omnidata, &discord_refs.msg.content);
     match futures::try_join!(reply_msg, save) {
         Ok((_,_)) => {
             println!("Actually done saving.");
+            omnidata.saved();
+            if changed {
+                tracker::refresh_trackers(discord_refs, omnidata).await;
+            }
             return Ok(());
         },
         Err(e) => {
             println!("Save failed with error: {:?}", e.to_string());
+            // Whatever the command did in memory never made it to the store, so throw it away and reload next time.
+            *omnidata_guard = None;
+            discord_refs.send_message("Something went wrong saving the bot data. Rolling back the previous command!").await?;
             return Err(anyhow!("Save failed with error: {:?}", e.to_string()));
         }
     }
 }
 
-/// Given a string of arguments, this will parse and return the target aka the second word.
-/// Word, in this case, is the first thing surrounded by spaces, or a quoted string with
-/// zero or more words and spaces inside. This will automatically strip any quotes.
-fn get_character_from_command<'message>(omnidata: &'message mut Omnidata, arguments: &str) -> Result<&'message mut Character> {
-    let parsed = OmniCommandParser::parse(Rule::generic_command, arguments);
-    match parsed {
-        Ok(mut pairs) => {
-            let mut command_words = pairs.next().unwrap().into_inner();  // Go into OmniCommand
-            let target = command_words.nth(1).unwrap();                   // Second word should be a target
-            let target_name = target.as_str().replace("\"", "");    // Commands can have quotes, but we don't want them in the output
-
-            let found_character: Option<&mut Character> = omnidata.characters.iter_mut().find(|character| character.name.to_lowercase() == target_name);
-            if found_character.is_none() {
-                return Err(anyhow!(format!("Couldn't find a character with '{}' for a name. Check your spelling.",target_name)));
-            }
-            Ok(found_character.unwrap())
-        },
-        Err(e) => Err(anyhow!("Couldn't parse command."))
+/// Load the guild's omnidata and roll log from the store, or start fresh if the guild has never saved any.
+async fn load_omnidata(store: &dyn OmniStore, guild_id: GuildId) -> Result<Omnidata> {
+    let mut omnidata = match store.load(guild_id).await? {
+        Some(data) => Omnidata::load(&data)?,
+        None => Omnidata::new(),
+    };
+    match store.load_roll_log(guild_id).await? {
+        Some(data) => omnidata.roll_log = serde_json::from_str(&data).context("Saved roll log doesn't match the current format")?,
+        // Older data kept the roll log with everything else, so it's moved to its own place with the next save.
+        None => omnidata.is_roll_log_dirty = true,
+    }
+    Ok(omnidata)
+}
+
+/// Save the omnidata to the store to preserve state between bot commands.
+/// Only whatever is dirty is saved, and the roll log doesn't make a new revision. It goes first, since some stores
+/// only hold on to it until the next revision is saved.
+async fn save_omnidata(store: &dyn OmniStore, guild_id: GuildId, omnidata: &Omnidata, reason: &str) -> Result<()> {
+    if omnidata.is_roll_log_dirty {
+        let data = serde_json::to_string(&omnidata.roll_log)?;
+        store.save_roll_log(guild_id, data).await?;
+    }
+    if omnidata.is_dirty {
+        let data = serde_json::to_string(omnidata)?;
+        store.save(guild_id, data, reason.to_string()).await?;
     }
+    Ok(())
+}
+
+/// Given a string of arguments, this will parse and return all the words of a verb-noun-target command.
+/// Commands can have quotes, but we don't want them in the output.
+fn parse_generic_command(arguments: &str) -> Result<GenericCommand> {
+    let mut pairs = OmniCommandParser::parse(Rule::generic_command, arguments).map_err(|_| anyhow!("Couldn't parse command."))?;
+    let mut command_words = pairs.next().unwrap().into_inner()  // Go into OmniCommand
+        .filter(|word| word.as_rule() != Rule::EOI)
+        .map(|word| word.as_str().replace("\"", ""));
+    Ok(GenericCommand {
+        noun: command_words.next().unwrap(),
+        target: command_words.next().unwrap(),
+        extras: command_words.collect(),
+    })
 }
 
 /// Given a string of arguments, this will parse and return the noun aka the first word.
 /// Word, in this case, is the first thing surrounded by spaces, or a quoted string with
 /// zero or more words and spaces inside. This will automatically strip any quotes.
+/// Only the noun is parsed, since whatever follows it might be stats that the noun's own parser has to handle.
 fn get_noun_from_command(arguments: &str) -> Result<String> {
-    let parsed = OmniCommandParser::parse(Rule::generic_command, arguments);
-    match parsed {
-        Ok(mut pairs) => {
-            let mut command_words = pairs.next().unwrap().into_inner(); // Go into OmniCommand
-            let noun = command_words.next().unwrap();                    // First word should be a noun
-            Ok(noun.as_str().replace("\"", ""))                            // Commands can have quotes, but we don't want them in the output
-        },
-        Err(e) => Err(anyhow!("Couldn't parse command."))
-    }
+    let mut pairs = OmniCommandParser::parse(Rule::noun, arguments.trim_start()).map_err(|_| anyhow!("Couldn't parse command."))?;
+    Ok(pairs.next().unwrap().as_str().replace("\"", ""))  // Commands can have quotes, but we don't want them in the output
 }
 
 /// Handle all ADD commands, although mostly that just involves figuring out what should be added and calling the correct function.
-fn handle_add_command<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'message>, omnidata: &mut Omnidata, arguments: &str) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
+fn handle_add_command<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'message>, omnidata: &mut Omnidata, author: Author, arguments: &str) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
     let noun = get_noun_from_command(arguments);
     if noun.is_err() {
         return Box::pin(discord_refs.send_message_reply("Failed to parse command. Remember the add command should follow the verb-noun-target syntax. For more help, consult `!help add`."));
@@ -126,29 +315,582 @@ fn handle_add_command<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'mess
     match get_noun_from_command(arguments).unwrap().as_str() {
         "player" | "enemy" => add_character(discord_refs, omnidata, arguments),
         "stat" => {
-            let char = match  get_character_from_command(omnidata, arguments) {
-                Ok(character) => character,
+            let parsed_command = match parse_noun_target_stats_command(arguments) {
+                Ok(parsed_command) => parsed_command,
                 Err(error) => return Box::pin(discord_refs.send_message_reply(error.to_string())),
             };
-            char.add_stat(discord_refs, arguments)
+            let stats = parsed_command.stats;
+            reply(discord_refs, edit_character(omnidata, &parsed_command.target, &author, |character| character.add_stats(stats)))
+        }
+        "effect" => reply(discord_refs, parse_generic_command(arguments).and_then(|command| add_effect(omnidata, author.id, command))),
+        "condition" => reply(discord_refs, parse_generic_command(arguments).and_then(|command| edit_character(omnidata, &command.target, &author, |character| match command.extras.as_slice() {
+            [name] => character.add_condition(name, None),
+            [name, value] => character.add_condition(name, Some(value)),
+            _ => Err(anyhow!("Give a condition and maybe its value, like `!add condition {} frightened 2`.", character.name)),
+        }))),
+        "immunity" | "weakness" | "resistance" => {
+            let command = match parse_generic_command(arguments) {
+                Ok(command) => command,
+                Err(error) => return Box::pin(discord_refs.send_message_reply(error.to_string())),
+            };
+            reply(discord_refs, edit_character(omnidata, &command.target, &author, |character| character.add_defense(&command.noun, &command.extras)))
         }
         unknown => return Box::pin(discord_refs.send_message_reply(format!("Sorry, I don't know how to add a '{}'. For more help, consult `!help add`.", unknown))),
     }
 }
 
-/// Handle simple roll commands. Arguments parameter should contain what to roll.
-/// Return is a Future containing the message back to the user with the results.
-fn handle_roll_command<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'message>, omnidata: &Omnidata, arguments: &str) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
-    // TODO: Call a function that will resolve any named stats in the dice notation for the character being rolled, for example !roll reflex
-    match roll_inline(arguments, false) {
-        Ok(roll) => Box::pin(discord_refs.send_message_reply(format!("```\n{}```", roll.string_result))),
-        Err(err) => Box::pin(discord_refs.send_message_reply(format!("```\n{}```", err)))
+/// Handle all REMOVE commands. Characters can be removed entirely, or just some of their stats.
+fn handle_remove_command<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'message>, omnidata: &mut Omnidata, author: Author, arguments: &str) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
+    let command = match parse_generic_command(arguments) {
+        Ok(command) => command,
+        Err(_) => return Box::pin(discord_refs.send_message_reply("Failed to parse command. Remember the remove command should follow the verb-noun-target syntax. For more help, consult `!help remove`.")),
+    };
+    let result = match command.noun.as_str() {
+        "player" | "enemy" => remove_character(omnidata, &command.noun, &command.target, &author),
+        "stat" => edit_character(omnidata, &command.target, &author, |character| character.remove_stats(&command.extras)),
+        "immunity" | "weakness" | "resistance" => edit_character(omnidata, &command.target, &author, |character| character.remove_defense(&command.noun, &command.extras)),
+        "effect" => remove_effect(omnidata, &author, &command.target, &command.extras),
+        "condition" => edit_character(omnidata, &command.target, &author, |character| match command.extras.as_slice() {
+            [name] => character.remove_condition(name),
+            _ => Err(anyhow!("Give the condition to remove, like `!remove condition {} frightened`.", character.name)),
+        }),
+        unknown => Err(anyhow!("Sorry, I don't know how to remove a '{}'. For more help, consult `!help remove`.", unknown)),
+    };
+    reply(discord_refs, result)
+}
+
+/// Handle all SET commands, which change the value of something that already exists.
+fn handle_set_command<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'message>, omnidata: &mut Omnidata, author: Author, arguments: &str) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
+    let command = match parse_noun_target_stats_command(arguments) {
+        Ok(command) => command,
+        Err(error) => return Box::pin(discord_refs.send_message_reply(error.to_string())),
+    };
+    let stats = command.stats;
+    let result = match command.noun.as_str() {
+        "stat" => edit_character(omnidata, &command.target, &author, |character| character.set_stats(stats)),
+        "ability" => edit_character(omnidata, &command.target, &author, |character| character.set_abilities(stats)),
+        "proficiency" => edit_character(omnidata, &command.target, &author, |character| character.set_proficiencies(stats)),
+        unknown => Err(anyhow!("Sorry, I don't know how to set a '{}'. For more help, consult `!help set`.", unknown)),
+    };
+    reply(discord_refs, result)
+}
+
+/// Handle all RENAME commands. The new name comes after the target, or after the stat being renamed.
+fn handle_rename_command<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'message>, omnidata: &mut Omnidata, author: Author, arguments: &str) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
+    let command = match parse_generic_command(arguments) {
+        Ok(command) => command,
+        Err(_) => return Box::pin(discord_refs.send_message_reply("Failed to parse command. Remember the rename command should follow the verb-noun-target syntax, followed by the new name. For more help, consult `!help rename`.")),
+    };
+    let result = match (command.noun.as_str(), command.extras.as_slice()) {
+        ("player", [new_name]) | ("enemy", [new_name]) => rename_character(omnidata, &command.noun, &command.target, new_name, &author),
+        ("stat", [old_name, new_name]) => edit_character(omnidata, &command.target, &author, |character| character.rename_stat(old_name, new_name)),
+        ("player", _) | ("enemy", _) | ("stat", _) => Err(anyhow!("Couldn't tell what the new name should be. Try something like `!rename {} {} \"New Name\"`.", command.noun, command.target)),
+        (unknown, _) => Err(anyhow!("Sorry, I don't know how to rename a '{}'. For more help, consult `!help rename`.", unknown)),
+    };
+    reply(discord_refs, result)
+}
+
+/// Handle GIVE commands, which hand a character over to someone else, like `!give player Plunk @Bob`.
+fn give(omnidata: &mut Omnidata, author: &Author, arguments: &str) -> Result<String> {
+    let (noun, rest) = split_first_word(arguments.trim());
+    let (name, new_owner) = split_first_word(rest);
+    if name.is_empty() {
+        return Err(anyhow!("Give a character and who should get them, like `!give player Plunk @Bob`."));
+    }
+    let new_owner = gm::parse_user_mention(new_owner)
+        .ok_or_else(|| anyhow!("Mention who should get {}, like `!give {} {} @Bob`.", name.replace("\"", ""), noun, name))?;
+    give_character(omnidata, &noun.to_lowercase(), name, new_owner, author)
+}
+
+/// Handle SHOW commands, which reply with everything known about a character.
+/// The noun is optional here, so both `!show player Plunk` and `!show Plunk` work. Enemies with hidden stats are only described roughly.
+fn show_character(omnidata: &Omnidata, arguments: &str) -> Result<Redacted> {
+    let name = match parse_generic_command(arguments) {
+        Ok(command) if command.extras.is_empty() => command.target,
+        _ => arguments.trim().replace("\"", ""),
+    };
+    let character = omnidata.find_character(&name)
+        .ok_or_else(|| anyhow!("Couldn't find a character with '{}' for a name. Check your spelling.", name))?;
+    let effects = omnidata.describe_effects(character.id);
+    let with_effects = |description: String| match effects.is_empty() {
+        true => description,
+        false => format!("{}\n{}", description, effects),
+    };
+    Ok(Redacted::about(omnidata, character, with_effects(character.describe()), with_effects(character.describe_hidden())))
+}
+
+/// Put an effect on a character, like `!add effect Plunk Bless 1 minute from Frank`. Without a `from`,
+/// the effect comes from the author's character if they own exactly one, and otherwise from nobody in particular.
+fn add_effect(omnidata: &mut Omnidata, author: u64, command: GenericCommand) -> Result<String> {
+    let target = find_character_id(omnidata, &command.target)?;
+    let (name, mut rest) = command.extras.split_first()
+        .ok_or_else(|| anyhow!("Give the effect a name, like `!add effect {} Bless 1 minute`.", command.target))?;
+    let mut duration = Vec::new();
+    let mut modifiers = Vec::new();
+    let mut source = None;
+    while let Some(word) = rest.first() {
+        if is_signed_number(word) {
+            let (modifier, remaining) = EffectModifier::parse(rest)?;
+            modifiers.push(modifier);
+            rest = remaining;
+        } else if word.eq_ignore_ascii_case("from") {
+            // Names with spaces are quoted, so they're already a single word.
+            let name = rest.get(1).ok_or_else(|| anyhow!("Say who the effect is from, like `from Frank` or `from \"War Boss\"`."))?;
+            source = Some(find_character_id(omnidata, name)?);
+            rest = &rest[2..];
+        } else {
+            duration.push(word.clone());
+            rest = &rest[1..];
+        }
+    }
+    if source.is_none() {
+        let mut owned = omnidata.characters.iter().filter(|character| character.owner == author);
+        source = owned.next().filter(|_| owned.next().is_none()).map(|character| character.id);
+    }
+    let duration = Duration::parse(&duration)?;
+    Ok(omnidata.add_effect(Effect {
+        name: name.to_string(),
+        target,
+        source,
+        duration,
+        modifiers,
+    }))
+}
+
+/// Take an effect off a character, like `!remove effect Plunk Bless`.
+fn remove_effect(omnidata: &mut Omnidata, author: &Author, target: &str, extras: &[String]) -> Result<String> {
+    let target = find_character_id(omnidata, target)?;
+    match extras {
+        [name] => omnidata.remove_effect(target, name, author),
+        _ => Err(anyhow!("Give the name of the effect to remove, like `!remove effect Plunk Bless`.")),
+    }
+}
+
+/// Keep a sustained effect going for another turn, like `!sustain Plunk Light`.
+fn sustain_effect(omnidata: &mut Omnidata, author: &Author, arguments: &str) -> Result<String> {
+    let (target, name) = split_first_word(arguments.trim());
+    if target.is_empty() || name.is_empty() {
+        return Err(anyhow!("Give a character and the effect on them to sustain, like `!sustain Plunk Light`."));
+    }
+    let target = find_character_id(omnidata, target)?;
+    omnidata.sustain_effect(target, &name.replace("\"", ""), author)
+}
+
+/// Start an encounter, like `!start encounter` for everyone or `!start encounter Plunk Frank "War Boss"` for just some characters.
+fn start_encounter(omnidata: &mut Omnidata, arguments: &str) -> Result<String> {
+    let (noun, mut rest) = split_first_word(arguments.trim());
+    if !noun.eq_ignore_ascii_case("encounter") {
+        return Err(anyhow!("Only encounters can be started, with `!start encounter`."));
+    }
+    let mut participants = Vec::new();
+    while !rest.is_empty() {
+        let (name, remaining) = split_first_word(rest);
+        participants.push(find_character_id(omnidata, name)?);
+        rest = remaining;
+    }
+    if participants.is_empty() {
+        participants = omnidata.characters.iter().map(|character| character.id).collect();
+    }
+    omnidata.start_encounter(&participants)
+}
+
+fn end_encounter(omnidata: &mut Omnidata, arguments: &str) -> Result<String> {
+    if !arguments.trim().eq_ignore_ascii_case("encounter") {
+        return Err(anyhow!("Only encounters can be ended, with `!end encounter`."));
+    }
+    omnidata.end_encounter()
+}
+
+/// Set someone's initiative like `!init Plunk 17`, roll it with `!init Plunk`, or see the turn order with just `!init`.
+fn set_initiative(omnidata: &mut Omnidata, author: &Author, arguments: &str) -> Result<String> {
+    let (name, initiative) = split_first_word(arguments.trim());
+    if name.is_empty() {
+        return Ok(omnidata.describe_encounter());
+    }
+    let character = find_character_id(omnidata, name)?;
+    ensure_turn_allowed(omnidata, author, Some(character))?;
+    let initiative = match initiative {
+        "" => None,
+        number => Some(number.parse::<i64>().map_err(|_| anyhow!("'{}' isn't a number. Try something like `!init {} 17`.", number, name))?),
+    };
+    omnidata.set_initiative(character, initiative)
+}
+
+/// Move on to the next turn with `!next`, or let someone who is delaying step back in with `!next Plunk`.
+fn next_turn(omnidata: &mut Omnidata, author: &Author, arguments: &str) -> Result<String> {
+    match arguments.trim() {
+        "" => {
+            ensure_turn_allowed(omnidata, author, None)?;
+            omnidata.next_turn(None)
+        },
+        name => {
+            let returning = find_character_id(omnidata, name)?;
+            ensure_turn_allowed(omnidata, author, Some(returning))?;
+            omnidata.next_turn(Some(returning))
+        },
+    }
+}
+
+/// Delay the current turn, with `!delay` or `!delay Plunk`.
+fn delay_turn(omnidata: &mut Omnidata, author: &Author, arguments: &str) -> Result<String> {
+    match arguments.trim() {
+        "" => {
+            ensure_turn_allowed(omnidata, author, None)?;
+            omnidata.delay_turn(None)
+        },
+        name => {
+            let character = find_character_id(omnidata, name)?;
+            ensure_turn_allowed(omnidata, author, Some(character))?;
+            omnidata.delay_turn(Some(character))
+        },
+    }
+}
+
+/// Ready an action like `!ready Plunk Slurk comes through the door`, or clear it once it's used with `!ready Plunk`.
+fn ready_action(omnidata: &mut Omnidata, author: &Author, arguments: &str) -> Result<String> {
+    let (name, trigger) = split_first_word(arguments.trim());
+    if name.is_empty() {
+        return Err(anyhow!("Give who is readying and what they're waiting for, like `!ready Plunk Slurk comes through the door`."));
+    }
+    let character = find_character_id(omnidata, name)?;
+    ensure_turn_allowed(omnidata, author, Some(character))?;
+    omnidata.ready_action(character, Some(trigger.to_string()).filter(|trigger| !trigger.is_empty()))
+}
+
+/// Only a character's owner or a GM can change their place in the turn order. Without a character, it's whoever's turn it is.
+/// If there's nobody to check, the encounter itself will explain why nothing can happen.
+fn ensure_turn_allowed(omnidata: &Omnidata, author: &Author, character: Option<u64>) -> Result<()> {
+    match character.or_else(|| omnidata.active_combatant()).and_then(|id| omnidata.find_character_by_id(id)) {
+        Some(character) if character.owner != author.id && !author.is_gm =>
+            Err(anyhow!("Only the owner of {} or a GM can change their place in the turn order.", character.name)),
+        _ => Ok(()),
+    }
+}
+
+fn find_character_id(omnidata: &Omnidata, name: &str) -> Result<u64> {
+    omnidata.find_character(name)
+        .map(|character| character.id)
+        .ok_or_else(|| anyhow!("Couldn't find a character with '{}' for a name. Check your spelling.", name))
+}
+
+/// Make a change to a character that only its owner or a GM is allowed to make, marking the omnidata dirty if it worked.
+fn edit_character<F: FnOnce(&mut Character) -> Result<String>>(omnidata: &mut Omnidata, name: &str, author: &Author, edit: F) -> Result<String> {
+    let character = omnidata.find_character_mut(name)
+        .ok_or_else(|| anyhow!("Couldn't find a character with '{}' for a name. Check your spelling.", name))?;
+    character.ensure_editable_by(author)?;
+    let response = edit(character)?;
+    omnidata.dirty();
+    Ok(response)
+}
+
+/// Reply to the user with the response from a command, or with the reason it failed.
+fn reply<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'message>, result: Result<String>) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
+    match result {
+        Ok(response) => Box::pin(discord_refs.send_message_reply(response)),
+        Err(error) => Box::pin(discord_refs.send_message_reply(error.to_string())),
+    }
+}
+
+/// Like `reply`, but for replies that may have left out things only GMs should see. If the author is a GM, they're sent those privately.
+fn reply_redacted<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'message>, is_gm: bool, result: Result<Redacted>) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
+    match result {
+        Ok(Redacted { public, secret: Some(secret) }) if is_gm => Box::pin(async move {
+            discord_refs.send_message_reply(public).await?;
+            discord_refs.send_dm(secret).await
+        }),
+        Ok(redacted) => reply(discord_refs, Ok(redacted.public)),
+        Err(error) => reply(discord_refs, Err(error)),
+    }
+}
+
+/// Handle roll commands. Arguments parameter should contain what to roll, either dice or a character's stat, optionally followed by a DC.
+fn roll(omnidata: &Omnidata, author: u64, arguments: &str) -> Result<Redacted> {
+    let (arguments, dc) = split_dc(arguments)?;
+    if let Ok(command) = parse_generic_command(arguments) {
+        if command.noun == "stat" {
+            return roll_character_stat(omnidata, &command.target, &command.extras, dc);
+        }
+    }
+    roll_expression(omnidata, author, arguments, dc)
+}
+
+/// `!roll secret ...` is another way to write `!sroll ...`.
+fn secret_roll_command<'a>(command: &'a str, arguments: &'a str) -> (&'a str, &'a str) {
+    match split_first_word(arguments.trim()) {
+        (first_word, rest) if command == "roll" && first_word.eq_ignore_ascii_case("secret") => ("sroll", rest),
+        _ => (command, arguments),
+    }
+}
+
+/// Roll a check against a DC, like `!check Plunk athletics vs 18` or `!check 1d20+7 dc 20`.
+fn check(omnidata: &Omnidata, author: u64, arguments: &str) -> Result<Redacted> {
+    match split_dc(arguments)? {
+        (expression, Some(dc)) if !expression.is_empty() => roll_expression(omnidata, author, expression, Some(dc)),
+        _ => Err(anyhow!("Give what to roll and the DC to roll it against, like `!check Plunk athletics vs 18` or `!check 1d20+7 dc 20`.")),
+    }
+}
+
+/// Split a DC off the end of a roll, like `athletics vs 18` or `1d20+7 dc 20`. Rolls without one are left as they are.
+fn split_dc(arguments: &str) -> Result<(&str, Option<i64>)> {
+    match split_option(arguments, &["vs", "dc"]) {
+        (rest, Some(dc)) => Ok((rest, Some(dc.parse().map_err(|_| anyhow!("The DC has to be a number, like `vs 18`, not '{}'.", dc))?))),
+        (rest, None) => Ok((rest, None)),
+    }
+}
+
+/// Split one of the keywords and the word after it off the end of some arguments, like `crit 19`.
+fn split_option<'a>(arguments: &'a str, keywords: &[&str]) -> (&'a str, Option<&'a str>) {
+    let arguments = arguments.trim();
+    let (rest, value) = match arguments.rsplit_once(char::is_whitespace) {
+        Some(split) => split,
+        None => return (arguments, None),
+    };
+    let rest = rest.trim_end();
+    let (rest, keyword) = rest.rsplit_once(char::is_whitespace).unwrap_or(("", rest));
+    match keywords.iter().any(|expected| keyword.eq_ignore_ascii_case(expected)) {
+        true => (rest.trim_end(), Some(value)),
+        false => (arguments, None),
+    }
+}
+
+/// Split the damage off the end of `!odds`, which is everything after the word `damage`.
+fn split_damage_option(arguments: &str) -> (&str, Option<&str>) {
+    let arguments = arguments.trim();
+    let lowercase = arguments.to_ascii_lowercase();
+    let start = lowercase.match_indices("damage").map(|(start, _)| start).find(|start| {
+        lowercase[..*start].ends_with(char::is_whitespace) && lowercase[start + "damage".len()..].chars().next().is_none_or(char::is_whitespace)
+    });
+    match start {
+        Some(start) => (arguments[..start].trim_end(), Some(arguments[start + "damage".len()..].trim())),
+        None => (arguments, None),
+    }
+}
+
+/// Show a character's roll, followed by how it did against the DC if there is one. Everyone sees the degree of success,
+/// even for characters whose stats are hidden.
+fn describe_roll(omnidata: &Omnidata, character: &Character, rolled: formula::Rolled, dc: Option<i64>) -> Redacted {
+    let (mut full, mut total) = (rolled.describe(), rolled.describe_total());
+    if let Some(dc) = dc {
+        let degree = rolled.describe_degree(dc);
+        full.push_str(&format!("\n{}", degree));
+        total.push_str(&format!("\n{}", degree));
+    }
+    Redacted::about(omnidata, character, full, total)
+}
+
+/// Roll dice that may refer to a character's stats, like `!roll 1d20+STR`, `!roll perception` or `!roll Plunk athletics+2`.
+/// If the roll doesn't start with a character's name, any stats are looked up on the character owned by the author.
+fn roll_expression(omnidata: &Omnidata, author: u64, arguments: &str, dc: Option<i64>) -> Result<Redacted> {
+    let arguments = arguments.trim();
+    let (character, expression) = match find_roller(omnidata, author, arguments)? {
+        Some(roller) => roller,
+        None => {
+            let roll = dice::roll(arguments)?;
+            let mut response = format!("```\n{}```", roll.breakdown);
+            if let Some(dc) = dc {
+                response.push_str(&format!("\n{}", roll.describe_degree(dc)));
+            }
+            return Ok(Redacted::public(response));
+        },
+    };
+
+    let rolled = match character.find_stat(expression) {
+        Some(_) => character.roll_stat(expression, &omnidata.effects_on(character.id))?,
+        None => formula::Rolled {
+            title: format!("**{}**", character.name),
+            formula: expression.to_string(),
+            evaluation: formula::evaluate_roll(character, expression, None, &omnidata.effects_on(character.id))?,
+        },
+    };
+    Ok(describe_roll(omnidata, character, rolled, dc))
+}
+
+/// Work out which character is making a roll, and what they're rolling. Rolls of plain dice, without any stats, have nobody making them.
+fn find_roller<'a>(omnidata: &'a Omnidata, author: u64, arguments: &'a str) -> Result<Option<(&'a Character, &'a str)>> {
+    let (first_word, rest) = split_first_word(arguments);
+    match omnidata.find_character(first_word) {
+        Some(character) if rest.is_empty() => Err(anyhow!("What should {} roll? Try something like `!roll {} perception`.", character.name, first_word)),
+        Some(character) => Ok(Some((character, rest))),
+        None if !formula::has_references(arguments) => Ok(None),
+        None => Ok(Some((find_rolling_character(omnidata, author, arguments)?, arguments))),
+    }
+}
+
+/// Work out the odds of a roll instead of rolling it, like `!odds 2d6+4` or `!odds Plunk longsword vs 22 crit 19 damage 1d8+4`.
+/// Anything `!roll` can roll works, with the same bonuses and penalties. With a DC, the chance of each degree of success is shown,
+/// and damage is doubled on a critical success like it is for a Strike.
+fn odds(omnidata: &Omnidata, author: u64, arguments: &str) -> Result<Redacted> {
+    let usage = "Give what to roll, optionally followed by a DC, like `!odds 2d6+4`, `!odds Plunk athletics vs 18` or `!odds 1d20+12 vs 22 crit 19 damage 2d8+4`.";
+    let (arguments, damage) = split_damage_option(arguments);
+    let (arguments, crit) = split_option(arguments, &["crit"]);
+    let crit = crit.map(|crit| crit.parse().map_err(|_| anyhow!("The critical range has to be the lowest natural that crits, like `crit 19`, not '{}'.", crit)))
+        .transpose()?;
+    let (arguments, dc) = split_dc(arguments)?;
+    if arguments.is_empty() || damage == Some("") {
+        return Err(anyhow!(usage));
+    }
+    if dc.is_none() && (crit.is_some() || damage.is_some()) {
+        return Err(anyhow!("Critical hits and damage need a DC to hit, like `!odds 1d20+12 vs 22 damage 2d8+4`."));
+    }
+
+    let roller = find_roller(omnidata, author, arguments)?;
+    let (title, expression) = match roller {
+        None => (String::from("**Odds of**"), arguments.to_string()),
+        Some((character, expression)) => {
+            let (title, formula, stat_name) = match character.find_stat(expression) {
+                Some(stat) => (format!("**{}'s {}**", character.name, stat.display_name), stat.formula().unwrap_or(&stat.value).to_string(), Some(stat.display_name.as_str())),
+                None => (format!("**{}**", character.name), expression.to_string(), None),
+            };
+            let (resolved, modifiers) = formula::resolve_roll(character, &formula, stat_name, &omnidata.effects_on(character.id))?;
+            (title, formula::with_modifiers(&resolved, &modifiers))
+        },
+    };
+    let odds = dice::odds(&expression)?;
+    let mut response = format!("{} `{}`", title, odds.expression);
+    match dc {
+        None => response.push_str(&format!(": {}", odds.describe())),
+        Some(dc) => {
+            response.push_str(&format!(" against DC {}\n{}", dc, odds.describe_degrees(dc, crit)?));
+            if let Some(damage) = damage {
+                let damage = match roller {
+                    Some((character, _)) => formula::resolve(character, damage)?,
+                    None => damage.to_string(),
+                };
+                response.push_str(&format!("\n{}", odds.describe_damage(&dice::odds(&damage)?, dc, crit)?));
+            }
+        },
+    }
+    Ok(match roller {
+        Some((character, _)) => Redacted::about(omnidata, character, response, format!("The odds for **{}** are hidden.", character.name)),
+        None => Redacted::public(response),
+    })
+}
+
+/// Damage a character, like `!damage Slurk 10 fire` or `!damage Slurk 2d6+4 slashing, 1d6 precision`.
+/// Each comma separated part is rolled on its own, and then the whole lot goes through the character's immunities, weaknesses and resistances.
+fn damage_character(omnidata: &mut Omnidata, author: &Author, arguments: &str) -> Result<Redacted> {
+    let (name, damage) = split_first_word(arguments.trim());
+    if name.is_empty() || damage.is_empty() {
+        return Err(anyhow!("Give a character and an amount, like `!damage Plunk 12` or `!damage Slurk 2d6+4 fire`."));
+    }
+    let character = omnidata.find_character_mut(name)
+        .ok_or_else(|| anyhow!("Couldn't find a character with '{}' for a name. Check your spelling.", name))?;
+    character.ensure_editable_by(author)?;
+
+    let mut parts = Vec::new();
+    let mut rolls = Vec::new();
+    for part in damage.split(',') {
+        let (amount, words) = split_damage_part(part);
+        if amount.is_empty() {
+            return Err(anyhow!("'{}' needs an amount of damage, like `1d6 fire`.", part.trim()));
+        }
+        let roll = dice::roll(&amount)?;
+        if amount.parse::<i64>().is_err() {
+            rolls.push(roll.breakdown);
+        }
+        parts.push(DamagePart::new(roll.total.max(0), &words));
+    }
+
+    let adjusted = character.adjust_damage(parts);
+    let mut response = character.damage(adjusted.total)?;
+    // Immunities, weaknesses and resistances are hidden along with the rest of a character's stats.
+    for reason in adjusted.reasons {
+        response.push_detail(&format!("\n{}", reason));
+    }
+    if !rolls.is_empty() {
+        response.push_str(&format!("\n```\n{}```", rolls.join("\n")));
+    }
+    let response = Redacted::about(omnidata, omnidata.find_character(name).expect("Character went missing while taking damage!"), response.full, response.hidden);
+    omnidata.dirty();
+    Ok(response)
+}
+
+/// Split some damage like `2d6 + 4 fire splash` into the amount to roll and the words describing it.
+fn split_damage_part(part: &str) -> (String, Vec<&str>) {
+    let words: Vec<&str> = part.split_whitespace().collect();
+    let amount_length = words.iter().position(|word| word.chars().all(|c| c.is_ascii_alphabetic())).unwrap_or(words.len());
+    (words[..amount_length].concat(), words[amount_length..].to_vec())
+}
+
+/// Heal or give temporary HP to a character, like `!heal "War Boss" 2d8+4` or `!temphp Plunk 5`.
+fn change_hit_points(omnidata: &mut Omnidata, author: &Author, arguments: &str, change: fn(&mut Character, i64) -> Result<HitPointsReply>) -> Result<Redacted> {
+    let (name, amount) = split_first_word(arguments.trim());
+    if name.is_empty() || amount.is_empty() {
+        return Err(anyhow!("Give a character and an amount, like `!damage Plunk 12` or `!heal Plunk 2d8+4`."));
+    }
+    let character = omnidata.find_character_mut(name)
+        .ok_or_else(|| anyhow!("Couldn't find a character with '{}' for a name. Check your spelling.", name))?;
+    character.ensure_editable_by(author)?;
+    let roll = dice::roll(amount)?;
+    let mut response = change(character, roll.total)?;
+    if amount.parse::<i64>().is_err() {
+        response.push_str(&format!("\n```\n{}```", roll.breakdown));
+    }
+    let response = Redacted::about(omnidata, omnidata.find_character(name).expect("Character went missing while changing HP!"), response.full, response.hidden);
+    omnidata.dirty();
+    Ok(response)
+}
+
+/// Split off the first word, which may be quoted to include spaces.
+fn split_first_word(arguments: &str) -> (&str, &str) {
+    let end = match arguments.strip_prefix('"') {
+        Some(quoted) => quoted.find('"').map(|end| end + 2).unwrap_or(arguments.len()),
+        None => arguments.find(char::is_whitespace).unwrap_or(arguments.len()),
+    };
+    (&arguments[..end], arguments[end..].trim())
+}
+
+/// Pick which of the author's characters a roll is for. Characters that don't have every stat the roll needs are skipped,
+/// so someone with a player and a familiar can usually just `!roll athletics`.
+fn find_rolling_character<'a>(omnidata: &'a Omnidata, author: u64, expression: &str) -> Result<&'a Character> {
+    let owned: Vec<&Character> = omnidata.characters.iter().filter(|character| character.owner == author).collect();
+    if owned.is_empty() {
+        return Err(anyhow!("You don't own any characters to roll `{}` for. Add one with `!add player`, or name who's rolling, like `!roll Plunk {}`.", expression, expression));
+    }
+    let able: Vec<&Character> = owned.iter().copied().filter(|character| formula::resolve(character, expression).is_ok()).collect();
+    // In an encounter, whoever's turn it is gets to roll without saying who they are.
+    if let Some(active) = able.iter().find(|character| Some(character.id) == omnidata.active_combatant()) {
+        return Ok(active);
+    }
+    match able.as_slice() {
+        [character] => Ok(character),
+        // None of them can roll it, so let the single owned character (if there is one) explain what's missing.
+        [] if owned.len() == 1 => Ok(owned[0]),
+        [] => Err(anyhow!("None of your characters ({}) can roll `{}`. Check the stat names with `!show`.", names(&owned), expression)),
+        several => Err(anyhow!("You own {}, and any of them could roll that. Say which one, like `!roll {} {}`.", names(several), quote_name(&several[0].name), expression)),
+    }
+}
+
+fn names(characters: &[&Character]) -> String {
+    characters.iter().map(|character| character.name.as_str()).collect::<Vec<&str>>().join(", ")
+}
+
+/// Names with spaces need quotes to be typed back into a command.
+fn quote_name(name: &str) -> String {
+    if name.contains(' ') {
+        format!("\"{}\"", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Roll a stat on a character, for example `!roll stat Frank Reflex`.
+fn roll_character_stat(omnidata: &Omnidata, name: &str, stat_names: &[String], dc: Option<i64>) -> Result<Redacted> {
+    let character = omnidata.find_character(name)
+        .ok_or_else(|| anyhow!("Couldn't find a character with '{}' for a name. Check your spelling.", name))?;
+    match stat_names {
+        [stat_name] => {
+            let rolled = character.roll_stat(stat_name, &omnidata.effects_on(character.id))?;
+            Ok(describe_roll(omnidata, character, rolled, dc))
+        },
+        _ => Err(anyhow!("Which of {}'s stats should be rolled? Try something like `!roll stat {} Reflex`.", character.name, character.name)),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::omni::character::CharacterKind;
 
     #[test]
     fn new_omnidata() {
@@ -166,8 +908,304 @@ mod tests {
 
     #[test]
     fn dice_string() {
-        let roll = roll_inline("1d4", false).unwrap();
-        assert!(roll.string_result.contains("="));
+        let roll = dice::loaded(&[3], || dice::roll("1d4").unwrap());
+        assert_eq!(roll.breakdown, "1d4 = [3] = 3");
+        assert_eq!(roll.total, 3);
+    }
+
+    #[test]
+    fn loaded_rolls() {
+        let gm = Author { id: 0, is_gm: true };
+        let mut omnidata = Omnidata::new();
+        let mut frank = Character::new("Frank", CharacterKind::Player, 1);
+        frank.add_stats(crate::omni::character::parse_noun_target_stats_command("stat Frank DEX:3 HP:10/30 Perception:=1d20+DEX").unwrap().stats).unwrap();
+        omnidata.insert_character(frank);
+        let mut mittens = Character::new("Mittens", CharacterKind::Player, 2);
+        mittens.add_stats(crate::omni::character::parse_noun_target_stats_command("stat Mittens DEX:1 Perception:=1d20+DEX").unwrap().stats).unwrap();
+        omnidata.insert_character(mittens);
+
+        // Checks, with a natural 20 making a failure a success.
+        let rolled = dice::loaded(&[20], || check(&omnidata, 1, "Frank perception vs 30")).unwrap().public;
+        assert_eq!(rolled, "**Frank's Perception** `=1d20+DEX`\n```\n1d20+3 = [20] + 3 = 23```\n**Success** against DC 30 (natural 20)");
+        let rolled = dice::loaded(&[12], || roll(&omnidata, 1, "1d20+7 dc 20")).unwrap().public;
+        assert_eq!(rolled, "```\n1d20+7 = [12] + 7 = 19```\n**Failure** against DC 20");
+
+        // Conditions add their penalties to the roll.
+        omnidata.find_character_mut("frank").unwrap().add_condition("frightened", Some("2")).unwrap();
+        let rolled = dice::loaded(&[10], || roll_expression(&omnidata, 1, "Frank perception", Some(12))).unwrap().public;
+        assert_eq!(rolled, "**Frank's Perception** `=1d20+DEX`\n```\n1d20+3-2 = [10] + 3 - 2 = 11\nfrightened 2: -2 status```\n**Failure** against DC 12");
+
+        // Damage and healing.
+        let response = dice::loaded(&[4, 6], || damage_character(&mut omnidata, &gm, "Frank 2d6+1 fire")).unwrap().public;
+        assert!(response.starts_with("Frank takes 11 damage."), "{}", response);
+        let response = dice::loaded(&[3], || change_hit_points(&mut omnidata, &gm, "Frank 1d8+2", Character::heal)).unwrap().public;
+        assert!(response.contains("HP: 5/30"), "{}", response);
+
+        // Initiative is rolled in the order the characters were added, and sorted highest first. Frank is still frightened.
+        dice::loaded(&[5, 17], || start_encounter(&mut omnidata, "encounter")).unwrap();
+        assert_eq!(set_initiative(&mut omnidata, &gm, "").unwrap(), "**Initiative**\n   18 Mittens\n   6 Frank");
+    }
+
+    #[test]
+    fn roll_stats() {
+        let mut omnidata = Omnidata::new();
+        let mut frank = Character::new("Frank", CharacterKind::Player, 1);
+        frank.add_stats(crate::omni::character::parse_noun_target_stats_command("stat Frank DEX:3 Reflex:=10+DEX").unwrap().stats).unwrap();
+        omnidata.characters.push(frank);
+        assert_eq!(roll_character_stat(&omnidata, "frank", &[String::from("reflex")], None).unwrap().public, "**Frank's Reflex** `=10+DEX`\n```\n10+3 = 10 + 3 = 13```");
+        assert!(roll_character_stat(&omnidata, "frank", &[], None).is_err());
+        assert!(roll_character_stat(&omnidata, "bob", &[String::from("reflex")], None).is_err());
+    }
+
+    #[test]
+    fn roll_expressions() {
+        let mut omnidata = Omnidata::new();
+        let mut frank = Character::new("Frank", CharacterKind::Player, 1);
+        frank.add_stats(crate::omni::character::parse_noun_target_stats_command("stat Frank DEX:3 Perception:=10+DEX").unwrap().stats).unwrap();
+        omnidata.characters.push(frank);
+        let mut boss = Character::new("War Boss", CharacterKind::Npc, 2);
+        boss.add_stats(crate::omni::character::parse_noun_target_stats_command("stat Boss STR:5").unwrap().stats).unwrap();
+        omnidata.characters.push(boss);
+
+        assert_eq!(roll_expression(&omnidata, 1, "perception", None).unwrap().public, "**Frank's Perception** `=10+DEX`\n```\n10+3 = 10 + 3 = 13```");
+        assert_eq!(roll_expression(&omnidata, 1, "10+DEX", None).unwrap().public, "**Frank** `10+DEX`\n```\n10+3 = 10 + 3 = 13```");
+        assert_eq!(roll_expression(&omnidata, 1, "\"war boss\" 10+STR", None).unwrap().public, "**War Boss** `10+STR`\n```\n10+5 = 10 + 5 = 15```");
+        assert!(roll_expression(&omnidata, 1, "2+2", None).unwrap().public.contains("= 4"));
+        assert!(roll_expression(&omnidata, 1, "Frank", None).is_err());
+        assert!(roll_expression(&omnidata, 3, "perception", None).unwrap_err().to_string().contains("don't own any"));
+        assert!(roll_expression(&omnidata, 1, "WIS", None).unwrap_err().to_string().contains("doesn't have a stat named WIS"));
+
+        let mut familiar = Character::new("Mittens", CharacterKind::Player, 1);
+        familiar.add_stats(crate::omni::character::parse_noun_target_stats_command("stat Mittens DEX:1").unwrap().stats).unwrap();
+        omnidata.characters.push(familiar);
+        assert!(roll_expression(&omnidata, 1, "perception", None).unwrap().public.starts_with("**Frank's Perception**"));
+        let error = roll_expression(&omnidata, 1, "DEX", None).unwrap_err().to_string();
+        assert!(error.contains("Frank, Mittens") && error.contains("`!roll Frank DEX`"), "{}", error);
+        assert!(roll_expression(&omnidata, 1, "WIS", None).unwrap_err().to_string().contains("None of your characters"));
+
+        assert!(roll(&omnidata, 1, "perception vs 13").unwrap().public.ends_with("```\n**Success** against DC 13"));
+        assert!(roll(&omnidata, 1, "10+3 DC 24").unwrap().public.ends_with("```\n**Critical Failure** against DC 24"));
+        assert!(roll(&omnidata, 1, "stat Frank perception dc 3").unwrap().public.ends_with("**Critical Success** against DC 3"));
+        assert!(check(&omnidata, 1, "Frank perception vs 14").unwrap().public.ends_with("**Failure** against DC 14"));
+        assert!(check(&omnidata, 1, "perception").unwrap_err().to_string().contains("`!check Plunk athletics vs 18`"));
+        assert!(check(&omnidata, 1, "perception vs hard").unwrap_err().to_string().contains("has to be a number"));
+    }
+
+    #[test]
+    fn encounter_commands() {
+        let gm = Author { id: 0, is_gm: true };
+        let mut omnidata = Omnidata::new();
+        let mut frank = Character::new("Frank", CharacterKind::Player, 1);
+        frank.add_stats(crate::omni::character::parse_noun_target_stats_command("stat Frank DEX:3").unwrap().stats).unwrap();
+        omnidata.insert_character(frank);
+        let mut mittens = Character::new("Mittens", CharacterKind::Player, 1);
+        mittens.add_stats(crate::omni::character::parse_noun_target_stats_command("stat Mittens DEX:1").unwrap().stats).unwrap();
+        omnidata.insert_character(mittens);
+        omnidata.insert_character(Character::new("War Boss", CharacterKind::Npc, 2));
+
+        assert!(start_encounter(&mut omnidata, "fight").is_err());
+        assert!(start_encounter(&mut omnidata, "encounter Frank Nobody").is_err());
+        start_encounter(&mut omnidata, "encounter Frank \"war boss\"").unwrap();
+        assert!(set_initiative(&mut omnidata, &gm, "Frank lots").is_err());
+        set_initiative(&mut omnidata, &gm, "Frank 12").unwrap();
+        set_initiative(&mut omnidata, &gm, "\"War Boss\" 18").unwrap();
+        // Mittens joins partway, just by having an initiative.
+        set_initiative(&mut omnidata, &gm, "Mittens 15").unwrap();
+        assert_eq!(set_initiative(&mut omnidata, &gm, "").unwrap(), "**Initiative**\n   18 War Boss\n   15 Mittens\n   12 Frank");
+
+        assert_eq!(next_turn(&mut omnidata, &gm, "").unwrap(), "**Round 1**\nIt's War Boss's turn.");
+        // Players only get to move their own characters along.
+        let player = Author { id: 1, is_gm: false };
+        assert!(next_turn(&mut omnidata, &player, "").unwrap_err().to_string().contains("Only the owner of War Boss or a GM"));
+        assert!(delay_turn(&mut omnidata, &player, "").is_err());
+        assert!(set_initiative(&mut omnidata, &player, "\"War Boss\" 30").is_err());
+        assert!(roll_expression(&omnidata, 1, "DEX", None).unwrap_err().to_string().contains("Frank, Mittens"));
+        next_turn(&mut omnidata, &gm, "").unwrap();
+        assert!(roll_expression(&omnidata, 1, "DEX", None).unwrap().public.starts_with("**Mittens's DEX**"));
+        assert!(ready_action(&mut omnidata, &gm, "Mittens").is_err());
+        ready_action(&mut omnidata, &player, "Mittens the boss moves").unwrap();
+        assert!(delay_turn(&mut omnidata, &gm, "Frank").is_err());
+        assert_eq!(delay_turn(&mut omnidata, &gm, "").unwrap(), "Mittens is delaying. Bring them back in with `!next Mittens`.\nIt's Frank's turn.");
+        assert!(roll_expression(&omnidata, 1, "DEX", None).unwrap().public.starts_with("**Frank's DEX**"));
+        assert!(next_turn(&mut omnidata, &gm, "Frank").is_err());
+        assert!(end_encounter(&mut omnidata, "").is_err());
+        assert_eq!(end_encounter(&mut omnidata, "encounter").unwrap(), "The encounter is over after 1 round.");
+    }
+
+    #[test]
+    fn hit_points() {
+        let gm = Author { id: 0, is_gm: true };
+        let mut omnidata = Omnidata::new();
+        let mut boss = Character::new("War Boss", CharacterKind::Npc, 2);
+        boss.add_stats(crate::omni::character::parse_noun_target_stats_command("stat Boss HP:40/40").unwrap().stats).unwrap();
+        omnidata.characters.push(boss);
+
+        assert_eq!(damage_character(&mut omnidata, &gm, "\"war boss\" 12").unwrap().public, "War Boss takes 12 damage. HP: 28/40");
+        assert!(omnidata.is_dirty);
+        let response = change_hit_points(&mut omnidata, &gm, "\"War Boss\" 1d4+4", Character::heal).unwrap().public;
+        assert!(response.contains("```"), "{}", response);
+        assert!(damage_character(&mut omnidata, &gm, "Slurk 5").is_err());
+        assert!(damage_character(&mut omnidata, &gm, "\"War Boss\"").is_err());
+        assert!(change_hit_points(&mut omnidata, &gm, "\"War Boss\"", Character::heal).is_err());
+        let player = Author { id: 1, is_gm: false };
+        assert!(damage_character(&mut omnidata, &player, "\"War Boss\" 5").unwrap_err().to_string().contains("Only the owner of War Boss or a GM"));
+        assert!(change_hit_points(&mut omnidata, &player, "\"War Boss\" 5", Character::gain_temp_hp).is_err());
+        assert!(change_hit_points(&mut omnidata, &Author { id: 2, is_gm: false }, "\"War Boss\" 0", Character::heal).is_ok());
+
+        omnidata.find_character_mut("war boss").unwrap().add_defense("resistance", &[String::from("fire"), String::from("5")]).unwrap();
+        let response = damage_character(&mut omnidata, &gm, "\"War Boss\" 8 fire, 2 + 2 cold").unwrap().public;
+        assert!(response.starts_with("War Boss takes 7 damage."), "{}", response);
+        assert!(response.contains("resistance to fire 5"));
+        assert!(damage_character(&mut omnidata, &gm, "\"War Boss\" 8 fire, cold").is_err());
+
+        // Once there's a GM role, only GMs get to see exactly how hurt enemies are.
+        omnidata.gm_role = Some(42);
+        let response = damage_character(&mut omnidata, &gm, "\"War Boss\" 4").unwrap();
+        assert_eq!(response.public, "War Boss takes 4 damage. Health: Hurt");
+        assert!(response.secret.unwrap().contains("/40"));
+        let response = damage_character(&mut omnidata, &gm, "\"War Boss\" 6 fire").unwrap();
+        assert_eq!(response.public, "War Boss takes 1 damage. Health: Hurt");
+        assert!(response.secret.unwrap().contains("resistance to fire 5"));
+        let response = roll_expression(&omnidata, 1, "\"war boss\" 10+5", None).unwrap();
+        assert_eq!((response.public.as_str(), response.secret.is_some()), ("**War Boss**: 15", true));
+    }
+
+    #[test]
+    fn effect_commands() {
+        let mut omnidata = Omnidata::new();
+        omnidata.insert_character(Character::new("Plunk", CharacterKind::Player, 1));
+        omnidata.insert_character(Character::new("War Boss", CharacterKind::Npc, 2));
+
+        let response = add_effect(&mut omnidata, 2, parse_generic_command("effect Plunk Frightened 2 rounds").unwrap()).unwrap();
+        assert_eq!(response, "Plunk now has Frightened (2 rounds, from War Boss).");
+        let response = add_effect(&mut omnidata, 3, parse_generic_command("effect Plunk \"Inspire Courage\" until end of next turn from \"war boss\"").unwrap()).unwrap();
+        assert_eq!(response, "Plunk now has Inspire Courage (until end of next turn, from War Boss).");
+        let response = add_effect(&mut omnidata, 3, parse_generic_command("effect \"War Boss\" Light sustained").unwrap()).unwrap();
+        assert_eq!(response, "War Boss now has Light (sustained).");
+        assert_eq!(omnidata.effects[2].source, None);
+        assert!(add_effect(&mut omnidata, 1, parse_generic_command("effect Plunk Bless 3 days").unwrap()).is_err());
+        assert!(add_effect(&mut omnidata, 1, parse_generic_command("effect Plunk Bless from Nobody").unwrap()).is_err());
+        assert!(add_effect(&mut omnidata, 1, parse_generic_command("effect Plunk Bless 1 minute from").unwrap()).is_err());
+        let response = add_effect(&mut omnidata, 1, parse_generic_command("effect Plunk Heroism from \"War Boss\" 1 minute").unwrap()).unwrap();
+        assert_eq!(response, "Plunk now has Heroism (1 minute, from War Boss).");
+        omnidata.effects.pop();
+        let response = add_effect(&mut omnidata, 1, parse_generic_command("effect Plunk Bless 1 minute +1 status to attack from \"War Boss\"").unwrap()).unwrap();
+        assert_eq!(response, "Plunk now has Bless (1 minute, +1 status to attack, from War Boss).");
+        let response = add_effect(&mut omnidata, 1, parse_generic_command("effect Plunk Sickened from \"War Boss\" -1 status -1 circumstance to reflex").unwrap()).unwrap();
+        assert_eq!(response, "Plunk now has Sickened (until removed, -1 status to checks, -1 circumstance to reflex, from War Boss).");
+        omnidata.effects.truncate(3);
+
+        // Players can't take effects off characters they don't own, unless their character caused it.
+        let player = Author { id: 1, is_gm: false };
+        assert!(sustain_effect(&mut omnidata, &player, "\"War Boss\" light").is_err());
+        assert!(remove_effect(&mut omnidata, &player, "\"War Boss\"", &[String::from("light")]).unwrap_err().to_string().contains("Only a GM"));
+        assert!(sustain_effect(&mut omnidata, &Author { id: 2, is_gm: false }, "\"War Boss\" light").is_ok());
+        assert!(sustain_effect(&mut omnidata, &player, "Plunk Frightened").is_err());
+        assert_eq!(remove_effect(&mut omnidata, &player, "plunk", &[String::from("frightened")]).unwrap(), "Plunk no longer has Frightened.");
+        assert_eq!(omnidata.effects.len(), 2);
+    }
+
+    #[test]
+    fn damage_parts() {
+        assert_eq!(split_damage_part(" 2d6 + 4 fire splash"), (String::from("2d6+4"), vec!["fire", "splash"]));
+        assert_eq!(split_damage_part("12"), (String::from("12"), vec![]));
+    }
+
+    #[test]
+    fn secret_roll_commands() {
+        assert_eq!(secret_roll_command("roll", " secret Slurk stealth"), ("sroll", "Slurk stealth"));
+        assert_eq!(secret_roll_command("roll", "Secret recall_knowledge dc 15"), ("sroll", "recall_knowledge dc 15"));
+        assert_eq!(secret_roll_command("roll", "Plunk secret"), ("roll", "Plunk secret"));
+        assert_eq!(secret_roll_command("sroll", "Slurk stealth"), ("sroll", "Slurk stealth"));
+        assert_eq!(secret_roll_command("show", "secret"), ("show", "secret"));
+    }
+
+    #[test]
+    fn dcs() {
+        assert_eq!(split_dc("athletics vs 18").unwrap(), ("athletics", Some(18)));
+        assert_eq!(split_dc(" \"War Boss\" 1d20+7  DC 20 ").unwrap(), ("\"War Boss\" 1d20+7", Some(20)));
+        assert_eq!(split_dc("vs 18").unwrap(), ("", Some(18)));
+        assert_eq!(split_dc("Plunk dc").unwrap(), ("Plunk dc", None));
+        assert_eq!(split_dc("1d20+7").unwrap(), ("1d20+7", None));
+        assert!(split_dc("athletics vs DC").is_err());
+    }
+
+    #[test]
+    fn odds_command() {
+        let mut omnidata = Omnidata::new();
+        let mut frank = Character::new("Frank", CharacterKind::Player, 1);
+        frank.add_stats(crate::omni::character::parse_noun_target_stats_command("stat Frank DEX:3 Perception:=1d20+DEX").unwrap().stats).unwrap();
+        frank.add_condition("frightened", Some("2")).unwrap();
+        omnidata.characters.push(frank);
+        let mut boss = Character::new("War Boss", CharacterKind::Npc, 2);
+        boss.add_stats(crate::omni::character::parse_noun_target_stats_command("stat Boss Athletics:=1d20+9").unwrap().stats).unwrap();
+        omnidata.characters.push(boss);
+
+        assert!(odds(&omnidata, 3, "2d6").unwrap().public.starts_with("**Odds of** `2d6`: average 7, from 2 to 12\n```\n"));
+        assert_eq!(odds(&omnidata, 1, "perception vs 12").unwrap().public,
+            "**Frank's Perception** `1d20+3-2` against DC 12\n```\nCritical Success   5.0%\nSuccess           45.0%\nFailure           45.0%\nCritical Failure   5.0%\n```");
+        assert!(odds(&omnidata, 3, "1d20+10 vs 20 crit 19 damage 10").unwrap().public.ends_with(
+            "\nDamage `10` averages 10 on a hit and 20 on a critical hit, for 6.5 per attack."));
+        assert!(odds(&omnidata, 3, "1d20 crit 19").unwrap_err().to_string().contains("need a DC"));
+        assert!(odds(&omnidata, 3, "1d20 vs 10 damage").unwrap_err().to_string().contains("`!odds 2d6+4`"));
+        assert!(odds(&omnidata, 3, "").is_err());
+
+        // Enemies' odds give away their stats just like their rolls would.
+        omnidata.gm_role = Some(42);
+        let response = odds(&omnidata, 1, "\"war boss\" athletics vs 20").unwrap();
+        assert_eq!(response.public, "The odds for **War Boss** are hidden.");
+        assert!(response.secret.unwrap().starts_with("**War Boss's Athletics** `1d20+9` against DC 20"));
+
+        assert_eq!(split_damage_option("1d20+7 vs 20 Damage 2d6 + 4"), ("1d20+7 vs 20", Some("2d6 + 4")));
+        assert_eq!(split_damage_option("1d20+damage_bonus"), ("1d20+damage_bonus", None));
+        assert_eq!(split_option("1d20+7 vs 20 crit 19", &["crit"]), ("1d20+7 vs 20", Some("19")));
+    }
+
+    #[test]
+    fn first_word() {
+        assert_eq!(split_first_word("Plunk athletics+2"), ("Plunk", "athletics+2"));
+        assert_eq!(split_first_word("\"War Boss\" 1d20"), ("\"War Boss\"", "1d20"));
+        assert_eq!(split_first_word("perception"), ("perception", ""));
+    }
+
+    #[test]
+    fn find_characters() {
+        let mut omnidata = Omnidata::new();
+        omnidata.characters.push(Character::new("War Boss", CharacterKind::Npc, 1));
+        assert!(omnidata.find_character("\"war boss\"").is_some());
+        assert!(omnidata.find_character("War").is_none());
+        assert!(edit_character(&mut omnidata, "War Boss", &Author { id: 2, is_gm: false }, |_| Ok(String::new())).is_err());
+        assert!(!omnidata.is_dirty);
+        assert!(edit_character(&mut omnidata, "War Boss", &Author { id: 1, is_gm: false }, |_| Ok(String::new())).is_ok());
+        assert!(omnidata.is_dirty);
+        assert!(edit_character(&mut omnidata, "War Boss", &Author { id: 2, is_gm: true }, |_| Ok(String::new())).is_ok());
+    }
+
+    #[test]
+    fn give_characters() {
+        let mut omnidata = Omnidata::new();
+        omnidata.insert_character(Character::new("War Boss", CharacterKind::Npc, 1));
+        let author = Author { id: 1, is_gm: false };
+        assert!(give(&mut omnidata, &author, "enemy \"War Boss\"").unwrap_err().to_string().contains("`!give enemy \"War Boss\" @Bob`"));
+        assert!(give(&mut omnidata, &author, "player \"War Boss\" <@!5>").is_err());
+        assert_eq!(give(&mut omnidata, &author, "Enemy \"war boss\" <@!5>").unwrap(), "War Boss now belongs to <@5>.");
+        assert_eq!(omnidata.characters[0].owner, 5);
+        assert!(give(&mut omnidata, &author, "enemy \"War Boss\" <@1>").is_err());
+    }
+
+    #[test]
+    fn generic_parser() {
+        let command = parse_generic_command("stat \"War Boss\" HP AC").unwrap();
+        assert_eq!(command.noun, "stat");
+        assert_eq!(command.target, "War Boss");
+        assert_eq!(command.extras, vec!["HP", "AC"]);
+
+        // Names from sheets and conditions can have underscores and hyphens, and nothing is quietly left off the end.
+        assert_eq!(parse_generic_command("stat Plunk HP Hit_Points").unwrap().extras, vec!["HP", "Hit_Points"]);
+        assert_eq!(parse_generic_command("stat Tilly Sarenrae_Lore").unwrap().extras, vec!["Sarenrae_Lore"]);
+        assert_eq!(parse_generic_command("condition Plunk off-guard ").unwrap().extras, vec!["off-guard"]);
+        assert_eq!(parse_generic_command("effect Plunk \"Hunter's Prey\"").unwrap().extras, vec!["Hunter's Prey"]);
+        assert!(parse_generic_command("stat Plunk HP:30").is_err());
     }
 
     #[test]
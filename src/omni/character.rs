@@ -1,66 +1,320 @@
-mod effect;
+pub mod condition;
+pub mod defense;
+pub mod formula;
+mod health;
+pub use self::health::HitPointsReply;
+pub mod modifier;
+pub mod sheet;
 use crate::{discord::DiscordReferences};
 use futures::Future;
 use serde::{Deserialize, Serialize};
 use std::{pin::Pin, sync::Arc, u16, u64};
 use anyhow::{Result, anyhow};
 use pest::Parser;
-use self::effect::Effect;
+use self::condition::Condition;
+use self::defense::Defense;
+use self::sheet::Sheet;
 use self::stat::Stat;
 
 use super::Omnidata;
-mod stat;
+use super::effect::Effect;
+use super::gm::Author;
+pub mod stat;
 
 #[derive(Parser)]
 #[grammar = "character_commands.pest"]
 pub struct CharacterCommandParser;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum CharacterKind {
     Player,
     Npc
 }
 
-struct parsed_noun_target_stats_command {
-    noun: String,
-    target: String,
-    stats: Vec<Stat>,
+impl CharacterKind {
+    /// The noun users type to refer to this kind of character.
+    pub fn noun(&self) -> &'static str {
+        match self {
+            CharacterKind::Player => "player",
+            CharacterKind::Npc => "enemy",
+        }
+    }
+}
+
+pub(crate) struct parsed_noun_target_stats_command {
+    pub(crate) noun: String,
+    pub(crate) target: String,
+    pub(crate) stats: Vec<Stat>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Character {
+    /// Stays the same for as long as the character exists, even if they're renamed. Assigned by `Omnidata::insert_character`.
+    pub id: u64,
     pub kind: CharacterKind,
     pub name: String,
     pub owner: u64,  //TODO: May need to find a better type for this
-    pub effects: Vec<Effect>,
     pub stats: Vec<Stat>,
+    /// Temporary HP, which is lost before any real HP.
+    pub temp_hp: i64,
+    pub defenses: Vec<Defense>,
+    /// Only the conditions the character was given. See `active_conditions` for everything affecting them.
+    pub conditions: Vec<Condition>,
+    /// Their level, ability scores and proficiencies, if they have a PF2e sheet. The stats made from it are kept in `stats` too.
+    pub sheet: Option<Sheet>,
 }
 
 impl Character {
-    /// Adds a new stat to the character's data.
-    /// Returns a box containing a future to await.
-    pub fn add_stat<'a, 'message:'a>(&mut self, discord_refs: &'a DiscordReferences<'message>, arguments: &str) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
-        let parsed_command = match parse_noun_target_stats_command(arguments) {
-            Ok(parsed_command) => parsed_command,
-            Err(reason) => return Box::pin(discord_refs.send_message_reply(reason.to_string())),
-        };
-
-        println!("Found these stats to add: {:?}", parsed_command.stats);
-        for stat in parsed_command.stats {
-            println!("Adding {:?} to stats.", stat);
+    pub fn new(name: &str, kind: CharacterKind, owner: u64) -> Self {
+        Character {
+            id: 0,
+            name: name.to_string(),
+            kind,
+            owner,
+            stats: Vec::new(),
+            temp_hp: 0,
+            defenses: Vec::new(),
+            conditions: Vec::new(),
+            sheet: None,
+        }
+    }
+
+    /// Only the owner of a character, or a GM, is allowed to change it.
+    pub fn ensure_editable_by(&self, author: &Author) -> Result<()> {
+        if self.owner != author.id && !author.is_gm {
+            return Err(anyhow!("Only the owner of {} or a GM can change them.", self.name));
+        }
+        Ok(())
+    }
+
+    /// Find a stat by name, ignoring case.
+    pub fn find_stat(&self, name: &str) -> Option<&Stat> {
+        let name = name.to_lowercase();
+        self.stats.iter().find(|stat| stat.name() == name)
+    }
+
+    fn find_stat_mut(&mut self, name: &str) -> Option<&mut Stat> {
+        let name = name.to_lowercase();
+        self.stats.iter_mut().find(|stat| stat.name() == name)
+    }
+
+    /// Change the values of existing stats. A maximum or the tracker flag is only changed if the new stat includes one.
+    /// Either every stat is set or, if any of them don't exist, none are.
+    pub fn set_stats(&mut self, stats: Vec<Stat>) -> Result<String> {
+        if stats.is_empty() {
+            return Err(anyhow!("No stats given to set. Try something like `!set stat {} HP:20`.", self.name));
+        }
+        if let Some(missing) = stats.iter().find(|stat| self.find_stat(&stat.name()).is_none()) {
+            return Err(anyhow!("{} doesn't have a stat named {}. Use `!add stat` to add it.", self.name, missing.display_name));
+        }
+        for stat in stats.iter().filter(|stat| stat.name() != "level") {
+            self.ensure_not_derived(&stat.name())?;
+        }
+        let sets_level = stats.iter().any(|stat| stat.name() == "level");
+
+        // Later stats can refer to ones set earlier in the same command, so each is resolved and set in turn,
+        // and everything is put back if any of them fail.
+        let original = self.stats.clone();
+        let mut changed = Vec::new();
+        let mut rolls = Vec::new();
+        for mut new_stat in stats {
+            match self.resolve_static(&mut new_stat) {
+                Ok(resolved) => rolls.extend(resolved),
+                Err(error) => {
+                    self.stats = original;
+                    return Err(error);
+                }
+            }
+            let stat = self.find_stat_mut(&new_stat.name()).expect("Stat went missing after checking for it!");
+            stat.value = new_stat.value;
+            if new_stat.maximum_value.is_some() {
+                stat.maximum_value = new_stat.maximum_value;
+            }
+            if new_stat.display_on_tracker {
+                stat.display_on_tracker = true;
+            }
+            changed.push(stat.to_string());
+        }
+        if sets_level {
+            if let Err(error) = self.level_changed() {
+                self.stats = original;
+                return Err(error);
+            }
+        }
+        Ok(format!("Set {} for {}.{}", changed.join(", "), self.name, format_rolls(&rolls)))
+    }
+
+    /// Remove stats by name. Either every stat is removed or, if any of them don't exist, none are.
+    pub fn remove_stats(&mut self, names: &[String]) -> Result<String> {
+        if names.is_empty() {
+            return Err(anyhow!("No stats given to remove. Try something like `!remove stat {} HP`.", self.name));
+        }
+        if let Some(missing) = names.iter().find(|name| self.find_stat(name).is_none()) {
+            return Err(anyhow!("{} doesn't have a stat named {}.", self.name, missing));
+        }
+        for name in names {
+            self.ensure_not_derived(name)?;
+        }
+
+        let lowercase_names: Vec<String> = names.iter().map(|name| name.to_lowercase()).collect();
+        self.stats.retain(|stat| !lowercase_names.contains(&stat.name()));
+        Ok(format!("Removed {} from {}.", names.join(", "), self.name))
+    }
+
+    pub fn rename_stat(&mut self, old_name: &str, new_name: &str) -> Result<String> {
+        if self.find_stat(new_name).is_some() && old_name.to_lowercase() != new_name.to_lowercase() {
+            return Err(anyhow!("{} already has a stat named {}.", self.name, new_name));
+        }
+        self.ensure_not_derived(old_name)?;
+        let character_name = self.name.clone();
+        let stat = self.find_stat_mut(old_name).ok_or_else(|| anyhow!("{} doesn't have a stat named {}.", character_name, old_name))?;
+        let old_display_name = std::mem::replace(&mut stat.display_name, new_name.to_string());
+        Ok(format!("Renamed {}'s {} to {}.", self.name, old_display_name, new_name))
+    }
+
+    /// Roll one of the character's stats, resolving any references and dice in it, and describe how the result came about.
+    /// Any of the effects on them that give bonuses or penalties to the stat are added to the roll.
+    pub fn roll_stat(&self, name: &str, effects: &[&Effect]) -> Result<formula::Rolled> {
+        let stat = self.find_stat(name).ok_or_else(|| anyhow!("{} doesn't have a stat named {}.", self.name, name))?;
+        let evaluation = formula::evaluate_roll(self, stat.formula().unwrap_or(&stat.value), Some(&stat.display_name), effects)?;
+        Ok(formula::Rolled {
+            title: format!("**{}'s {}**", self.name, stat.display_name),
+            formula: stat.value.clone(),
+            evaluation,
+        })
+    }
+
+    /// A summary of the character and all of their stats, for showing to users.
+    pub fn describe(&self) -> String {
+        let mut description = format!("**{}** ({})", self.name, self.kind.noun());
+        if self.stats.is_empty() {
+            description.push_str("\nNo stats yet.");
+        }
+        for stat in self.stats.iter() {
+            description.push_str(&format!("\n{}", stat));
+        }
+        if self.temp_hp > 0 {
+            description.push_str(&format!("\nTemp HP: {}", self.temp_hp));
+        }
+        let proficiencies = self.sheet.as_ref().map(Sheet::describe).unwrap_or_default();
+        if !proficiencies.is_empty() {
+            description.push_str(&format!("\nProficiencies: {}", proficiencies));
+        }
+        let conditions = self.describe_conditions();
+        if !conditions.is_empty() {
+            description.push_str(&format!("\nConditions: {}", conditions));
+        }
+        let defenses = self.describe_defenses();
+        if !defenses.is_empty() {
+            description.push_str(&format!("\n{}", defenses));
+        }
+        description
+    }
+
+    /// A summary that doesn't give away any numbers, for showing players an enemy whose stats are hidden from them.
+    /// Their HP is only described roughly, and conditions are shown since they're plain to see.
+    pub fn describe_hidden(&self) -> String {
+        let mut description = format!("**{}** ({})", self.name, self.kind.noun());
+        if let Some(health) = self.describe_health() {
+            description.push_str(&format!("\n{}", health));
+        }
+        let conditions = self.describe_conditions();
+        if !conditions.is_empty() {
+            description.push_str(&format!("\nConditions: {}", conditions));
+        }
+        description
+    }
+
+    /// Adds new stats to the character's data. Stats the character already has can't be added again,
+    /// since they'd be ambiguous; they should be changed with `!set` instead.
+    pub fn add_stats(&mut self, stats: Vec<Stat>) -> Result<String> {
+        if stats.is_empty() {
+            return Err(anyhow!("No stats given to add. Try something like `!add stat {} HP:30/30`.", self.name));
+        }
+        self.ensure_new_stats(&stats)?;
+
+        let original_count = self.stats.len();
+        let rolls = self.insert_stats(stats)?;
+        let added: Vec<String> = self.stats[original_count..].iter().map(|stat| stat.to_string()).collect();
+        Ok(format!("Added {} to {}.{}", added.join(", "), self.name, format_rolls(&rolls)))
+    }
+
+    /// Stats the character already has can't be added again, and neither can the same stat twice in one go.
+    fn ensure_new_stats(&self, stats: &[Stat]) -> Result<()> {
+        for (index, stat) in stats.iter().enumerate() {
+            if self.find_stat(&stat.name()).is_some() {
+                return Err(anyhow!("{} already has a stat named {}. Use `!set stat` to change it.", self.name, stat.display_name));
+            }
+            if stats[..index].iter().any(|earlier| earlier.name() == stat.name()) {
+                return Err(anyhow!("{} was given more than once.", stat.display_name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve and add each stat in turn, so later stats can refer to earlier ones.
+    /// If any of them can't be resolved, none are added. Returns the breakdown of anything that was rolled.
+    fn insert_stats(&mut self, stats: Vec<Stat>) -> Result<Vec<String>> {
+        let original_count = self.stats.len();
+        let mut rolls = Vec::new();
+        for mut stat in stats {
+            match self.resolve_static(&mut stat) {
+                Ok(resolved) => rolls.extend(resolved),
+                Err(error) => {
+                    self.stats.truncate(original_count);
+                    return Err(error);
+                }
+            }
             self.stats.push(stat);
         }
+        Ok(rolls)
+    }
 
-        Box::pin(discord_refs.send_message_reply(format!("Added new stat")))
+    /// Static stats given as dice or references are worked out once, right now, and only the result is kept.
+    /// Returns the breakdown of each value that had to be worked out.
+    fn resolve_static(&self, stat: &mut Stat) -> Result<Vec<String>> {
+        let mut rolls = Vec::new();
+        if stat.formula().is_some() {
+            return Ok(rolls);
+        }
+        if self.needs_resolving(&stat.value) {
+            let evaluation = formula::evaluate(self, &stat.value)?;
+            rolls.push(format!("{}: {}", stat.display_name, evaluation.roll.breakdown));
+            stat.value = evaluation.roll.total.to_string();
+        }
+        if let Some(maximum_value) = stat.maximum_value.clone().filter(|maximum_value| self.needs_resolving(maximum_value)) {
+            let evaluation = formula::evaluate(self, &maximum_value)?;
+            rolls.push(format!("{} maximum: {}", stat.display_name, evaluation.roll.breakdown));
+            stat.maximum_value = Some(evaluation.roll.total.to_string());
+        }
+        Ok(rolls)
+    }
+
+    /// Plain numbers and text are stored as they are. Anything with dice, math, or a reference to another stat needs working out.
+    /// A lone word is only treated as a reference if the character has a stat by that name, so values like `Class:Wizard` still work.
+    fn needs_resolving(&self, value: &str) -> bool {
+        if value.parse::<i64>().is_ok() || value.starts_with('"') {
+            return false;
+        }
+        let is_single_word = value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        !is_single_word || formula::is_dice(value) || self.find_stat(value).is_some()
     }
 }
 
+/// Show what was rolled while adding or setting stats, if anything.
+fn format_rolls(rolls: &[String]) -> String {
+    if rolls.is_empty() {
+        return String::new();
+    }
+    format!("\n```\n{}```", rolls.join("\n"))
+}
+
 /// Given some arguments, will parse the command and return the noun, target, and stats
 /// Should generally only be used for the longer verb+noun+target+props syntax
-fn parse_noun_target_stats_command(arguments: &str) -> Result<parsed_noun_target_stats_command> {
+pub(crate) fn parse_noun_target_stats_command(arguments: &str) -> Result<parsed_noun_target_stats_command> {
     let parsed = CharacterCommandParser::parse(Rule::add_character, arguments);
     if parsed.is_err() {
-        return Err(anyhow!("Failed to parse command. Remember the add command should follow the verb-noun-target syntax. For more help, consult `!help add`."));
+        return Err(anyhow!("Failed to parse command. Remember commands like this should follow the verb-noun-target syntax, followed by stats like `HP:30`. For more help, consult `!help stat`."));
     }
     let pairs = parsed.expect("Parse failed and wasn't caught!").next().unwrap().into_inner(); //Go into the command object
     
@@ -108,7 +362,7 @@ fn parse_noun_target_stats_command(arguments: &str) -> Result<parsed_noun_target
 
     Ok(parsed_noun_target_stats_command {
         noun: noun_pair.unwrap().as_str().to_string(),
-        target: target_pair.unwrap().as_str().to_string(),
+        target: target_pair.unwrap().as_str().replace("\"", ""),   // Commands can have quotes, but we don't want them in the output
         stats: stats,
     })
 
@@ -117,45 +371,114 @@ fn parse_noun_target_stats_command(arguments: &str) -> Result<parsed_noun_target
 /// Adds a new player or NPC to the server's data. Basically, anything that can have stats like HP is a character.
 /// Returns a box containing a future to await.
 pub fn add_character<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'message>, omnidata: &mut Omnidata, arguments: &str) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
-    let parsed = CharacterCommandParser::parse(Rule::add_character, arguments);
-    if parsed.is_err() {
-        return Box::pin(discord_refs.send_message_reply("Failed to parse command. Remember the add command should follow the verb-noun-target syntax. For more help, consult `!help add`."));
-    }
-    let mut pairs = parsed.expect("Parse failed and wasn't caught!").next().unwrap().into_inner(); //Go into the command object
-    
-    // First pair should be the noun.
-    let noun_pair = pairs.next().unwrap();
-    if noun_pair.as_rule() != Rule::noun {
-        return Box::pin(discord_refs.send_message_reply(format!("Failed to parse command. Expected a Noun, got something unknown")));
+    let parsed_command = match parse_noun_target_stats_command(arguments) {
+        Ok(parsed_command) => parsed_command,
+        Err(reason) => return Box::pin(discord_refs.send_message_reply(reason.to_string())),
+    };
+    match create_character(omnidata, parsed_command, discord_refs.msg.author.id.0) {
+        Ok(response) => Box::pin(discord_refs.send_message_reply(response)),
+        Err(reason) => Box::pin(discord_refs.send_message_reply(reason.to_string())),
     }
-    let kind = match noun_pair.as_str() {
+}
+
+/// Creates the character described by a parsed add command, along with any stats it was given.
+/// Character names have to be unique, otherwise there'd be no telling which one later commands meant.
+fn create_character(omnidata: &mut Omnidata, parsed_command: parsed_noun_target_stats_command, owner: u64) -> Result<String> {
+    let kind = match parsed_command.noun.as_str() {
         "player" => CharacterKind::Player,
         "enemy" => CharacterKind::Npc,
-        unknown => return Box::pin(discord_refs.send_message_reply(format!("Failed to parse command. Unknown noun of '{}'", unknown))),
+        unknown => return Err(anyhow!("Failed to parse command. Unknown noun of '{}'", unknown)),
     };
-    
-    // Second pair should be the character name.
-    let name_pair = pairs.next().unwrap();
-    if name_pair.as_rule() != Rule::target {
-        return Box::pin(discord_refs.send_message_reply(format!("Failed to parse command. Expected a Target, got something unknown")));
-    }
-    let name = name_pair.as_str();
-
-    // Everything else should be stats to add to the character. This will be done after the character is added.
-    // TODO: Parse and add props to new char
-
-    omnidata.characters.push(Character {
-        name: name.to_string(),
-        kind: kind,
-        owner: discord_refs.msg.author.id.0,
-        effects: Vec::new(),
-        stats: Vec::new(),
-    });
+    let name = parsed_command.target;
+    if let Some(existing) = omnidata.find_character(&name) {
+        return Err(anyhow!("There's already a {} named {}. Use `!rename` or pick a different name.", existing.kind.noun(), existing.name));
+    }
+
+    let mut character = Character::new(&name, kind, owner);
+    let response = if parsed_command.stats.is_empty() {
+        format!("Added new character named {}.", name)
+    } else {
+        character.ensure_new_stats(&parsed_command.stats)?;
+        let rolls = character.insert_stats(parsed_command.stats)?;
+        format!("Added new character named {} with {}.{}", name, character.stats.iter().map(|stat| stat.to_string()).collect::<Vec<String>>().join(", "), format_rolls(&rolls))
+    };
+    omnidata.insert_character(character);
+    omnidata.dirty();
+    Ok(response)
+}
+
+/// Removes a player or NPC from the server's data, along with all of their stats.
+pub fn remove_character(omnidata: &mut Omnidata, noun: &str, name: &str, author: &Author) -> Result<String> {
+    let index = omnidata.character_index(name).ok_or_else(|| anyhow!("Couldn't find a character with '{}' for a name. Check your spelling.", name))?;
+    let character = &omnidata.characters[index];
+    ensure_kind(character, noun)?;
+    character.ensure_editable_by(author)?;
+
+    let removed = omnidata.characters.remove(index);
+    omnidata.forget_character_effects(removed.id);
+    omnidata.forget_combatant(removed.id);
     omnidata.dirty();
+    Ok(format!("Removed {} {}.", removed.kind.noun(), removed.name))
+}
+
+/// Gives a player or NPC a new name, as long as no other character already has it.
+pub fn rename_character(omnidata: &mut Omnidata, noun: &str, name: &str, new_name: &str, author: &Author) -> Result<String> {
+    let index = omnidata.character_index(name).ok_or_else(|| anyhow!("Couldn't find a character with '{}' for a name. Check your spelling.", name))?;
+    if omnidata.character_index(new_name).filter(|&other| other != index).is_some() {
+        return Err(anyhow!("There's already a character named {}.", new_name));
+    }
+    let character = &mut omnidata.characters[index];
+    ensure_kind(character, noun)?;
+    character.ensure_editable_by(author)?;
 
-    Box::pin(discord_refs.send_message_reply(format!("Added new charcter named {}", name)))
+    let old_name = std::mem::replace(&mut character.name, new_name.to_string());
+    omnidata.dirty();
+    Ok(format!("Renamed {} to {}.", old_name, new_name))
 }
 
+/// Hands a player or NPC over to someone else, who becomes the only one besides GMs who can change them.
+pub fn give_character(omnidata: &mut Omnidata, noun: &str, name: &str, new_owner: u64, author: &Author) -> Result<String> {
+    let character = omnidata.find_character_mut(name).ok_or_else(|| anyhow!("Couldn't find a character with '{}' for a name. Check your spelling.", name))?;
+    ensure_kind(character, noun)?;
+    character.ensure_editable_by(author)?;
+
+    character.owner = new_owner;
+    let response = format!("{} now belongs to <@{}>.", character.name, new_owner);
+    omnidata.dirty();
+    Ok(response)
+}
+
+/// Lists the names of every character, or just the players or enemies if asked.
+pub fn list_characters(omnidata: &Omnidata, filter: &str) -> Result<String> {
+    let kinds = match filter.trim().to_lowercase().as_str() {
+        "" | "characters" => vec![CharacterKind::Player, CharacterKind::Npc],
+        "player" | "players" => vec![CharacterKind::Player],
+        "enemy" | "enemies" => vec![CharacterKind::Npc],
+        unknown => return Err(anyhow!("Sorry, I don't know how to list '{}'. Try `!list players` or `!list enemies`.", unknown)),
+    };
+
+    let mut response = String::new();
+    for kind in kinds {
+        let names: Vec<String> = omnidata.characters.iter()
+            .filter(|character| character.kind == kind)
+            .map(|character| match character.describe_conditions() {
+                conditions if conditions.is_empty() => character.name.clone(),
+                conditions => format!("{} ({})", character.name, conditions),
+            })
+            .collect();
+        let names = if names.is_empty() { String::from("None") } else { names.join(", ") };
+        response.push_str(&format!("**{}:** {}\n", match kind { CharacterKind::Player => "Players", CharacterKind::Npc => "Enemies" }, names));
+    }
+    Ok(response)
+}
+
+/// Make sure the noun the user typed matches the kind of character they're pointing at.
+fn ensure_kind(character: &Character, noun: &str) -> Result<()> {
+    if character.kind.noun() != noun {
+        return Err(anyhow!("{} is a {}, not a {}.", character.name, character.kind.noun(), noun));
+    }
+    Ok(())
+}
 
 #[cfg(test)]
 mod tests {
@@ -190,6 +513,124 @@ mod tests {
         assert_eq!(parsed.stats[0].display_name, "FP");
         assert_eq!(parsed.stats[0].value, "2");
         assert_eq!(parsed.stats[0].maximum_value, Some(String::from("3")));
+
+        // Formulas can divide, while a `/` after a plain value is still the maximum.
+        parsed = parse_noun_target_stats_command("stat Plunk Half:=Level/2 HP:20/30").unwrap();
+        assert_eq!(parsed.stats[0].value, "=Level/2");
+        assert_eq!(parsed.stats[0].maximum_value, None);
+        assert_eq!(parsed.stats[1].maximum_value, Some(String::from("30")));
+    }
+
+    /// Someone who isn't a GM.
+    fn player(id: u64) -> Author {
+        Author { id, is_gm: false }
+    }
+
+    fn plunk() -> Character {
+        let mut plunk = Character::new("Plunk", CharacterKind::Player, 1);
+        plunk.stats = parse_noun_target_stats_command("player Plunk !HP:30/30 AC:18").unwrap().stats;
+        plunk
+    }
+
+    #[test]
+    fn set_stats() {
+        let mut character = plunk();
+        let new_stats = parse_noun_target_stats_command("stat Plunk hp:20 AC:19/25").unwrap().stats;
+        assert_eq!(character.set_stats(new_stats).unwrap(), "Set HP: 20/30, AC: 19/25 for Plunk.");
+        assert_eq!(character.stats[0].value, "20");
+        assert!(character.stats[0].display_on_tracker);
+        assert_eq!(character.stats[1].maximum_value, Some(String::from("25")));
+
+        let missing = parse_noun_target_stats_command("stat Plunk HP:1 FP:1").unwrap().stats;
+        assert!(character.set_stats(missing).is_err());
+        assert_eq!(character.stats[0].value, "20");
+    }
+
+    #[test]
+    fn remove_and_rename_stats() {
+        let mut character = plunk();
+        assert!(character.remove_stats(&[String::from("hp"), String::from("FP")]).is_err());
+        assert_eq!(character.stats.len(), 2);
+        assert!(character.rename_stat("AC", "hp").is_err());
+        character.rename_stat("ac", "Armor").unwrap();
+        assert_eq!(character.find_stat("ARMOR").unwrap().value, "18");
+        character.remove_stats(&[String::from("hp")]).unwrap();
+        assert_eq!(character.stats.len(), 1);
+    }
+
+    #[test]
+    fn characters() {
+        let mut omnidata = Omnidata::new();
+        omnidata.characters.push(plunk());
+        omnidata.characters.push(Character::new("Slurk", CharacterKind::Npc, 2));
+
+        assert!(remove_character(&mut omnidata, "enemy", "Plunk", &player(1)).is_err());
+        assert!(remove_character(&mut omnidata, "player", "plunk", &player(2)).is_err());
+        assert!(rename_character(&mut omnidata, "player", "Plunk", "slurk", &player(1)).is_err());
+        rename_character(&mut omnidata, "player", "PLUNK", "Plonk", &player(1)).unwrap();
+        assert_eq!(list_characters(&omnidata, "").unwrap(), "**Players:** Plonk\n**Enemies:** Slurk\n");
+        assert!(list_characters(&omnidata, "stats").is_err());
+
+        // GMs can change characters they don't own, and characters can be given to someone else.
+        assert!(give_character(&mut omnidata, "player", "Plonk", 3, &player(2)).is_err());
+        assert_eq!(give_character(&mut omnidata, "player", "Plonk", 3, &player(1)).unwrap(), "Plonk now belongs to <@3>.");
+        assert!(rename_character(&mut omnidata, "player", "Plonk", "Plunk", &player(1)).is_err());
+        rename_character(&mut omnidata, "player", "Plonk", "Plunk", &Author { id: 1, is_gm: true }).unwrap();
+        remove_character(&mut omnidata, "enemy", "Slurk", &player(2)).unwrap();
+        assert_eq!(list_characters(&omnidata, "enemies").unwrap(), "**Enemies:** None\n");
+        assert!(omnidata.is_dirty);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn add_stats() {
+        let mut character = plunk();
+        assert!(character.add_stats(parse_noun_target_stats_command("stat Plunk hp:5").unwrap().stats).is_err());
+        assert!(character.add_stats(parse_noun_target_stats_command("stat Plunk FP:1 fp:2").unwrap().stats).is_err());
+        assert_eq!(character.stats.len(), 2);
+        assert_eq!(character.add_stats(parse_noun_target_stats_command("stat Plunk FP:1/2").unwrap().stats).unwrap(), "Added FP: 1/2 to Plunk.");
+        assert_eq!(character.stats.len(), 3);
+    }
+
+    #[test]
+    fn resolve_static_stats() {
+        let mut character = plunk();
+        let response = character.add_stats(parse_noun_target_stats_command("stat Plunk STR:4 Athletics:STR+3 Class:Wizard Reflex:=1d20+STR").unwrap().stats).unwrap();
+        assert!(response.starts_with("Added STR: 4, Athletics: 7, Class: Wizard, Reflex: =1d20+STR to Plunk.\n```\nAthletics: "), "{}", response);
+        assert_eq!(character.find_stat("athletics").unwrap().value, "7");
+
+        assert!(character.add_stats(parse_noun_target_stats_command("stat Plunk Level:1 Bad:WIS+1").unwrap().stats).is_err());
+        assert!(character.find_stat("level").is_none());
+
+        character.set_stats(parse_noun_target_stats_command("stat Plunk HP:HP-5/AC+20 STR:STR+1 Athletics:STR+3").unwrap().stats).unwrap();
+        assert_eq!(character.find_stat("hp").unwrap().to_string(), "HP: 25/38");
+        assert_eq!(character.find_stat("athletics").unwrap().value, "8");
+        assert!(character.set_stats(parse_noun_target_stats_command("stat Plunk STR:1 AC:Class+1").unwrap().stats).is_err());
+        assert_eq!(character.find_stat("str").unwrap().value, "5");
+
+        character.set_stats(parse_noun_target_stats_command("stat Plunk AC:1d4").unwrap().stats).unwrap();
+        let ac = character.find_stat("ac").unwrap().value.parse::<i64>().unwrap();
+        assert!((1..=4).contains(&ac));
+    }
+
+    #[test]
+    fn create_characters() {
+        let mut omnidata = Omnidata::new();
+        let response = create_character(&mut omnidata, parse_noun_target_stats_command("player Plunk HP:30/30 AC:18").unwrap(), 1).unwrap();
+        assert_eq!(response, "Added new character named Plunk with HP: 30/30, AC: 18.");
+        assert_eq!(omnidata.characters[0].find_stat("ac").unwrap().value, "18");
+        assert_eq!(omnidata.characters[0].owner, 1);
+        assert!(omnidata.is_dirty);
+
+        assert!(create_character(&mut omnidata, parse_noun_target_stats_command("enemy plunk").unwrap(), 2).is_err());
+        assert!(create_character(&mut omnidata, parse_noun_target_stats_command("enemy Slurk HP:1 HP:2").unwrap(), 2).is_err());
+        assert!(create_character(&mut omnidata, parse_noun_target_stats_command("stat Slurk").unwrap(), 2).is_err());
+        create_character(&mut omnidata, parse_noun_target_stats_command("enemy \"War Boss\"").unwrap(), 2).unwrap();
+        assert_eq!(omnidata.characters.len(), 2);
+        assert_eq!(omnidata.characters[1].name, "War Boss");
+    }
+
+    #[test]
+    fn describe() {
+        assert_eq!(plunk().describe(), "**Plunk** (player)\nHP: 30/30\nAC: 18");
+    }
+}
@@ -0,0 +1,394 @@
+//! Pathfinder's conditions, like frightened 2 or prone. Some have a value, and some imply others while they last,
+//! such as dying making a character unconscious. Only the conditions a character was given are stored;
+//! the ones they imply are worked out whenever they're needed, so they go away along with whatever caused them.
+
+use std::fmt;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use super::Character;
+use super::modifier::{Modifier, ModifierKind};
+
+const DEX_BASED: [&str; 7] = ["reflex", "ref", "acrobatics", "stealth", "thievery", "dex", "ranged"];
+const STR_BASED: [&str; 3] = ["athletics", "str", "melee"];
+const CON_BASED: [&str; 3] = ["fortitude", "fort", "con"];
+const MENTAL: [&str; 20] = ["will", "perception", "arcana", "crafting", "lore", "medicine", "nature", "occultism", "religion", "society",
+    "survival", "deception", "diplomacy", "intimidation", "performance", "int", "wis", "cha", "spell", "spells"];
+const SAVES: [&str; 5] = ["fortitude", "fort", "reflex", "ref", "will"];
+const UNCONSCIOUS: [&str; 3] = ["perception", "reflex", "ref"];
+const ATTACKS: [&str; 5] = ["attack", "strike", "melee", "ranged", "spell"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ConditionKind {
+    Blinded,
+    Clumsy,
+    Concealed,
+    Confused,
+    Controlled,
+    Dazzled,
+    Deafened,
+    Doomed,
+    Drained,
+    Dying,
+    Encumbered,
+    Enfeebled,
+    Fascinated,
+    Fatigued,
+    FlatFooted,
+    Fleeing,
+    Frightened,
+    Grabbed,
+    Immobilized,
+    Invisible,
+    Paralyzed,
+    Petrified,
+    Prone,
+    Quickened,
+    Restrained,
+    Sickened,
+    Slowed,
+    Stunned,
+    Stupefied,
+    Unconscious,
+    Wounded,
+}
+
+/// Which rolls a condition's penalty applies to.
+enum Scope {
+    /// Every check, meaning any roll of a d20.
+    Checks,
+    /// Checks for stats with any of these words in their name.
+    Stats(&'static [&'static str]),
+}
+
+const ALL: [ConditionKind; 31] = [
+    ConditionKind::Blinded, ConditionKind::Clumsy, ConditionKind::Concealed, ConditionKind::Confused, ConditionKind::Controlled,
+    ConditionKind::Dazzled, ConditionKind::Deafened, ConditionKind::Doomed, ConditionKind::Drained, ConditionKind::Dying,
+    ConditionKind::Encumbered, ConditionKind::Enfeebled, ConditionKind::Fascinated, ConditionKind::Fatigued, ConditionKind::FlatFooted,
+    ConditionKind::Fleeing, ConditionKind::Frightened, ConditionKind::Grabbed, ConditionKind::Immobilized, ConditionKind::Invisible,
+    ConditionKind::Paralyzed, ConditionKind::Petrified, ConditionKind::Prone, ConditionKind::Quickened, ConditionKind::Restrained,
+    ConditionKind::Sickened, ConditionKind::Slowed, ConditionKind::Stunned, ConditionKind::Stupefied, ConditionKind::Unconscious,
+    ConditionKind::Wounded,
+];
+
+impl ConditionKind {
+    /// Find a condition by name, ignoring case. Off-guard is the newer name for flat-footed.
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        match name.as_str() {
+            "off-guard" | "offguard" | "flatfooted" => Some(ConditionKind::FlatFooted),
+            _ => ALL.iter().copied().find(|kind| kind.name() == name),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConditionKind::Blinded => "blinded",
+            ConditionKind::Clumsy => "clumsy",
+            ConditionKind::Concealed => "concealed",
+            ConditionKind::Confused => "confused",
+            ConditionKind::Controlled => "controlled",
+            ConditionKind::Dazzled => "dazzled",
+            ConditionKind::Deafened => "deafened",
+            ConditionKind::Doomed => "doomed",
+            ConditionKind::Drained => "drained",
+            ConditionKind::Dying => "dying",
+            ConditionKind::Encumbered => "encumbered",
+            ConditionKind::Enfeebled => "enfeebled",
+            ConditionKind::Fascinated => "fascinated",
+            ConditionKind::Fatigued => "fatigued",
+            ConditionKind::FlatFooted => "flat-footed",
+            ConditionKind::Fleeing => "fleeing",
+            ConditionKind::Frightened => "frightened",
+            ConditionKind::Grabbed => "grabbed",
+            ConditionKind::Immobilized => "immobilized",
+            ConditionKind::Invisible => "invisible",
+            ConditionKind::Paralyzed => "paralyzed",
+            ConditionKind::Petrified => "petrified",
+            ConditionKind::Prone => "prone",
+            ConditionKind::Quickened => "quickened",
+            ConditionKind::Restrained => "restrained",
+            ConditionKind::Sickened => "sickened",
+            ConditionKind::Slowed => "slowed",
+            ConditionKind::Stunned => "stunned",
+            ConditionKind::Stupefied => "stupefied",
+            ConditionKind::Unconscious => "unconscious",
+            ConditionKind::Wounded => "wounded",
+        }
+    }
+
+    /// Valued conditions, like frightened 2, always have a value of at least 1.
+    pub fn is_valued(&self) -> bool {
+        matches!(self, ConditionKind::Clumsy | ConditionKind::Doomed | ConditionKind::Drained | ConditionKind::Dying
+            | ConditionKind::Enfeebled | ConditionKind::Frightened | ConditionKind::Sickened | ConditionKind::Slowed
+            | ConditionKind::Stunned | ConditionKind::Stupefied | ConditionKind::Wounded)
+    }
+
+    /// Other conditions a character has for as long as they have this one.
+    fn implies(&self) -> &'static [(ConditionKind, Option<u32>)] {
+        match self {
+            ConditionKind::Dying => &[(ConditionKind::Unconscious, None)],
+            ConditionKind::Unconscious => &[(ConditionKind::Blinded, None), (ConditionKind::FlatFooted, None)],
+            ConditionKind::Grabbed | ConditionKind::Restrained => &[(ConditionKind::FlatFooted, None), (ConditionKind::Immobilized, None)],
+            ConditionKind::Paralyzed | ConditionKind::Prone | ConditionKind::Confused => &[(ConditionKind::FlatFooted, None)],
+            ConditionKind::Encumbered => &[(ConditionKind::Clumsy, Some(1))],
+            _ => &[],
+        }
+    }
+
+    /// The penalty the condition gives to rolls, if any, and which rolls it applies to.
+    fn penalty(&self, value: i64) -> Option<(ModifierKind, i64, Scope)> {
+        match self {
+            ConditionKind::Frightened | ConditionKind::Sickened => Some((ModifierKind::Status, -value, Scope::Checks)),
+            ConditionKind::Clumsy => Some((ModifierKind::Status, -value, Scope::Stats(&DEX_BASED))),
+            ConditionKind::Enfeebled => Some((ModifierKind::Status, -value, Scope::Stats(&STR_BASED))),
+            ConditionKind::Drained => Some((ModifierKind::Status, -value, Scope::Stats(&CON_BASED))),
+            ConditionKind::Stupefied => Some((ModifierKind::Status, -value, Scope::Stats(&MENTAL))),
+            ConditionKind::Fatigued => Some((ModifierKind::Status, -1, Scope::Stats(&SAVES))),
+            ConditionKind::Unconscious => Some((ModifierKind::Status, -4, Scope::Stats(&UNCONSCIOUS))),
+            ConditionKind::Prone => Some((ModifierKind::Circumstance, -2, Scope::Stats(&ATTACKS))),
+            _ => None,
+        }
+    }
+}
+
+/// A condition on a character. Only valued conditions have a value.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub kind: ConditionKind,
+    pub value: Option<u32>,
+}
+
+/// Formats the condition for showing to users, for example `frightened 2`.
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind.name())?;
+        if let Some(value) = self.value {
+            write!(f, " {}", value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Character {
+    /// Give the character a condition, like `!add condition Plunk frightened 2`. Valued conditions default to 1,
+    /// and if the character already has the condition they keep whichever value is higher.
+    pub fn add_condition(&mut self, name: &str, value: Option<&str>) -> Result<String> {
+        let kind = ConditionKind::parse(name)
+            .ok_or_else(|| anyhow!("'{}' isn't a condition I know. Check `!help condition` for the list.", name))?;
+        let value = match (kind.is_valued(), value) {
+            (true, None) => 1,
+            (true, Some(value)) => value.parse::<u32>().ok().filter(|&value| value > 0)
+                .ok_or_else(|| anyhow!("'{}' isn't a value for {}. It should be a whole number, like `{} 2`.", value, kind.name(), kind.name()))?,
+            (false, None) => 1,
+            (false, Some(_)) => return Err(anyhow!("{} doesn't have a value. Just use `!add condition {} {}`.", kind.name(), self.name, kind.name())),
+        };
+        let value = self.condition_value(kind).unwrap_or(0).max(value);
+        self.set_condition(kind, value);
+
+        let mut response = format!("{} is now {}.", self.name, self.find_condition(kind).expect("Condition went missing after adding it!"));
+        if kind == ConditionKind::Dying {
+            response.push_str(&self.check_for_death());
+        }
+        Ok(response)
+    }
+
+    pub fn remove_condition(&mut self, name: &str) -> Result<String> {
+        let kind = ConditionKind::parse(name)
+            .ok_or_else(|| anyhow!("'{}' isn't a condition I know. Check `!help condition` for the list.", name))?;
+        if self.find_condition(kind).is_none() {
+            let reason = match self.active_conditions().iter().find(|condition| condition.kind == kind) {
+                Some(_) => " of its own, only because of another of their conditions",
+                None => "",
+            };
+            return Err(anyhow!("{} isn't {}{}.", self.name, kind.name(), reason));
+        }
+        if kind == ConditionKind::Dying {
+            return Ok(self.recover_from_dying());
+        }
+        self.set_condition(kind, 0);
+        Ok(format!("{} is no longer {}.", self.name, kind.name()))
+    }
+
+    /// Stop dying, which always leaves the character a little more wounded than before.
+    pub(crate) fn recover_from_dying(&mut self) -> String {
+        let wounded = self.condition_value(ConditionKind::Wounded).unwrap_or(0) + 1;
+        self.set_condition(ConditionKind::Dying, 0);
+        self.set_condition(ConditionKind::Wounded, wounded);
+        format!("{} is no longer dying, but is now wounded {}.", self.name, wounded)
+    }
+
+    fn find_condition(&self, kind: ConditionKind) -> Option<&Condition> {
+        self.conditions.iter().find(|condition| condition.kind == kind)
+    }
+
+    /// The value of a condition the character was given, or 1 for conditions without values. None if they don't have it.
+    pub(crate) fn condition_value(&self, kind: ConditionKind) -> Option<u32> {
+        self.find_condition(kind).map(|condition| condition.value.unwrap_or(1))
+    }
+
+    /// Give the character a condition with exactly this value, replacing any they had. Setting a condition to 0 removes it,
+    /// and for conditions without values, anything else adds it.
+    pub(crate) fn set_condition(&mut self, kind: ConditionKind, value: u32) {
+        self.conditions.retain(|condition| condition.kind != kind);
+        if value == 0 {
+            return;
+        }
+        self.conditions.push(Condition {
+            kind,
+            value: Some(value).filter(|_| kind.is_valued()),
+        });
+    }
+
+    /// Every condition affecting the character, including those implied by others. Implied valued conditions only
+    /// show up if they're worse than what the character already has.
+    pub fn active_conditions(&self) -> Vec<Condition> {
+        let mut active: Vec<Condition> = self.conditions.clone();
+        let mut index = 0;
+        while index < active.len() {
+            for (kind, value) in active[index].kind.implies() {
+                match active.iter_mut().find(|condition| condition.kind == *kind) {
+                    Some(existing) if existing.value < *value => existing.value = *value,
+                    Some(_) => {},
+                    None => active.push(Condition { kind: *kind, value: *value }),
+                }
+            }
+            index += 1;
+        }
+        active
+    }
+
+    /// The character's conditions for showing to users, with implied ones in brackets. Empty if they have none.
+    pub fn describe_conditions(&self) -> String {
+        let active = self.active_conditions();
+        let given: Vec<String> = active.iter().filter(|condition| self.conditions.contains(condition)).map(|condition| condition.to_string()).collect();
+        let implied: Vec<String> = active.iter().filter(|condition| !self.conditions.contains(condition)).map(|condition| condition.to_string()).collect();
+        let mut description = given.join(", ");
+        if !implied.is_empty() {
+            description.push_str(&format!(" ({})", implied.join(", ")));
+        }
+        description
+    }
+
+    /// Penalties from the character's conditions that apply to a roll. The words are from the names of the stat being rolled
+    /// and any it refers to, and a roll is a check if it rolls a d20.
+    pub fn condition_modifiers(&self, words: &[String], is_check: bool) -> Vec<Modifier> {
+        self.active_conditions().iter().filter_map(|condition| {
+            let (kind, value, scope) = condition.kind.penalty(condition.value.unwrap_or(0) as i64)?;
+            let applies = match scope {
+                Scope::Checks => is_check,
+                Scope::Stats(keywords) => is_check && words.iter().any(|word| keywords.contains(&word.as_str())),
+            };
+            Some(Modifier { source: condition.to_string(), kind, value }).filter(|_| applies)
+        }).collect()
+    }
+
+    /// Conditions that wear off at the end of the character's turn. Returns what changed.
+    pub fn end_turn_conditions(&mut self) -> Vec<String> {
+        match self.condition_value(ConditionKind::Frightened) {
+            Some(frightened) => {
+                self.set_condition(ConditionKind::Frightened, frightened - 1);
+                match self.find_condition(ConditionKind::Frightened) {
+                    Some(condition) => vec![format!("{} is now {}.", self.name, condition)],
+                    None => vec![format!("{} is no longer frightened.", self.name)],
+                }
+            },
+            None => Vec::new(),
+        }
+    }
+
+    /// A character dies when their dying value reaches 4, or less if they're doomed.
+    pub(crate) fn check_for_death(&self) -> String {
+        let dying = self.condition_value(ConditionKind::Dying).unwrap_or(0);
+        let threshold = 4u32.saturating_sub(self.condition_value(ConditionKind::Doomed).unwrap_or(0));
+        if dying > 0 && dying >= threshold {
+            return format!("\n{} has reached dying {} and dies!", self.name, dying);
+        }
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::omni::character::CharacterKind;
+
+    fn plunk() -> Character {
+        Character::new("Plunk", CharacterKind::Player, 1)
+    }
+
+    #[test]
+    fn parse_names() {
+        assert_eq!(ConditionKind::parse("Frightened"), Some(ConditionKind::Frightened));
+        assert_eq!(ConditionKind::parse("off-guard"), Some(ConditionKind::FlatFooted));
+        assert_eq!(ConditionKind::parse("flat-footed"), Some(ConditionKind::FlatFooted));
+        assert_eq!(ConditionKind::parse("sleepy"), None);
+        for kind in ALL.iter() {
+            assert_eq!(ConditionKind::parse(kind.name()), Some(*kind));
+        }
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut plunk = plunk();
+        assert_eq!(plunk.add_condition("frightened", Some("2")).unwrap(), "Plunk is now frightened 2.");
+        assert_eq!(plunk.add_condition("Frightened", None).unwrap(), "Plunk is now frightened 2.");
+        assert_eq!(plunk.add_condition("prone", None).unwrap(), "Plunk is now prone.");
+        assert!(plunk.add_condition("prone", Some("2")).is_err());
+        assert!(plunk.add_condition("sickened", Some("0")).is_err());
+        assert!(plunk.add_condition("sleepy", None).is_err());
+        assert_eq!(plunk.describe_conditions(), "frightened 2, prone (flat-footed)");
+
+        assert!(plunk.remove_condition("flat-footed").unwrap_err().to_string().contains("only because of another"));
+        assert_eq!(plunk.remove_condition("prone").unwrap(), "Plunk is no longer prone.");
+        assert!(plunk.remove_condition("prone").is_err());
+    }
+
+    #[test]
+    fn implied_conditions() {
+        let mut plunk = plunk();
+        plunk.add_condition("dying", None).unwrap();
+        plunk.add_condition("encumbered", None).unwrap();
+        plunk.add_condition("clumsy", Some("2")).unwrap();
+        assert_eq!(plunk.describe_conditions(), "dying 1, encumbered, clumsy 2 (unconscious, blinded, flat-footed)");
+    }
+
+    #[test]
+    fn penalties() {
+        let mut plunk = plunk();
+        plunk.add_condition("frightened", Some("2")).unwrap();
+        plunk.add_condition("clumsy", Some("1")).unwrap();
+        let names = |names: &[&str]| crate::omni::character::modifier::stat_words(&names.iter().map(|name| name.to_string()).collect::<Vec<String>>());
+        assert_eq!(plunk.condition_modifiers(&names(&["Reflex"]), true).len(), 2);
+        assert_eq!(plunk.condition_modifiers(&names(&["Will"]), true), vec![Modifier { source: String::from("frightened 2"), kind: ModifierKind::Status, value: -2 }]);
+        assert!(plunk.condition_modifiers(&names(&["Reflex"]), false).is_empty());
+        plunk.add_condition("prone", None).unwrap();
+        assert_eq!(plunk.condition_modifiers(&names(&["Longsword Attack"]), true).len(), 2);
+    }
+
+    #[test]
+    fn frightened_wears_off() {
+        let mut plunk = plunk();
+        plunk.add_condition("frightened", Some("2")).unwrap();
+        assert_eq!(plunk.end_turn_conditions(), vec!["Plunk is now frightened 1."]);
+        assert_eq!(plunk.end_turn_conditions(), vec!["Plunk is no longer frightened."]);
+        assert!(plunk.end_turn_conditions().is_empty());
+    }
+
+    #[test]
+    fn recovering_leaves_them_wounded() {
+        let mut plunk = plunk();
+        plunk.add_condition("dying", Some("2")).unwrap();
+        assert_eq!(plunk.remove_condition("dying").unwrap(), "Plunk is no longer dying, but is now wounded 1.");
+        plunk.add_condition("dying", None).unwrap();
+        assert_eq!(plunk.remove_condition("Dying").unwrap(), "Plunk is no longer dying, but is now wounded 2.");
+        assert_eq!(plunk.describe_conditions(), "wounded 2");
+    }
+
+    #[test]
+    fn death() {
+        let mut plunk = plunk();
+        plunk.add_condition("doomed", Some("1")).unwrap();
+        assert_eq!(plunk.add_condition("dying", Some("2")).unwrap(), "Plunk is now dying 2.");
+        assert!(plunk.add_condition("dying", Some("3")).unwrap().ends_with("Plunk has reached dying 3 and dies!"));
+    }
+}
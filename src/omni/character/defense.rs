@@ -0,0 +1,297 @@
+//! Pathfinder damage always has a type, and creatures can be immune, weak, or resistant to particular types.
+//! Damage is given as one or more parts, each an amount and the words describing it, like `2d6+4 slashing, 1d6 precision`.
+//! A defense applies to a part if it names any of those words, the category a damage type belongs to (physical or energy),
+//! or `all`. The rules are applied in the book's order: immunities, then weaknesses, then resistances.
+
+use std::fmt;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use super::Character;
+
+const PHYSICAL: [&str; 3] = ["bludgeoning", "piercing", "slashing"];
+const ENERGY: [&str; 10] = ["acid", "cold", "electricity", "fire", "force", "sonic", "vitality", "void", "positive", "negative"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DefenseKind {
+    Immunity,
+    Weakness,
+    Resistance,
+}
+
+impl DefenseKind {
+    pub fn from_noun(noun: &str) -> Option<Self> {
+        match noun {
+            "immunity" => Some(DefenseKind::Immunity),
+            "weakness" => Some(DefenseKind::Weakness),
+            "resistance" => Some(DefenseKind::Resistance),
+            _ => None,
+        }
+    }
+
+    /// The noun users type to refer to this kind of defense.
+    pub fn noun(&self) -> &'static str {
+        match self {
+            DefenseKind::Immunity => "immunity",
+            DefenseKind::Weakness => "weakness",
+            DefenseKind::Resistance => "resistance",
+        }
+    }
+}
+
+/// An immunity, weakness or resistance to a type of damage. Immunities don't have a value.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Defense {
+    pub kind: DefenseKind,
+    pub damage_type: String,
+    pub value: i64,
+}
+
+impl Defense {
+    fn applies_to(&self, part: &DamagePart) -> bool {
+        self.damage_type == "all" || part.words.iter().any(|word| *word == self.damage_type || category(word) == Some(self.damage_type.as_str()))
+    }
+}
+
+/// Formats the defense for showing to users, for example `resistance to fire 5`.
+impl fmt::Display for Defense {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            DefenseKind::Immunity => write!(f, "immunity to {}", self.damage_type),
+            kind => write!(f, "{} to {} {}", kind.noun(), self.damage_type, self.value),
+        }
+    }
+}
+
+/// Damage types are grouped into categories, which resistances and the like can name instead of every type.
+fn category(damage_type: &str) -> Option<&'static str> {
+    if PHYSICAL.contains(&damage_type) {
+        Some("physical")
+    } else if ENERGY.contains(&damage_type) {
+        Some("energy")
+    } else {
+        None
+    }
+}
+
+/// One part of an instance of damage, like the `1d6 fire splash` in `2d6 slashing, 1d6 fire splash`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DamagePart {
+    pub amount: i64,
+    /// The damage type along with anything else that defenses might care about, like `precision` or `splash`. Untyped damage has none.
+    pub words: Vec<String>,
+}
+
+impl DamagePart {
+    pub fn new(amount: i64, words: &[&str]) -> Self {
+        DamagePart {
+            amount,
+            words: words.iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+
+    fn is_precision(&self) -> bool {
+        self.words.iter().any(|word| word == "precision")
+    }
+
+    fn describe(&self) -> String {
+        if self.words.is_empty() {
+            return String::from("untyped");
+        }
+        self.words.join(" ")
+    }
+}
+
+/// The damage left after applying a character's defenses, and what changed it.
+#[derive(Debug, PartialEq)]
+pub struct AdjustedDamage {
+    pub total: i64,
+    pub reasons: Vec<String>,
+}
+
+impl Character {
+    /// Add an immunity, weakness or resistance, like `!add resistance Slurk fire 5`. Adding one the character already has replaces its value.
+    pub fn add_defense(&mut self, noun: &str, arguments: &[String]) -> Result<String> {
+        let kind = DefenseKind::from_noun(noun).ok_or_else(|| anyhow!("'{}' isn't a kind of defense.", noun))?;
+        let (damage_type, value) = match (kind, arguments) {
+            (DefenseKind::Immunity, [damage_type]) => (damage_type, 0),
+            (DefenseKind::Immunity, _) => return Err(anyhow!("Give one type of damage, like `!add immunity {} poison`.", self.name)),
+            (kind, [damage_type, value]) => match value.parse::<i64>() {
+                Ok(value) if value > 0 => (damage_type, value),
+                _ => return Err(anyhow!("'{}' isn't a {} value. It should be a whole number, like `!add {} {} {} 5`.", value, kind.noun(), kind.noun(), self.name, damage_type)),
+            },
+            (kind, _) => return Err(anyhow!("Give a type of damage and a value, like `!add {} {} fire 5`.", kind.noun(), self.name)),
+        };
+        let defense = Defense {
+            kind,
+            damage_type: damage_type.to_lowercase(),
+            value,
+        };
+        let response = format!("{} now has {}.", self.name, defense);
+        self.defenses.retain(|existing| existing.kind != defense.kind || existing.damage_type != defense.damage_type);
+        self.defenses.push(defense);
+        Ok(response)
+    }
+
+    /// Remove an immunity, weakness or resistance, like `!remove resistance Slurk fire`.
+    pub fn remove_defense(&mut self, noun: &str, arguments: &[String]) -> Result<String> {
+        let kind = DefenseKind::from_noun(noun).ok_or_else(|| anyhow!("'{}' isn't a kind of defense.", noun))?;
+        let damage_type = match arguments {
+            [damage_type] => damage_type.to_lowercase(),
+            _ => return Err(anyhow!("Give the type of damage, like `!remove {} {} fire`.", kind.noun(), self.name)),
+        };
+        let index = self.defenses.iter().position(|defense| defense.kind == kind && defense.damage_type == damage_type)
+            .ok_or_else(|| anyhow!("{} doesn't have {} to {}.", self.name, kind.noun(), damage_type))?;
+        let removed = self.defenses.remove(index);
+        Ok(format!("{} no longer has {}.", self.name, removed))
+    }
+
+    /// Work out how much of some damage actually gets through the character's immunities, weaknesses and resistances.
+    pub fn adjust_damage(&self, parts: Vec<DamagePart>) -> AdjustedDamage {
+        let mut reasons = Vec::new();
+        let immune = |part: &DamagePart| self.defenses.iter()
+            .find(|defense| defense.kind == DefenseKind::Immunity && defense.applies_to(part))
+            .cloned();
+
+        // Precision damage is part of the attack it came with, so it takes on that attack's damage type.
+        // It's dropped entirely by anything immune to precision, even if it's weak to the attack's type.
+        let mut merged: Vec<DamagePart> = Vec::new();
+        for part in parts {
+            if !part.is_precision() {
+                merged.push(part);
+                continue;
+            }
+            if let Some(immunity) = immune(&part) {
+                reasons.push(format!("{} has {}, so {} precision damage is ignored.", self.name, immunity, part.amount));
+                continue;
+            }
+            match merged.iter_mut().find(|earlier| !earlier.is_precision()) {
+                Some(attack) => attack.amount += part.amount,
+                None => merged.push(part),
+            }
+        }
+
+        let mut parts = Vec::new();
+        for part in merged {
+            match immune(&part) {
+                Some(immunity) => reasons.push(format!("{} has {}, so {} {} damage is ignored.", self.name, immunity, part.amount, part.describe())),
+                None => parts.push(part),
+            }
+        }
+
+        // Only the single highest weakness applies, however many types of damage it could apply to.
+        let weakness = parts.iter().enumerate()
+            .filter(|(_, part)| part.amount > 0)
+            .flat_map(|(index, part)| self.defenses.iter()
+                .filter(move |defense| defense.kind == DefenseKind::Weakness && defense.applies_to(part))
+                .map(move |defense| (index, defense)))
+            .max_by_key(|(_, defense)| defense.value);
+        if let Some((index, weakness)) = weakness {
+            parts[index].amount += weakness.value;
+            reasons.push(format!("{} has {}, adding {}.", self.name, weakness, weakness.value));
+        }
+
+        // Each type of damage is resisted separately, using the highest resistance that applies to it.
+        for part in parts.iter_mut() {
+            let resistance = self.defenses.iter()
+                .filter(|defense| defense.kind == DefenseKind::Resistance && defense.applies_to(part))
+                .max_by_key(|defense| defense.value);
+            if let Some(resistance) = resistance {
+                let resisted = resistance.value.min(part.amount);
+                if resisted > 0 {
+                    part.amount -= resisted;
+                    reasons.push(format!("{} has {}, reducing {} damage by {}.", self.name, resistance, part.describe(), resisted));
+                }
+            }
+        }
+
+        AdjustedDamage {
+            total: parts.iter().map(|part| part.amount.max(0)).sum(),
+            reasons,
+        }
+    }
+
+    /// The character's defenses, for showing to users. Empty if they have none.
+    pub fn describe_defenses(&self) -> String {
+        let list = |kind: DefenseKind| self.defenses.iter()
+            .filter(|defense| defense.kind == kind)
+            .map(|defense| match kind {
+                DefenseKind::Immunity => defense.damage_type.clone(),
+                _ => format!("{} {}", defense.damage_type, defense.value),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        [("Immunities", DefenseKind::Immunity), ("Weaknesses", DefenseKind::Weakness), ("Resistances", DefenseKind::Resistance)].iter()
+            .map(|(label, kind)| (label, list(*kind)))
+            .filter(|(_, list)| !list.is_empty())
+            .map(|(label, list)| format!("{}: {}", label, list))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::omni::character::CharacterKind;
+
+    fn slurk(defenses: &[(&str, &str, &str)]) -> Character {
+        let mut slurk = Character::new("Slurk", CharacterKind::Npc, 1);
+        for (noun, damage_type, value) in defenses {
+            let mut arguments = vec![damage_type.to_string()];
+            if !value.is_empty() {
+                arguments.push(value.to_string());
+            }
+            slurk.add_defense(noun, &arguments).unwrap();
+        }
+        slurk
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut slurk = slurk(&[("resistance", "Fire", "5"), ("immunity", "poison", "")]);
+        assert_eq!(slurk.add_defense("resistance", &[String::from("fire"), String::from("10")]).unwrap(), "Slurk now has resistance to fire 10.");
+        assert_eq!(slurk.defenses.len(), 2);
+        assert!(slurk.add_defense("weakness", &[String::from("cold")]).is_err());
+        assert!(slurk.add_defense("weakness", &[String::from("cold"), String::from("lots")]).is_err());
+        assert!(slurk.add_defense("immunity", &[String::from("cold"), String::from("5")]).is_err());
+        assert_eq!(slurk.describe_defenses(), "Immunities: poison\nResistances: fire 10");
+        assert_eq!(slurk.remove_defense("immunity", &[String::from("Poison")]).unwrap(), "Slurk no longer has immunity to poison.");
+        assert!(slurk.remove_defense("weakness", &[String::from("fire")]).is_err());
+    }
+
+    #[test]
+    fn untyped_damage() {
+        let slurk = slurk(&[("resistance", "fire", "5")]);
+        assert_eq!(slurk.adjust_damage(vec![DamagePart::new(10, &[])]), AdjustedDamage { total: 10, reasons: vec![] });
+    }
+
+    #[test]
+    fn resistance_and_weakness() {
+        let slurk = slurk(&[("resistance", "fire", "5"), ("resistance", "energy", "3"), ("weakness", "cold", "5"), ("weakness", "energy", "2")]);
+        assert_eq!(slurk.adjust_damage(vec![DamagePart::new(10, &["fire"])]).total, 7);
+        assert_eq!(slurk.adjust_damage(vec![DamagePart::new(3, &["fire"])]).total, 0);
+        // Only the highest weakness applies, and then the cold damage is resisted as energy.
+        assert_eq!(slurk.adjust_damage(vec![DamagePart::new(10, &["cold"])]).total, 12);
+        // Weakness applies once, but each type is resisted separately.
+        let adjusted = slurk.adjust_damage(vec![DamagePart::new(10, &["fire"]), DamagePart::new(4, &["cold"])]);
+        assert_eq!(adjusted.total, 5 + 9 - 3);
+        assert_eq!(adjusted.reasons.len(), 3);
+    }
+
+    #[test]
+    fn immunity() {
+        let slurk = slurk(&[("immunity", "poison", ""), ("immunity", "precision", ""), ("resistance", "all", "2")]);
+        let adjusted = slurk.adjust_damage(vec![DamagePart::new(8, &["piercing"]), DamagePart::new(4, &["precision"]), DamagePart::new(6, &["poison"])]);
+        assert_eq!(adjusted.total, 6);
+        assert_eq!(adjusted.reasons[0], "Slurk has immunity to precision, so 4 precision damage is ignored.");
+        assert_eq!(adjusted.reasons[1], "Slurk has immunity to poison, so 6 poison damage is ignored.");
+    }
+
+    #[test]
+    fn precision_and_splash() {
+        // Precision damage joins the attack's damage, so the resistance only applies once.
+        let slurk = slurk(&[("resistance", "physical", "5"), ("weakness", "splash", "3")]);
+        assert_eq!(slurk.adjust_damage(vec![DamagePart::new(6, &["slashing"]), DamagePart::new(4, &["precision"])]).total, 5);
+        assert_eq!(slurk.adjust_damage(vec![DamagePart::new(2, &["fire", "splash"])]).total, 5);
+    }
+}
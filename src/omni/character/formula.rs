@@ -0,0 +1,284 @@
+//! Dynamic stats store a formula instead of a value, like `=1d20+DEX`. Formulas are plain dice notation,
+//! except that any word that isn't dice is a reference to another of the character's stats.
+//! Evaluating a formula swaps each reference for the referenced stat's value (or, if that stat is dynamic too,
+//! its own resolved formula) and then rolls the result, so any dice are rolled fresh every time.
+
+use anyhow::{Result, anyhow};
+use crate::dice::{self, DiceRoll};
+use crate::omni::effect::Effect;
+use super::Character;
+use super::modifier::{Stacked, stack, stat_words};
+
+/// A formula that has been resolved and rolled.
+#[derive(Debug)]
+pub struct Evaluation {
+    pub roll: DiceRoll,
+    /// Bonuses and penalties that were added to the roll, and those that weren't because they don't stack.
+    pub modifiers: Stacked,
+}
+
+impl Evaluation {
+    /// The roll's breakdown, followed by each modifier that was added to it and then each one that wasn't.
+    pub fn breakdown(&self) -> String {
+        let mut breakdown = self.roll.breakdown.clone();
+        for modifier in self.modifiers.applied.iter() {
+            breakdown.push_str(&format!("\n{}", modifier));
+        }
+        for modifier in self.modifiers.suppressed.iter() {
+            breakdown.push_str(&format!("\n{} (doesn't stack)", modifier));
+        }
+        breakdown
+    }
+}
+
+/// A character's roll, ready to show to users.
+pub struct Rolled {
+    /// What was rolled, like `**Frank's Reflex**`
+    pub title: String,
+    /// The formula as the user or the stat gave it, before anything was resolved.
+    pub formula: String,
+    pub evaluation: Evaluation,
+}
+
+impl Rolled {
+    /// Everything about the roll: the formula, how it was rolled and every modifier.
+    pub fn describe(&self) -> String {
+        format!("{} `{}`\n```\n{}```", self.title, self.formula, self.evaluation.breakdown())
+    }
+
+    /// Just the total, for rolls that shouldn't give away the stats behind them.
+    pub fn describe_total(&self) -> String {
+        format!("{}: {}", self.title, self.evaluation.roll.total)
+    }
+
+    /// How the roll did as a check against the DC.
+    pub fn describe_degree(&self, dc: i64) -> String {
+        self.evaluation.roll.describe_degree(dc)
+    }
+}
+
+/// Resolve every stat reference in the formula and roll it.
+pub fn evaluate(character: &Character, formula: &str) -> Result<Evaluation> {
+    let resolved = resolve(character, formula)?;
+    let roll = dice::roll(&resolved)?;
+    Ok(Evaluation {
+        roll,
+        modifiers: Stacked::default(),
+    })
+}
+
+/// Like `evaluate`, but for when the character is actually rolling something, so bonuses and penalties from their conditions
+/// and the given effects apply. Which ones apply depends on the name of the stat being rolled, if any, and the stats the formula refers to.
+pub fn evaluate_roll(character: &Character, formula: &str, stat_name: Option<&str>, effects: &[&Effect]) -> Result<Evaluation> {
+    let (resolved, modifiers) = resolve_roll(character, formula, stat_name, effects)?;
+    let roll = dice::roll(&with_modifiers(&resolved, &modifiers))?;
+    Ok(Evaluation {
+        roll,
+        modifiers,
+    })
+}
+
+/// Everything `evaluate_roll` does short of rolling: the resolved formula, and the bonuses and penalties that apply to it.
+pub fn resolve_roll(character: &Character, formula: &str, stat_name: Option<&str>, effects: &[&Effect]) -> Result<(String, Stacked)> {
+    let resolved = resolve(character, formula)?;
+    let mut names = references(formula);
+    names.extend(stat_name.map(String::from));
+    let words = stat_words(&names);
+    let is_check = resolved.to_lowercase().contains("d20");
+    let mut modifiers = character.condition_modifiers(&words, is_check);
+    modifiers.extend(effects.iter().flat_map(|effect| effect.roll_modifiers(&words, is_check)));
+    Ok((resolved, stack(modifiers)))
+}
+
+/// What actually gets rolled for a resolved formula, like `1d20+7-2` when frightened 2.
+pub fn with_modifiers(resolved: &str, modifiers: &Stacked) -> String {
+    // Adding and subtracting come last in the order of operations, so tacking the total on the end is always safe.
+    match modifiers.total() {
+        0 => resolved.to_string(),
+        total => format!("{}{:+}", resolved, total),
+    }
+}
+
+/// Replace every stat reference in the formula with the value it refers to, without rolling anything.
+pub fn resolve(character: &Character, formula: &str) -> Result<String> {
+    resolve_nested(character, formula, &mut Vec::new())
+}
+
+/// Resolve a formula that may be nested inside others. The stack holds the names of the dynamic stats
+/// currently being resolved, so a stat that ends up referring back to itself can be caught instead of recursing forever.
+fn resolve_nested(character: &Character, formula: &str, stack: &mut Vec<String>) -> Result<String> {
+    let mut resolved = String::new();
+    let mut word = String::new();
+    for character_in_formula in formula.chars().chain(std::iter::once(' ')) {
+        if character_in_formula.is_ascii_alphanumeric() || character_in_formula == '_' {
+            word.push(character_in_formula);
+            continue;
+        }
+        if !word.is_empty() {
+            resolved.push_str(&resolve_word(character, &word, stack)?);
+            word.clear();
+        }
+        match character_in_formula {
+            '+' | '-' | '*' | '/' | '(' | ')' => resolved.push(character_in_formula),
+            ' ' => {},
+            unknown => return Err(anyhow!("Didn't expect '{}' in `{}`. Formulas can only contain dice, numbers, stat names, and + - * / ( )", unknown, formula)),
+        }
+    }
+    Ok(resolved)
+}
+
+/// A single word from a formula is either a number, some dice, `fortune` or `misfortune`, or a reference to another stat.
+fn resolve_word(character: &Character, word: &str, stack: &mut Vec<String>) -> Result<String> {
+    if word.chars().all(|c| c.is_ascii_digit()) || is_dice(word) || is_function(word) {
+        return Ok(word.to_string());
+    }
+
+    let stat = character.find_stat(word)
+        .ok_or_else(|| anyhow!("{} doesn't have a stat named {} to use in a formula.", character.name, word))?;
+    if let Some(formula) = stat.formula() {
+        if let Some(start) = stack.iter().position(|name| *name == stat.name()) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(stat.name());
+            return Err(anyhow!("{}'s stats refer to each other in a loop: {}", character.name, cycle.join(" → ")));
+        }
+        stack.push(stat.name());
+        let nested = resolve_nested(character, formula, stack)?;
+        stack.pop();
+        return Ok(format!("({})", nested));
+    }
+
+    match stat.value.parse::<i64>() {
+        Ok(value) if value < 0 => Ok(format!("({})", value)),
+        Ok(value) => Ok(value.to_string()),
+        Err(_) => Err(anyhow!("{}'s {} is '{}', which can't be used in a formula because it isn't a number.", character.name, stat.display_name, stat.value)),
+    }
+}
+
+/// Whether the formula refers to any stats, as opposed to being plain dice and numbers.
+pub fn has_references(formula: &str) -> bool {
+    !references(formula).is_empty()
+}
+
+/// The names of the stats the formula refers to directly.
+pub fn references(formula: &str) -> Vec<String> {
+    formula.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty() && !word.chars().all(|c| c.is_ascii_digit()) && !is_dice(word) && !is_function(word))
+        .map(String::from)
+        .collect()
+}
+
+/// Dice look like `2d6`, `d20` or `4d6kh3`.
+pub fn is_dice(word: &str) -> bool {
+    crate::dice::is_dice(word)
+}
+
+/// `fortune(...)` and `misfortune(...)` roll what's inside twice, so their names aren't stats.
+fn is_function(word: &str) -> bool {
+    word.eq_ignore_ascii_case("fortune") || word.eq_ignore_ascii_case("misfortune")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::omni::character::{CharacterKind, parse_noun_target_stats_command};
+    use crate::omni::character::modifier::ModifierKind;
+    use crate::omni::effect::{Duration, EffectModifier};
+
+    fn frank() -> Character {
+        let mut frank = Character::new("Frank", CharacterKind::Player, 1);
+        frank.stats = parse_noun_target_stats_command("player Frank DEX:3 STR:-1 Level:5 Reflex:=1d20+DEX Name:\"Frank\" Attack:=Reflex+level*2 Loop:=Attack+Again Again:=LOOP").unwrap().stats;
+        frank
+    }
+
+    #[test]
+    fn dice_words() {
+        assert!(is_dice("1d20"));
+        assert!(is_dice("d6"));
+        assert!(is_dice("10D10"));
+        assert!(!is_dice("DEX"));
+        assert!(!is_dice("d"));
+        assert!(is_dice("2d6x"));
+        assert!(is_dice("4d6kh3"));
+        assert!(!is_dice("2d6q"));
+    }
+
+    #[test]
+    fn find_references() {
+        assert!(!has_references("2d6 + 4*(1d8-1)"));
+        assert!(has_references("1d20+DEX"));
+        assert!(has_references("perception"));
+        assert!(!has_references("fortune(1d20+4) + 4d6kh3"));
+    }
+
+    #[test]
+    fn resolve_references() {
+        let frank = frank();
+        assert_eq!(resolve(&frank, "1d20+DEX").unwrap(), "1d20+3");
+        assert_eq!(resolve(&frank, "str + 2").unwrap(), "(-1)+2");
+        assert_eq!(resolve(&frank, "Attack").unwrap(), "((1d20+3)+5*2)");
+        assert_eq!(resolve(&frank, "misfortune(1d20+DEX)").unwrap(), "misfortune(1d20+3)");
+        assert!(resolve(&frank, "1d20+WIS").is_err());
+        assert!(resolve(&frank, "Name+1").is_err());
+        assert!(resolve(&frank, "1d20>DEX").is_err());
+    }
+
+    #[test]
+    fn degrees_of_success() {
+        let frank = frank();
+        let rolled = Rolled { title: String::from("**Frank**"), formula: String::from("10+DEX"), evaluation: evaluate(&frank, "10+DEX").unwrap() };
+        assert_eq!(rolled.describe_degree(13), "**Success** against DC 13");
+        assert_eq!(rolled.describe_degree(3), "**Critical Success** against DC 3");
+    }
+
+    #[test]
+    fn detect_cycles() {
+        let error = resolve(&frank(), "Loop").unwrap_err().to_string();
+        assert!(error.contains("loop → again → loop"), "{}", error);
+    }
+
+    #[test]
+    fn conditions_penalize_rolls() {
+        let mut frank = frank();
+        frank.add_condition("frightened", Some("1")).unwrap();
+        frank.add_condition("sickened", Some("2")).unwrap();
+        let evaluation = evaluate_roll(&frank, "Reflex", Some("Reflex"), &[]).unwrap();
+        assert_eq!(evaluation.modifiers.applied.len(), 1);
+        assert!(evaluation.breakdown().ends_with("\nsickened 2: -2 status\nfrightened 1: -1 status (doesn't stack)"), "{}", evaluation.breakdown());
+        let total = evaluation.roll.total;
+        assert!((2..=21).contains(&total), "{}", total);
+        // Only checks are penalized, not damage or other plain math.
+        assert_eq!(evaluate_roll(&frank, "DEX+Level", None, &[]).unwrap().modifiers, Stacked::default());
+    }
+
+    #[test]
+    fn effects_modify_rolls() {
+        let mut frank = frank();
+        frank.add_condition("frightened", Some("1")).unwrap();
+        let bless = Effect {
+            name: String::from("Bless"),
+            target: frank.id,
+            source: None,
+            duration: Duration::Unlimited,
+            modifiers: vec![EffectModifier { kind: ModifierKind::Status, value: 1, stats: vec![String::from("attack")] }],
+        };
+        let heroism = Effect {
+            name: String::from("Heroism"),
+            target: frank.id,
+            source: None,
+            duration: Duration::Unlimited,
+            modifiers: vec![EffectModifier { kind: ModifierKind::Status, value: 2, stats: Vec::new() }],
+        };
+        let evaluation = evaluate_roll(&frank, "Attack", Some("Attack"), &[&bless, &heroism]).unwrap();
+        assert_eq!(evaluation.modifiers.total(), 1);
+        assert!(evaluation.breakdown().ends_with("\nfrightened 1: -1 status\nHeroism: +2 status\nBless: +1 status (doesn't stack)"), "{}", evaluation.breakdown());
+        // Bless still applies to attacks that aren't checks, like a fixed attack bonus, but heroism only helps checks.
+        assert_eq!(evaluate_roll(&frank, "Level+2", Some("Attack bonus"), &[&bless, &heroism]).unwrap().roll.total, 8);
+    }
+
+    #[test]
+    fn evaluate_without_dice() {
+        assert_eq!(resolve(&frank(), "DEX+Level").unwrap(), "3+5");
+        let evaluation = evaluate(&frank(), "DEX+Level").unwrap();
+        assert_eq!(evaluation.roll.total, 8);
+    }
+}
@@ -0,0 +1,267 @@
+//! Hit points change far more often than any other stat, so they get commands of their own that work relative to the current value.
+//! A character's HP is their `HP` stat, and its maximum (if it has one) caps any healing.
+//! Temporary HP is kept separately on the character and is always used up before real HP.
+//! Players at 0 HP are dying, and each time they recover from it they're left a little more wounded.
+
+use anyhow::{Result, anyhow};
+use super::{Character, CharacterKind};
+use super::condition::ConditionKind;
+
+/// A reply about a change to a character's HP, and the same reply without anything that gives away exactly how much HP
+/// or temporary HP they have, for characters whose stats are hidden.
+#[derive(Debug)]
+pub struct HitPointsReply {
+    pub full: String,
+    pub hidden: String,
+}
+
+impl HitPointsReply {
+    fn new(text: String) -> Self {
+        HitPointsReply { full: text.clone(), hidden: text }
+    }
+
+    /// Add to both versions of the reply.
+    pub fn push_str(&mut self, text: &str) {
+        self.full.push_str(text);
+        self.hidden.push_str(text);
+    }
+
+    /// Add a detail only the full reply should give.
+    pub fn push_detail(&mut self, text: &str) {
+        self.full.push_str(text);
+    }
+
+    /// The character's exact HP, or roughly how hurt they are in the hidden reply.
+    fn push_hit_points(&mut self, character: &Character) {
+        self.full.push_str(&character.describe_hit_points());
+        self.hidden.push_str(&character.describe_health().unwrap_or_default());
+    }
+}
+
+impl Character {
+    /// The character's current and maximum HP, read from their HP stat.
+    fn hit_points(&self) -> Result<(i64, Option<i64>)> {
+        let stat = self.find_stat("hp")
+            .ok_or_else(|| anyhow!("{} doesn't have an HP stat. Add one with something like `!add stat {} HP:30/30`.", self.name, self.name))?;
+        let number = |value: &str| value.parse::<i64>()
+            .map_err(|_| anyhow!("{}'s HP is '{}', which isn't a number.", self.name, value));
+        let maximum = match &stat.maximum_value {
+            Some(maximum) => Some(number(maximum)?),
+            None => None,
+        };
+        Ok((number(&stat.value)?, maximum))
+    }
+
+    fn set_hit_points(&mut self, hit_points: i64) {
+        let stat = self.find_stat_mut("hp").expect("HP went missing after checking for it!");
+        stat.value = hit_points.to_string();
+    }
+
+    /// Current HP and temporary HP, for the end of a reply.
+    fn describe_hit_points(&self) -> String {
+        let mut description = self.find_stat("hp").map(|stat| stat.to_string()).unwrap_or_default();
+        if self.temp_hp > 0 {
+            description.push_str(&format!(" (+{} temp)", self.temp_hp));
+        }
+        description
+    }
+
+    /// Roughly how hurt the character is, like `Health: Bloodied`, for when their exact HP is hidden.
+    /// None if they don't have an HP stat to go by.
+    pub fn describe_health(&self) -> Option<String> {
+        let (hit_points, maximum) = self.hit_points().ok()?;
+        let health = match maximum {
+            _ if hit_points <= 0 => "Down",
+            Some(maximum) if hit_points >= maximum => "Unharmed",
+            Some(maximum) if hit_points * 2 > maximum => "Hurt",
+            Some(maximum) if hit_points * 4 > maximum => "Bloodied",
+            Some(_) => "Near death",
+            None => "Standing",
+        };
+        Some(format!("Health: {}", health))
+    }
+
+    /// Take damage, using up temporary HP first. HP never goes below 0.
+    pub fn damage(&mut self, amount: i64) -> Result<HitPointsReply> {
+        let (hit_points, maximum) = self.hit_points()?;
+        let amount = amount.max(0);
+        let absorbed = amount.min(self.temp_hp);
+        self.temp_hp -= absorbed;
+        let remaining = amount - absorbed;
+        self.set_hit_points((hit_points - remaining).max(0));
+
+        let mut response = HitPointsReply::new(format!("{} takes {} damage", self.name, amount));
+        if absorbed > 0 {
+            response.push_detail(&format!(", {} of it to temporary HP", absorbed));
+        }
+        response.push_str(". ");
+        response.push_hit_points(self);
+
+        // Pathfinder's massive damage rule: a single hit of at least double your maximum HP kills outright.
+        if maximum.is_some_and(|maximum| maximum > 0 && remaining >= maximum * 2) {
+            response.push_str(&format!("\nThat's massive damage. {} dies instantly!", self.name));
+        } else if hit_points > 0 && remaining >= hit_points {
+            match self.kind {
+                CharacterKind::Player => {
+                    let dying = 1 + self.condition_value(ConditionKind::Wounded).unwrap_or(0);
+                    self.set_condition(ConditionKind::Dying, dying);
+                    response.push_str(&format!("\n{} is down to 0 HP, falls unconscious and is dying {}!", self.name, dying));
+                    response.push_str(&self.check_for_death());
+                },
+                CharacterKind::Npc => response.push_str(&format!("\n{} is down to 0 HP and is dead, or unconscious if the damage was nonlethal!", self.name)),
+            }
+        } else if hit_points == 0 && remaining > 0 {
+            match self.condition_value(ConditionKind::Dying) {
+                Some(dying) => {
+                    self.set_condition(ConditionKind::Dying, dying + 1);
+                    response.push_str(&format!("\n{} was hit while dying, and is now dying {}.", self.name, dying + 1));
+                    response.push_str(&self.check_for_death());
+                },
+                None => response.push_str(&format!("\n{} was already at 0 HP.", self.name)),
+            }
+        }
+        Ok(response)
+    }
+
+    /// Regain HP, up to the maximum if there is one.
+    pub fn heal(&mut self, amount: i64) -> Result<HitPointsReply> {
+        let (hit_points, maximum) = self.hit_points()?;
+        let amount = amount.max(0);
+        let healed = match maximum {
+            Some(maximum) => (hit_points + amount).min(maximum.max(hit_points)),
+            None => hit_points + amount,
+        };
+        self.set_hit_points(healed);
+
+        // Healing stops at the maximum, so how much was regained can give away exactly how hurt they were.
+        let mut response = HitPointsReply { full: format!("{} regains {} HP", self.name, healed - hit_points), hidden: format!("{} is healed", self.name) };
+        if healed - hit_points < amount {
+            response.push_detail(&format!(" ({} more would be over their maximum)", amount - (healed - hit_points)));
+        }
+        response.push_str(". ");
+        response.push_hit_points(self);
+        if hit_points == 0 && healed > 0 {
+            self.set_condition(ConditionKind::Unconscious, 0);
+            if self.condition_value(ConditionKind::Dying).is_some() {
+                response.push_str(&format!("\n{}", self.recover_from_dying()));
+            } else {
+                response.push_str(&format!("\n{} is no longer at 0 HP.", self.name));
+            }
+        }
+        Ok(response)
+    }
+
+    /// Gain temporary HP. These don't stack, so the character keeps whichever is higher, their current temporary HP or the new amount.
+    /// Setting it to 0 removes any temporary HP they have.
+    pub fn gain_temp_hp(&mut self, amount: i64) -> Result<HitPointsReply> {
+        let amount = amount.max(0);
+        if amount == 0 {
+            self.temp_hp = 0;
+            return Ok(HitPointsReply::new(format!("{} no longer has any temporary HP.", self.name)));
+        }
+        if amount <= self.temp_hp {
+            return Ok(HitPointsReply {
+                full: format!("{} already has {} temporary HP. Temporary HP doesn't stack, so they keep that instead.", self.name, self.temp_hp),
+                hidden: format!("{} already has at least that much temporary HP. Temporary HP doesn't stack, so they keep that instead.", self.name),
+            });
+        }
+        self.temp_hp = amount;
+        Ok(HitPointsReply::new(format!("{} now has {} temporary HP.", self.name, amount)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::omni::character::parse_noun_target_stats_command;
+
+    fn character(kind: CharacterKind, stats: &str) -> Character {
+        let mut character = Character::new("Plunk", kind, 1);
+        character.stats = parse_noun_target_stats_command(&format!("player Plunk {}", stats)).unwrap().stats;
+        character
+    }
+
+    #[test]
+    fn damage() {
+        let mut plunk = character(CharacterKind::Player, "HP:30/30");
+        assert_eq!(plunk.damage(12).unwrap().full, "Plunk takes 12 damage. HP: 18/30");
+        plunk.temp_hp = 5;
+        assert_eq!(plunk.damage(8).unwrap().full, "Plunk takes 8 damage, 5 of it to temporary HP. HP: 15/30");
+        assert_eq!(plunk.temp_hp, 0);
+        assert!(plunk.damage(20).unwrap().full.ends_with("Plunk is down to 0 HP, falls unconscious and is dying 1!"));
+        assert_eq!(plunk.find_stat("hp").unwrap().value, "0");
+        assert!(plunk.damage(1).unwrap().full.ends_with("Plunk was hit while dying, and is now dying 2."));
+        plunk.set_condition(ConditionKind::Dying, 0);
+        assert!(plunk.damage(1).unwrap().full.ends_with("Plunk was already at 0 HP."));
+
+        let mut slurk = character(CharacterKind::Npc, "HP:10/10");
+        assert!(slurk.damage(10).unwrap().full.contains("is dead"));
+        let mut slurk = character(CharacterKind::Npc, "HP:10/10");
+        assert!(slurk.damage(20).unwrap().full.contains("dies instantly"));
+    }
+
+    #[test]
+    fn heal() {
+        let mut plunk = character(CharacterKind::Player, "HP:0/30");
+        assert_eq!(plunk.heal(10).unwrap().full, "Plunk regains 10 HP. HP: 10/30\nPlunk is no longer at 0 HP.");
+        assert_eq!(plunk.heal(25).unwrap().full, "Plunk regains 20 HP (5 more would be over their maximum). HP: 30/30");
+        let mut plunk = character(CharacterKind::Player, "HP:3");
+        assert_eq!(plunk.heal(5).unwrap().full, "Plunk regains 5 HP. HP: 8");
+    }
+
+    #[test]
+    fn dying_and_wounded() {
+        let mut plunk = character(CharacterKind::Player, "HP:10/10");
+        plunk.damage(10).unwrap();
+        assert_eq!(plunk.describe_conditions(), "dying 1 (unconscious, blinded, flat-footed)");
+        assert!(plunk.heal(5).unwrap().full.ends_with("Plunk is no longer dying, but is now wounded 1."));
+        assert_eq!(plunk.describe_conditions(), "wounded 1");
+
+        // Being wounded makes going down again more dangerous.
+        assert!(plunk.damage(5).unwrap().full.ends_with("is dying 2!"));
+        let response = plunk.damage(1).unwrap().full;
+        assert!(response.contains("dying 3"), "{}", response);
+        assert!(plunk.damage(1).unwrap().full.ends_with("Plunk has reached dying 4 and dies!"));
+    }
+
+    #[test]
+    fn temp_hp() {
+        let mut plunk = character(CharacterKind::Player, "HP:30/30");
+        plunk.gain_temp_hp(5).unwrap();
+        assert!(plunk.gain_temp_hp(3).unwrap().full.contains("doesn't stack"));
+        assert_eq!(plunk.temp_hp, 5);
+        assert!(plunk.damage(2).unwrap().full.ends_with("HP: 30/30 (+3 temp)"));
+        plunk.gain_temp_hp(0).unwrap();
+        assert_eq!(plunk.temp_hp, 0);
+    }
+
+    #[test]
+    fn hidden_health() {
+        let mut slurk = character(CharacterKind::Npc, "HP:40/40");
+        assert_eq!(slurk.describe_health().unwrap(), "Health: Unharmed");
+        assert_eq!(slurk.damage(15).unwrap().hidden, "Plunk takes 15 damage. Health: Hurt");
+        slurk.damage(5).unwrap();
+        assert_eq!(slurk.describe_health().unwrap(), "Health: Bloodied");
+        slurk.temp_hp = 5;
+        let response = slurk.damage(15).unwrap();
+        assert_eq!(response.full, "Plunk takes 15 damage, 5 of it to temporary HP. HP: 10/40");
+        assert_eq!(response.hidden, "Plunk takes 15 damage. Health: Near death");
+        let response = slurk.heal(40).unwrap();
+        assert_eq!(response.full, "Plunk regains 30 HP (10 more would be over their maximum). HP: 40/40");
+        assert_eq!(response.hidden, "Plunk is healed. Health: Unharmed");
+        slurk.gain_temp_hp(8).unwrap();
+        let response = slurk.gain_temp_hp(6).unwrap();
+        assert!(response.full.contains("already has 8 temporary HP"));
+        assert!(!response.hidden.contains('8'));
+        slurk.damage(60).unwrap();
+        assert_eq!(slurk.describe_health().unwrap(), "Health: Down");
+        assert_eq!(character(CharacterKind::Npc, "HP:3").describe_health().unwrap(), "Health: Standing");
+        assert!(character(CharacterKind::Npc, "AC:3").describe_health().is_none());
+    }
+
+    #[test]
+    fn missing_hp() {
+        assert!(character(CharacterKind::Player, "AC:18").damage(1).is_err());
+        assert!(character(CharacterKind::Player, "HP:\"lots\"").heal(1).is_err());
+    }
+}
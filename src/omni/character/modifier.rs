@@ -0,0 +1,139 @@
+//! Bonuses and penalties that are added on top of a roll, like the status penalty for being frightened or the status bonus from Bless.
+//! Pathfinder doesn't let bonuses or penalties of the same type stack: only the highest bonus and the worst penalty of each type apply.
+//! The exception is untyped modifiers, which all apply.
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ModifierKind {
+    Circumstance,
+    Status,
+    Item,
+    Untyped,
+}
+
+impl ModifierKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "circumstance" => Some(ModifierKind::Circumstance),
+            "status" => Some(ModifierKind::Status),
+            "item" => Some(ModifierKind::Item),
+            "untyped" => Some(ModifierKind::Untyped),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ModifierKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModifierKind::Circumstance => write!(f, "circumstance"),
+            ModifierKind::Status => write!(f, "status"),
+            ModifierKind::Item => write!(f, "item"),
+            ModifierKind::Untyped => write!(f, "untyped"),
+        }
+    }
+}
+
+/// A bonus (positive value) or penalty (negative value) and where it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modifier {
+    pub source: String,
+    pub kind: ModifierKind,
+    pub value: i64,
+}
+
+/// Formats the modifier for a roll's breakdown, for example `frightened 2: -2 status`.
+impl fmt::Display for Modifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:+} {}", self.source, self.value, self.kind)
+    }
+}
+
+/// Modifiers sorted into those that apply to a roll and those that don't because something better (or worse) of the same type does.
+#[derive(Debug, Default, PartialEq)]
+pub struct Stacked {
+    pub applied: Vec<Modifier>,
+    pub suppressed: Vec<Modifier>,
+}
+
+impl Stacked {
+    pub fn total(&self) -> i64 {
+        self.applied.iter().map(|modifier| modifier.value).sum()
+    }
+}
+
+/// Apply Pathfinder's stacking rules. When two modifiers tie, the first one given is the one that applies.
+pub fn stack(modifiers: Vec<Modifier>) -> Stacked {
+    let mut stacked = Stacked::default();
+    for modifier in modifiers {
+        if modifier.kind == ModifierKind::Untyped || modifier.value == 0 {
+            stacked.applied.push(modifier);
+            continue;
+        }
+        let is_bonus = modifier.value > 0;
+        let rival = stacked.applied.iter().position(|applied| applied.kind == modifier.kind && (applied.value > 0) == is_bonus);
+        match rival {
+            Some(index) if modifier.value.abs() > stacked.applied[index].value.abs() => {
+                let beaten = std::mem::replace(&mut stacked.applied[index], modifier);
+                stacked.suppressed.push(beaten);
+            },
+            Some(_) => stacked.suppressed.push(modifier),
+            None => stacked.applied.push(modifier),
+        }
+    }
+    stacked
+}
+
+/// Split the names of the stats involved in a roll into lowercase words, for matching against the stats a modifier applies to.
+pub fn stat_words(names: &[String]) -> Vec<String> {
+    names.iter()
+        .flat_map(|name| name.to_lowercase().split(|c: char| !c.is_ascii_alphanumeric()).filter(|word| !word.is_empty()).map(String::from).collect::<Vec<String>>())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modifier(source: &str, kind: ModifierKind, value: i64) -> Modifier {
+        Modifier { source: source.to_string(), kind, value }
+    }
+
+    #[test]
+    fn only_the_best_and_worst_of_a_type_apply() {
+        let stacked = stack(vec![
+            modifier("frightened 1", ModifierKind::Status, -1),
+            modifier("bless", ModifierKind::Status, 1),
+            modifier("sickened 2", ModifierKind::Status, -2),
+            modifier("heroism", ModifierKind::Status, 2),
+            modifier("prone", ModifierKind::Circumstance, -2),
+            modifier("flanking", ModifierKind::Circumstance, -2),
+        ]);
+        assert_eq!(stacked.applied, vec![
+            modifier("sickened 2", ModifierKind::Status, -2),
+            modifier("heroism", ModifierKind::Status, 2),
+            modifier("prone", ModifierKind::Circumstance, -2),
+        ]);
+        assert_eq!(stacked.suppressed, vec![
+            modifier("frightened 1", ModifierKind::Status, -1),
+            modifier("bless", ModifierKind::Status, 1),
+            modifier("flanking", ModifierKind::Circumstance, -2),
+        ]);
+        assert_eq!(stacked.total(), -2);
+        assert_eq!(stacked.applied[0].to_string(), "sickened 2: -2 status");
+    }
+
+    #[test]
+    fn untyped_modifiers_all_apply() {
+        let stacked = stack(vec![modifier("a", ModifierKind::Untyped, -1), modifier("b", ModifierKind::Untyped, -2), modifier("c", ModifierKind::Item, 1)]);
+        assert_eq!(stacked.applied.len(), 3);
+        assert_eq!(stacked.total(), -2);
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(stat_words(&[String::from("Longsword Attack"), String::from("STR")]), vec!["longsword", "attack", "str"]);
+    }
+}
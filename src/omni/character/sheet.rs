@@ -0,0 +1,525 @@
+//! A character can have a PF2e sheet alongside their free-form stats, holding their level, ability scores and proficiency ranks.
+//! The sheet is the source of truth for those: the stats made from it, like `STR`, `Athletics_Prof` and `Athletics`,
+//! are worked out again whenever the sheet changes, including when the character's level does,
+//! and can't be changed directly. Every other stat works just like it does on a character without a sheet.
+
+use std::fmt;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use super::Character;
+use super::stat::Stat;
+
+/// The six abilities, in the order they're listed on a character sheet.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Ability {
+    Str,
+    Dex,
+    Con,
+    Int,
+    Wis,
+    Cha,
+}
+
+impl Ability {
+    pub const ALL: [Ability; 6] = [Ability::Str, Ability::Dex, Ability::Con, Ability::Int, Ability::Wis, Ability::Cha];
+
+    /// How the ability is named in JSON exports, like `str`.
+    pub fn key(self) -> &'static str {
+        match self {
+            Ability::Str => "str",
+            Ability::Dex => "dex",
+            Ability::Con => "con",
+            Ability::Int => "int",
+            Ability::Wis => "wis",
+            Ability::Cha => "cha",
+        }
+    }
+
+    /// The name of the stat holding the ability's modifier, like `STR`.
+    pub fn stat_name(self) -> String {
+        self.key().to_uppercase()
+    }
+
+    /// Abilities can be given as `STR` or `Strength`.
+    pub fn parse(name: &str) -> Option<Ability> {
+        let name = name.to_lowercase();
+        Ability::ALL.iter().copied().find(|ability| name.len() >= 3 && (name == ability.key() || ability.full_name().to_lowercase() == name))
+    }
+
+    fn full_name(self) -> &'static str {
+        match self {
+            Ability::Str => "Strength",
+            Ability::Dex => "Dexterity",
+            Ability::Con => "Constitution",
+            Ability::Int => "Intelligence",
+            Ability::Wis => "Wisdom",
+            Ability::Cha => "Charisma",
+        }
+    }
+}
+
+/// The saves and perception, with the ability each uses.
+pub const SAVES: [(&str, Ability); 4] = [("Fortitude", Ability::Con), ("Reflex", Ability::Dex), ("Will", Ability::Wis), ("Perception", Ability::Wis)];
+
+/// Every skill besides lores, with the ability each uses.
+pub const SKILLS: [(&str, Ability); 16] = [
+    ("Acrobatics", Ability::Dex), ("Arcana", Ability::Int), ("Athletics", Ability::Str), ("Crafting", Ability::Int),
+    ("Deception", Ability::Cha), ("Diplomacy", Ability::Cha), ("Intimidation", Ability::Cha), ("Medicine", Ability::Wis),
+    ("Nature", Ability::Wis), ("Occultism", Ability::Int), ("Performance", Ability::Cha), ("Religion", Ability::Wis),
+    ("Society", Ability::Int), ("Stealth", Ability::Dex), ("Survival", Ability::Wis), ("Thievery", Ability::Dex),
+];
+
+const WEAPON_CATEGORIES: [&str; 4] = ["Unarmed", "Simple", "Martial", "Advanced"];
+const TRADITIONS: [&str; 4] = ["Arcane", "Divine", "Occult", "Primal"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Rank {
+    Untrained,
+    Trained,
+    Expert,
+    Master,
+    Legendary,
+}
+
+impl Rank {
+    const ALL: [Rank; 5] = [Rank::Untrained, Rank::Trained, Rank::Expert, Rank::Master, Rank::Legendary];
+
+    /// Ranks can be given in full or by their first letter, like `expert` or `e`.
+    pub fn parse(text: &str) -> Result<Rank> {
+        let text = text.to_lowercase();
+        Rank::ALL.iter().copied().find(|rank| {
+            let name = rank.to_string().to_lowercase();
+            text == name || text == name[..1]
+        }).ok_or_else(|| anyhow!("'{}' isn't a proficiency rank. Use untrained, trained, expert, master or legendary.", text))
+    }
+
+    /// Character builders often give a rank as the bonus it's worth: 0 for untrained, then 2, 4, 6 and 8.
+    pub fn from_bonus(bonus: i64) -> Rank {
+        Rank::ALL[(bonus / 2).clamp(0, 4) as usize]
+    }
+
+    /// What the rank adds before the character's level, which is only added once they're trained.
+    pub fn bonus(self) -> i64 {
+        Rank::ALL.iter().position(|rank| *rank == self).unwrap_or_default() as i64 * 2
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// What a proficiency is in, which decides the stats made from it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ProficiencyKind {
+    /// Skills, saves, perception and lores, which are rolled as a d20 plus an ability modifier and the proficiency bonus.
+    Check(Ability),
+    /// A category of weapons, like Martial, whose proficiency bonus the character's strikes can add.
+    Weapon,
+    /// A magical tradition, like Divine, with the ability the character casts its spells with.
+    Spell(Ability),
+    /// The class DC, which uses the class's key ability.
+    Class(Ability),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Proficiency {
+    pub name: String,
+    pub kind: ProficiencyKind,
+    pub rank: Rank,
+}
+
+impl Proficiency {
+    pub fn new(name: &str, kind: ProficiencyKind, rank: Rank) -> Self {
+        Proficiency {
+            name: name.to_string(),
+            kind,
+            rank,
+        }
+    }
+
+    /// The name of the stat holding the proficiency bonus, like `Athletics_Prof` or `Divine_Spell_Prof`.
+    fn stat_prefix(&self) -> String {
+        match self.kind {
+            ProficiencyKind::Spell(_) => format!("{}_Spell", self.name),
+            _ => self.name.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sheet {
+    pub level: i64,
+    /// Ability scores, like 18, in the order of `Ability::ALL`.
+    pub abilities: [i64; 6],
+    pub proficiencies: Vec<Proficiency>,
+}
+
+impl Sheet {
+    /// A sheet that is untrained in every save, skill and perception.
+    pub fn new(level: i64, abilities: [i64; 6]) -> Self {
+        let proficiencies = SAVES.iter().chain(SKILLS.iter())
+            .map(|(name, ability)| Proficiency::new(name, ProficiencyKind::Check(*ability), Rank::Untrained))
+            .collect();
+        Sheet {
+            level,
+            abilities,
+            proficiencies,
+        }
+    }
+
+    pub fn score(&self, ability: Ability) -> i64 {
+        self.abilities[Ability::ALL.iter().position(|known| *known == ability).unwrap_or_default()]
+    }
+
+    pub fn set_score(&mut self, ability: Ability, score: i64) {
+        self.abilities[Ability::ALL.iter().position(|known| *known == ability).unwrap_or_default()] = score;
+    }
+
+    pub fn modifier(&self, ability: Ability) -> i64 {
+        modifier(self.score(ability))
+    }
+
+    /// The proficiency bonus for a rank at the sheet's level.
+    pub fn proficiency_bonus(&self, rank: Rank) -> i64 {
+        match rank {
+            Rank::Untrained => 0,
+            rank => rank.bonus() + self.level,
+        }
+    }
+
+    pub fn find_proficiency(&self, name: &str) -> Option<&Proficiency> {
+        self.proficiencies.iter().find(|proficiency| proficiency.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Take a proficiency off the sheet, along with the stats made from it.
+    pub fn remove_proficiency(&mut self, name: &str) {
+        self.proficiencies.retain(|proficiency| !proficiency.name.eq_ignore_ascii_case(name));
+    }
+
+    /// Change the rank of a proficiency, adding it if the sheet doesn't have it yet.
+    /// New lores, weapon categories and traditions can be added by name, like `Warfare_Lore`, `Martial` or `Divine`.
+    /// Spells are cast with the character's best mental ability, and the class DC uses their best ability of all.
+    pub fn set_rank(&mut self, name: &str, rank: Rank) -> Result<&Proficiency> {
+        if let Some(index) = self.proficiencies.iter().position(|proficiency| proficiency.name.eq_ignore_ascii_case(name)) {
+            self.proficiencies[index].rank = rank;
+            return Ok(&self.proficiencies[index]);
+        }
+
+        let known = |names: &[&'static str]| names.iter().copied().find(|known| known.eq_ignore_ascii_case(name));
+        let checks: Vec<(&str, Ability)> = SAVES.iter().chain(SKILLS.iter()).copied().collect();
+        let (name, kind) = if let Some((name, ability)) = checks.iter().find(|(known, _)| known.eq_ignore_ascii_case(name)) {
+            (name.to_string(), ProficiencyKind::Check(*ability))
+        } else if name.to_lowercase().ends_with("_lore") && name.len() > "_lore".len() {
+            (name.to_string(), ProficiencyKind::Check(Ability::Int))
+        } else if let Some(category) = known(&WEAPON_CATEGORIES) {
+            (category.to_string(), ProficiencyKind::Weapon)
+        } else if let Some(tradition) = known(&TRADITIONS) {
+            (tradition.to_string(), ProficiencyKind::Spell(self.best(&[Ability::Int, Ability::Wis, Ability::Cha])))
+        } else if name.eq_ignore_ascii_case("class") {
+            (String::from("Class"), ProficiencyKind::Class(self.best(&Ability::ALL)))
+        } else {
+            return Err(anyhow!("{} isn't a skill, save, lore, weapon category, tradition or class. Lores end in _Lore, like `Warfare_Lore`.", name));
+        };
+        self.proficiencies.push(Proficiency::new(&name, kind, rank));
+        Ok(self.proficiencies.last().expect("Proficiency went missing right after adding it!"))
+    }
+
+    /// Whichever of the abilities has the highest score, preferring the first on a tie.
+    fn best(&self, abilities: &[Ability]) -> Ability {
+        abilities.iter().copied().fold(abilities[0], |best, ability| if self.score(ability) > self.score(best) { ability } else { best })
+    }
+
+    /// Every stat made from the sheet, in the order they're shown.
+    pub fn stats(&self) -> Vec<Stat> {
+        let mut stats = vec![Stat::new("Level", self.level.to_string())];
+        for ability in Ability::ALL.iter() {
+            stats.push(Stat::new(&ability.stat_name(), self.modifier(*ability).to_string()));
+        }
+        for proficiency in self.proficiencies.iter() {
+            let prefix = proficiency.stat_prefix();
+            let trained = proficiency.rank != Rank::Untrained;
+            let bonus = format!("+{}_Prof", prefix);
+            if trained {
+                stats.push(Stat::new(&format!("{}_Prof", prefix), self.proficiency_bonus(proficiency.rank).to_string()));
+            }
+            match (proficiency.kind, trained) {
+                (ProficiencyKind::Check(ability), _) => stats.push(Stat::new(&proficiency.name,
+                    format!("=1d20+{}{}", ability.stat_name(), if trained { bonus.as_str() } else { "" }))),
+                (ProficiencyKind::Spell(ability), true) => {
+                    stats.push(Stat::new(&format!("{}_Attack", prefix), format!("=1d20+{}{}", ability.stat_name(), bonus)));
+                    stats.push(Stat::new(&format!("{}_DC", prefix), format!("=10+{}{}", ability.stat_name(), bonus)));
+                },
+                (ProficiencyKind::Class(ability), true) => stats.push(Stat::new(&format!("{}_DC", prefix), format!("=10+{}{}", ability.stat_name(), bonus))),
+                _ => {},
+            }
+        }
+        stats
+    }
+
+    /// Every trained proficiency and its rank, like `Athletics expert, Martial trained`.
+    pub fn describe(&self) -> String {
+        self.proficiencies.iter()
+            .filter(|proficiency| proficiency.rank != Rank::Untrained)
+            .map(|proficiency| format!("{} {}", proficiency.name, proficiency.rank.to_string().to_lowercase()))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+/// The modifier an ability score gives, like +4 for 18.
+pub fn modifier(score: i64) -> i64 {
+    (score - 10).div_euclid(2)
+}
+
+impl Character {
+    /// Give the character a sheet, or replace the one they had, and work out every stat made from it.
+    /// Those stats go first, and take the place of any of the character's own stats that had the same names.
+    /// Returns the names of the character's own stats that were replaced.
+    pub fn set_sheet(&mut self, sheet: Sheet) -> Vec<String> {
+        let derived = sheet.stats();
+        let previous: Vec<String> = self.sheet.as_ref().map(|previous| previous.stats().iter().map(Stat::name).collect()).unwrap_or_default();
+        let mut replaced = Vec::new();
+        let mut on_tracker = Vec::new();
+        for stat in self.stats.iter().filter(|stat| previous.contains(&stat.name()) || derived.iter().any(|new| new.name() == stat.name())) {
+            if !previous.contains(&stat.name()) {
+                replaced.push(stat.display_name.clone());
+            }
+            if stat.display_on_tracker {
+                on_tracker.push(stat.name());
+            }
+        }
+
+        self.stats.retain(|stat| !previous.contains(&stat.name()) && !derived.iter().any(|new| new.name() == stat.name()));
+        self.stats.splice(0..0, derived.into_iter().map(|mut stat| {
+            stat.display_on_tracker = on_tracker.contains(&stat.name());
+            stat
+        }));
+        self.sheet = Some(sheet);
+        replaced
+    }
+
+    /// Stats made from the sheet can only be changed through it. Returns an error saying how, for a stat that is.
+    pub(super) fn ensure_not_derived(&self, name: &str) -> Result<()> {
+        let sheet = match &self.sheet {
+            Some(sheet) => sheet,
+            None => return Ok(()),
+        };
+        let stat = match sheet.stats().into_iter().find(|stat| stat.name() == name.to_lowercase()) {
+            Some(stat) => stat,
+            None => return Ok(()),
+        };
+        let quoted = crate::omni::quote_name(&self.name);
+        match Ability::ALL.iter().find(|ability| ability.stat_name() == stat.display_name) {
+            Some(ability) => Err(anyhow!("{}'s {} comes from their ability scores. Use something like `!set ability {} {}:{}` to change it.",
+                self.name, stat.display_name, quoted, stat.display_name, sheet.score(*ability))),
+            None if stat.display_name == "Level" => Err(anyhow!("{}'s Level is on their sheet, so it can only be changed with `!set stat`.", self.name)),
+            None => Err(anyhow!("{}'s {} comes from their proficiencies. Use something like `!set proficiency {} {}:expert` to change it.",
+                self.name, stat.display_name, quoted, sheet.proficiencies.iter().map(Proficiency::stat_prefix)
+                    .find(|prefix| stat.name().starts_with(&prefix.to_lowercase())).unwrap_or_else(|| stat.display_name.clone()).replace("_Spell", ""))),
+        }
+    }
+
+    /// The character's sheet, starting one if they don't have one yet. A new sheet takes their level and ability modifiers
+    /// from their stats, if they have them, since those are usually the first things added to a character.
+    fn sheet_or_new(&self) -> Result<Sheet> {
+        if let Some(sheet) = &self.sheet {
+            return Ok(sheet.clone());
+        }
+        let number = |name: &str| self.find_stat(name).and_then(|stat| stat.value.parse::<i64>().ok());
+        let level = number("Level")
+            .ok_or_else(|| anyhow!("{} needs a level before they can have a sheet. Add one with something like `!add stat {} Level:1`.", self.name, crate::omni::quote_name(&self.name)))?;
+        let mut abilities = [10; 6];
+        for (index, ability) in Ability::ALL.iter().enumerate() {
+            abilities[index] = number(&ability.stat_name()).map_or(10, |modifier| 10 + modifier * 2);
+        }
+        Ok(Sheet::new(level, abilities))
+    }
+
+    /// Change ability scores on the character's sheet, like `STR:18`, starting the sheet if they need one.
+    pub fn set_abilities(&mut self, stats: Vec<Stat>) -> Result<String> {
+        if stats.is_empty() {
+            return Err(anyhow!("No ability scores given to set. Try something like `!set ability {} STR:18`.", self.name));
+        }
+        let mut sheet = self.sheet_or_new()?;
+        for stat in stats.iter() {
+            let ability = Ability::parse(&stat.display_name)
+                .ok_or_else(|| anyhow!("{} isn't an ability. Use STR, DEX, CON, INT, WIS or CHA.", stat.display_name))?;
+            let score = stat.value.parse::<i64>()
+                .map_err(|_| anyhow!("'{}' isn't an ability score. Give the score itself, like `{}:18`.", stat.value, ability.stat_name()))?;
+            sheet.set_score(ability, score);
+        }
+        let changed: Vec<String> = stats.iter().filter_map(|stat| Ability::parse(&stat.display_name))
+            .map(|ability| format!("{} {} ({:+})", ability.stat_name(), sheet.score(ability), sheet.modifier(ability)))
+            .collect();
+        let replaced = self.set_sheet(sheet);
+        Ok(format!("Set {} for {}.{}", changed.join(", "), self.name, describe_replaced(&replaced)))
+    }
+
+    /// Change proficiency ranks on the character's sheet, like `Athletics:expert`, starting the sheet if they need one.
+    pub fn set_proficiencies(&mut self, stats: Vec<Stat>) -> Result<String> {
+        if stats.is_empty() {
+            return Err(anyhow!("No proficiencies given to set. Try something like `!set proficiency {} Athletics:expert`.", self.name));
+        }
+        let mut sheet = self.sheet_or_new()?;
+        let mut changed = Vec::new();
+        for stat in stats.iter() {
+            let rank = Rank::parse(&stat.value)?;
+            let proficiency = sheet.set_rank(&stat.display_name, rank)?.clone();
+            changed.push(match rank {
+                Rank::Untrained => format!("{} untrained", proficiency.name),
+                rank => format!("{} {} (+{})", proficiency.name, rank.to_string().to_lowercase(), sheet.proficiency_bonus(rank)),
+            });
+        }
+        let replaced = self.set_sheet(sheet);
+        Ok(format!("Set {} for {}.{}", changed.join(", "), self.name, describe_replaced(&replaced)))
+    }
+
+    /// Keep the sheet's level in step with the Level stat, after it's been set. Everything that depends on it is worked out again.
+    pub(super) fn level_changed(&mut self) -> Result<()> {
+        let mut sheet = match &self.sheet {
+            Some(sheet) => sheet.clone(),
+            None => return Ok(()),
+        };
+        let level = self.find_stat("Level").expect("Level went missing after setting it!");
+        sheet.level = level.value.parse::<i64>().map_err(|_| anyhow!("{}'s Level has to be a number, since their sheet depends on it.", self.name))?;
+        self.set_sheet(sheet);
+        Ok(())
+    }
+}
+
+/// Mention any of the character's own stats that the sheet took over, since their old values are gone.
+fn describe_replaced(replaced: &[String]) -> String {
+    match replaced {
+        [] => String::new(),
+        replaced => format!(" {} now come from their sheet.", replaced.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::omni::character::CharacterKind;
+
+    fn value(character: &Character, name: &str) -> String {
+        character.find_stat(name).unwrap_or_else(|| panic!("{} has no {}", character.name, name)).to_string()
+    }
+
+    fn plunk() -> Character {
+        let mut plunk = Character::new("Plunk", CharacterKind::Player, 1);
+        plunk.add_stats(vec![Stat::new("HP", String::from("30")), Stat::new("Level", String::from("3")), Stat::new("STR", String::from("4"))]).unwrap();
+        plunk
+    }
+
+    #[test]
+    fn ranks() {
+        assert_eq!(Rank::parse("Expert").unwrap(), Rank::Expert);
+        assert_eq!(Rank::parse("l").unwrap(), Rank::Legendary);
+        assert!(Rank::parse("grandmaster").is_err());
+        assert_eq!(Rank::from_bonus(4), Rank::Expert);
+        assert_eq!(Rank::from_bonus(0), Rank::Untrained);
+        assert_eq!(Rank::Master.bonus(), 6);
+
+        let sheet = Sheet::new(5, [10; 6]);
+        assert_eq!(sheet.proficiency_bonus(Rank::Untrained), 0);
+        assert_eq!(sheet.proficiency_bonus(Rank::Trained), 7);
+        assert_eq!(sheet.proficiency_bonus(Rank::Legendary), 13);
+        assert_eq!(modifier(18), 4);
+        assert_eq!(modifier(7), -2);
+    }
+
+    #[test]
+    fn sheets_are_started_from_stats() {
+        let mut plunk = plunk();
+        let response = plunk.set_proficiencies(vec![Stat::new("Athletics", String::from("expert"))]).unwrap();
+        assert_eq!(response, "Set Athletics expert (+7) for Plunk. Level, STR now come from their sheet.");
+        let sheet = plunk.sheet.as_ref().unwrap();
+        assert_eq!((sheet.level, sheet.score(Ability::Str), sheet.score(Ability::Dex)), (3, 18, 10));
+
+        assert_eq!(plunk.stats[0].to_string(), "Level: 3");
+        assert_eq!(value(&plunk, "STR"), "STR: 4");
+        assert_eq!(value(&plunk, "Athletics_Prof"), "Athletics_Prof: 7");
+        assert_eq!(value(&plunk, "Athletics"), "Athletics: =1d20+STR+Athletics_Prof");
+        assert_eq!(value(&plunk, "Stealth"), "Stealth: =1d20+DEX");
+        assert!(plunk.find_stat("Stealth_Prof").is_none());
+        // Their own stats carry on as they were.
+        assert_eq!(value(&plunk, "HP"), "HP: 30");
+        assert_eq!(plunk.stats.iter().filter(|stat| stat.name() == "level").count(), 1);
+
+        let mut nobody = Character::new("Nobody", CharacterKind::Player, 1);
+        assert!(nobody.set_abilities(vec![Stat::new("STR", String::from("18"))]).unwrap_err().to_string().contains("needs a level"));
+    }
+
+    #[test]
+    fn changing_the_level_changes_proficiencies() {
+        let mut plunk = plunk();
+        plunk.set_proficiencies(vec![Stat::new("Athletics", String::from("e")), Stat::new("Martial", String::from("t"))]).unwrap();
+        plunk.set_stats(vec![Stat::new("Level", String::from("Level+1"))]).unwrap();
+        assert_eq!(plunk.sheet.as_ref().unwrap().level, 4);
+        assert_eq!(value(&plunk, "Athletics_Prof"), "Athletics_Prof: 8");
+        assert_eq!(value(&plunk, "Martial_Prof"), "Martial_Prof: 6");
+        assert_eq!(crate::omni::character::formula::resolve(&plunk, "Athletics").unwrap(), "(1d20+4+8)");
+
+        assert!(plunk.set_stats(vec![Stat::new("Level", String::from("\"four\""))]).unwrap_err().to_string().contains("has to be a number"));
+        assert_eq!(value(&plunk, "Level"), "Level: 4");
+    }
+
+    #[test]
+    fn abilities() {
+        let mut plunk = plunk();
+        assert_eq!(plunk.set_abilities(vec![Stat::new("dex", String::from("16")), Stat::new("Charisma", String::from("7"))]).unwrap(),
+            "Set DEX 16 (+3), CHA 7 (-2) for Plunk. Level, STR now come from their sheet.");
+        assert_eq!(value(&plunk, "DEX"), "DEX: 3");
+        assert_eq!(value(&plunk, "STR"), "STR: 4");
+        assert!(plunk.set_abilities(vec![Stat::new("Luck", String::from("12"))]).is_err());
+        assert!(plunk.set_abilities(vec![Stat::new("STR", String::from("4d6"))]).unwrap_err().to_string().contains("isn't an ability score"));
+    }
+
+    #[test]
+    fn new_proficiencies() {
+        let mut tilly = plunk();
+        tilly.set_abilities(vec![Stat::new("WIS", String::from("20")), Stat::new("CHA", String::from("14"))]).unwrap();
+        tilly.set_proficiencies(vec![
+            Stat::new("Divine", String::from("trained")),
+            Stat::new("Sarenrae_Lore", String::from("trained")),
+            Stat::new("class", String::from("trained")),
+        ]).unwrap();
+        assert_eq!(value(&tilly, "Divine_Spell_Prof"), "Divine_Spell_Prof: 5");
+        assert_eq!(value(&tilly, "Divine_Spell_DC"), "Divine_Spell_DC: =10+WIS+Divine_Spell_Prof");
+        assert_eq!(value(&tilly, "Divine_Spell_Attack"), "Divine_Spell_Attack: =1d20+WIS+Divine_Spell_Prof");
+        assert_eq!(value(&tilly, "Sarenrae_Lore"), "Sarenrae_Lore: =1d20+INT+Sarenrae_Lore_Prof");
+        assert_eq!(value(&tilly, "Class_DC"), "Class_DC: =10+WIS+Class_Prof");
+        assert!(tilly.set_proficiencies(vec![Stat::new("Juggling", String::from("trained"))]).is_err());
+
+        tilly.set_proficiencies(vec![Stat::new("Divine", String::from("untrained"))]).unwrap();
+        assert!(tilly.find_stat("Divine_Spell_DC").is_none());
+        assert!(tilly.sheet.as_ref().unwrap().describe().starts_with("Sarenrae_Lore trained"));
+    }
+
+    #[test]
+    fn derived_stats_are_protected() {
+        let mut plunk = plunk();
+        plunk.set_proficiencies(vec![Stat::new("Divine", String::from("trained"))]).unwrap();
+        let error = plunk.set_stats(vec![Stat::new("STR", String::from("5"))]).unwrap_err().to_string();
+        assert_eq!(error, "Plunk's STR comes from their ability scores. Use something like `!set ability Plunk STR:18` to change it.");
+        let error = plunk.remove_stats(&[String::from("Divine_Spell_DC")]).unwrap_err().to_string();
+        assert!(error.contains("`!set proficiency Plunk Divine:expert`"), "{}", error);
+        assert!(plunk.rename_stat("Athletics", "Climbing").is_err());
+        assert!(plunk.remove_stats(&[String::from("Level")]).is_err());
+
+        // Stats that aren't on the sheet work just like before.
+        plunk.add_stats(vec![Stat::new("Hero_Points", String::from("1"))]).unwrap();
+        plunk.set_stats(vec![Stat::new("Hero_Points", String::from("2"))]).unwrap();
+        plunk.remove_stats(&[String::from("Hero_Points")]).unwrap();
+    }
+
+    #[test]
+    fn tracked_stats_stay_on_the_tracker() {
+        let mut plunk = plunk();
+        plunk.set_stats(vec![Stat { display_name: String::from("Level"), display_on_tracker: true, value: String::from("3"), maximum_value: None }]).unwrap();
+        plunk.set_proficiencies(vec![Stat::new("Athletics", String::from("trained"))]).unwrap();
+        assert!(plunk.find_stat("Level").unwrap().display_on_tracker);
+        assert!(!plunk.find_stat("Athletics").unwrap().display_on_tracker);
+    }
+}
@@ -1,7 +1,7 @@
-use crate::omni::character::Character;
+use std::fmt;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Stat {
     pub(crate) display_name: String,
     pub(crate) display_on_tracker: bool,
@@ -10,7 +10,34 @@ pub struct Stat {
 }
 
 impl Stat {
+    /// A stat that isn't shown on the tracker and has no maximum.
+    pub fn new(name: &str, value: String) -> Self {
+        Stat {
+            display_name: name.to_string(),
+            display_on_tracker: false,
+            value,
+            maximum_value: None,
+        }
+    }
+
     pub fn name(&self) -> String {
         self.display_name.to_lowercase()
     }
-}
\ No newline at end of file
+
+    /// Dynamic stats store a formula, marked by a leading equals sign, that is worked out each time it's used.
+    /// Returns the formula without the equals sign, or None for static stats.
+    pub fn formula(&self) -> Option<&str> {
+        self.value.strip_prefix('=')
+    }
+}
+
+/// Formats the stat for showing to users, for example `HP: 20/30`.
+impl fmt::Display for Stat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.display_name, self.value)?;
+        if let Some(maximum_value) = &self.maximum_value {
+            write!(f, "/{}", maximum_value)?;
+        }
+        Ok(())
+    }
+}
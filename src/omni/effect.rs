@@ -0,0 +1,434 @@
+//! Effects are named, temporary things affecting a character, like Bless or a lingering spell.
+//! Each one refers to the character it's on, and whoever caused it, by their character IDs so it survives renames.
+//! Durations count down the way Pathfinder's do, on the turns of whoever created the effect
+//! (or the target's turns, if nobody in particular did), so they expire on their own as an encounter advances.
+
+use std::fmt;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use super::Omnidata;
+use super::gm::Author;
+use super::character::modifier::{Modifier, ModifierKind};
+
+/// How many rounds there are in a minute.
+pub const ROUNDS_PER_MINUTE: u32 = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Duration {
+    /// Goes down by one at the start of each of the creator's turns, ending when it reaches 0.
+    Rounds(u32),
+    /// Lasts until the end of the creator's next turn. A turn only counts if it started after the effect was made,
+    /// so an effect made during the creator's own turn lasts through the end of their following one.
+    EndOfNextTurn { turn_started: bool },
+    /// Lasts until the end of the creator's next turn, unless they sustain it during that turn.
+    Sustained { turn_started: bool },
+    /// Lasts until someone removes it.
+    Unlimited,
+}
+
+impl Duration {
+    /// Read a duration like `3 rounds`, `1 minute`, `until end of next turn` or `sustained`. No duration at all means it lasts until removed.
+    pub fn parse(words: &[String]) -> Result<Self> {
+        let text = words.join(" ").to_lowercase();
+        let filler = ["until", "the", "your", "their"];
+        let meaningful: Vec<&str> = text.split_whitespace().filter(|word| !filler.contains(word)).collect();
+        match meaningful.as_slice() {
+            [] => Ok(Duration::Unlimited),
+            ["sustained"] => Ok(Duration::Sustained { turn_started: false }),
+            ["end", "of", "next", "turn"] => Ok(Duration::EndOfNextTurn { turn_started: false }),
+            [amount, unit] => {
+                let amount = amount.parse::<u32>().ok().filter(|&amount| amount > 0)
+                    .ok_or_else(|| anyhow!("'{}' isn't a number of {}.", amount, unit))?;
+                match *unit {
+                    "round" | "rounds" => Ok(Duration::Rounds(amount)),
+                    "minute" | "minutes" => Ok(Duration::Rounds(amount * ROUNDS_PER_MINUTE)),
+                    unknown => Err(anyhow!("Durations can be in rounds or minutes, not {}.", unknown)),
+                }
+            },
+            _ => Err(anyhow!("Didn't understand '{}' as a duration. Try `3 rounds`, `1 minute`, `until end of next turn` or `sustained`.", text)),
+        }
+    }
+}
+
+/// Formats the duration for showing to users, for example `1 minute` or `sustained`.
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Duration::Rounds(rounds) if rounds % ROUNDS_PER_MINUTE == 0 => {
+                let minutes = rounds / ROUNDS_PER_MINUTE;
+                write!(f, "{} minute{}", minutes, if minutes == 1 { "" } else { "s" })
+            },
+            Duration::Rounds(rounds) => write!(f, "{} round{}", rounds, if *rounds == 1 { "" } else { "s" }),
+            Duration::EndOfNextTurn { .. } => write!(f, "until end of next turn"),
+            Duration::Sustained { .. } => write!(f, "sustained"),
+            Duration::Unlimited => write!(f, "until removed"),
+        }
+    }
+}
+
+/// A bonus or penalty an effect gives, like Bless's `+1 status to attack`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EffectModifier {
+    pub kind: ModifierKind,
+    pub value: i64,
+    /// Words naming the stats it applies to, matched against the words of the stats being rolled.
+    /// With none, it applies to every check.
+    pub stats: Vec<String>,
+}
+
+impl EffectModifier {
+    /// Read a modifier like `+1 status to attack` or `-2 to reflex perception` from the start of some words,
+    /// returning it along with the words after it. Without a type, it's untyped.
+    /// The stats it applies to run until the next modifier, a `from`, or the start of a duration.
+    pub fn parse(words: &[String]) -> Result<(Self, &[String])> {
+        let (first, mut rest) = words.split_first().ok_or_else(|| anyhow!("Expected a bonus or penalty, like `+1 status`."))?;
+        let value = first.parse::<i64>().ok().filter(|_| is_signed_number(first))
+            .ok_or_else(|| anyhow!("'{}' isn't a bonus or penalty. They look like `+1` or `-2`.", first))?;
+        let mut kind = ModifierKind::Untyped;
+        if let Some(parsed) = rest.first().and_then(|word| ModifierKind::parse(word)) {
+            kind = parsed;
+            rest = &rest[1..];
+        }
+        let mut stats = Vec::new();
+        if rest.first().is_some_and(|word| word.eq_ignore_ascii_case("to")) {
+            let length = rest[1..].iter().position(|word| ends_stats(word)).unwrap_or(rest.len() - 1);
+            stats = rest[1..=length].iter().map(|word| word.to_lowercase()).collect();
+            if stats.is_empty() {
+                return Err(anyhow!("What does the {:+} apply to? Try something like `{:+} {} to attack`.", value, value, kind));
+            }
+            rest = &rest[length + 1..];
+        }
+        Ok((EffectModifier { kind, value, stats }, rest))
+    }
+
+    fn applies_to(&self, words: &[String], is_check: bool) -> bool {
+        match self.stats.is_empty() {
+            true => is_check,
+            false => words.iter().any(|word| self.stats.contains(word)),
+        }
+    }
+}
+
+/// Formats the modifier for showing to users, for example `+1 status to attack` or `-1 untyped to checks`.
+impl fmt::Display for EffectModifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.stats.is_empty() {
+            true => write!(f, "{:+} {} to checks", self.value, self.kind),
+            false => write!(f, "{:+} {} to {}", self.value, self.kind, self.stats.join(" ")),
+        }
+    }
+}
+
+/// Bonuses and penalties are written with their sign, so they can't be mistaken for the number in a duration.
+pub fn is_signed_number(word: &str) -> bool {
+    (word.starts_with('+') || word.starts_with('-')) && word.len() > 1 && word[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+fn ends_stats(word: &str) -> bool {
+    is_signed_number(word) || word.starts_with(|c: char| c.is_ascii_digit())
+        || ["from", "until", "sustained"].contains(&word.to_lowercase().as_str())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Effect {
+    pub name: String,
+    /// The ID of the character the effect is on.
+    pub target: u64,
+    /// The ID of the character who caused the effect, if anyone did.
+    pub source: Option<u64>,
+    pub duration: Duration,
+    pub modifiers: Vec<EffectModifier>,
+}
+
+impl Effect {
+    /// The character whose turns the duration counts on.
+    fn timekeeper(&self) -> u64 {
+        self.source.unwrap_or(self.target)
+    }
+
+    fn is_named(&self, name: &str) -> bool {
+        self.name.to_lowercase() == name.to_lowercase()
+    }
+
+    /// The effect's bonuses and penalties that apply to a roll. The words are from the names of the stats involved,
+    /// and a roll is a check if it rolls a d20.
+    pub fn roll_modifiers(&self, words: &[String], is_check: bool) -> Vec<Modifier> {
+        self.modifiers.iter()
+            .filter(|modifier| modifier.applies_to(words, is_check))
+            .map(|modifier| Modifier { source: self.name.clone(), kind: modifier.kind, value: modifier.value })
+            .collect()
+    }
+
+    /// The duration and any modifiers, for showing after the effect's name.
+    fn describe_details(&self) -> String {
+        let mut details = vec![self.duration.to_string()];
+        details.extend(self.modifiers.iter().map(|modifier| modifier.to_string()));
+        details.join(", ")
+    }
+}
+
+impl Omnidata {
+    /// Put an effect on a character. Effects with the same name don't stack, so adding one again just replaces it.
+    pub fn add_effect(&mut self, effect: Effect) -> String {
+        let response = format!("{} now has {} ({}{}).", self.character_name(effect.target), effect.name, effect.describe_details(),
+            effect.source.filter(|&source| source != effect.target).map(|source| format!(", from {}", self.character_name(source))).unwrap_or_default());
+        self.effects.retain(|existing| existing.target != effect.target || !existing.is_named(&effect.name));
+        self.effects.push(effect);
+        self.dirty();
+        response
+    }
+
+    pub fn remove_effect(&mut self, target: u64, name: &str, author: &Author) -> Result<String> {
+        let index = self.effects.iter().position(|effect| effect.target == target && effect.is_named(name))
+            .ok_or_else(|| anyhow!("{} doesn't have an effect named {}.", self.character_name(target), name))?;
+        self.ensure_effect_editable_by(&self.effects[index], author)?;
+        let removed = self.effects.remove(index);
+        self.dirty();
+        Ok(format!("{} no longer has {}.", self.character_name(target), removed.name))
+    }
+
+    /// Keep a sustained effect going through the end of its creator's next turn.
+    pub fn sustain_effect(&mut self, target: u64, name: &str, author: &Author) -> Result<String> {
+        let target_name = self.character_name(target);
+        let index = self.effects.iter().position(|effect| effect.target == target && effect.is_named(name))
+            .ok_or_else(|| anyhow!("{} doesn't have an effect named {}.", target_name, name))?;
+        self.ensure_effect_editable_by(&self.effects[index], author)?;
+        let effect = &mut self.effects[index];
+        match &mut effect.duration {
+            Duration::Sustained { turn_started } => *turn_started = false,
+            _ => return Err(anyhow!("{} on {} isn't sustained, so there's no need to sustain it.", effect.name, target_name)),
+        }
+        let response = format!("{} on {} is sustained until the end of its creator's next turn.", effect.name, target_name);
+        self.dirty();
+        Ok(response)
+    }
+
+    /// Anyone can add an effect, but only a GM, the owner of the character it's on, or the owner of whoever caused it
+    /// can sustain or remove it.
+    fn ensure_effect_editable_by(&self, effect: &Effect, author: &Author) -> Result<()> {
+        let owns = |id: u64| self.find_character_by_id(id).is_some_and(|character| character.owner == author.id);
+        if author.is_gm || owns(effect.target) || effect.source.is_some_and(owns) {
+            return Ok(());
+        }
+        Err(anyhow!("Only a GM or the owner of {} or whoever caused it can change {}.", self.character_name(effect.target), effect.name))
+    }
+
+    /// Count down durations for the start of a character's turn. Returns a message for each effect that ended.
+    pub fn start_turn(&mut self, character: u64) -> Vec<String> {
+        let mut changed = false;
+        for effect in self.effects.iter_mut().filter(|effect| effect.timekeeper() == character) {
+            match &mut effect.duration {
+                Duration::Rounds(rounds) => *rounds = rounds.saturating_sub(1),
+                Duration::EndOfNextTurn { turn_started } | Duration::Sustained { turn_started } => *turn_started = true,
+                Duration::Unlimited => continue,
+            }
+            changed = true;
+        }
+        if changed {
+            self.dirty();
+        }
+        self.expire(|effect| matches!(effect.duration, Duration::Rounds(0)))
+    }
+
+    /// Finish off effects that last until the end of a character's turn, and let their conditions wear off.
+    /// Returns a message for each thing that changed.
+    pub fn end_turn(&mut self, character: u64) -> Vec<String> {
+        let mut messages = self.expire(|effect| effect.timekeeper() == character && matches!(effect.duration,
+            Duration::EndOfNextTurn { turn_started: true } | Duration::Sustained { turn_started: true }));
+        let worn_off = match self.characters.iter_mut().find(|found| found.id == character) {
+            Some(found) => found.end_turn_conditions(),
+            None => Vec::new(),
+        };
+        if !worn_off.is_empty() {
+            self.dirty();
+            messages.extend(worn_off);
+        }
+        messages
+    }
+
+    fn expire<F: Fn(&Effect) -> bool>(&mut self, has_ended: F) -> Vec<String> {
+        let (ended, remaining): (Vec<Effect>, Vec<Effect>) = self.effects.drain(..).partition(|effect| has_ended(effect));
+        self.effects = remaining;
+        if !ended.is_empty() {
+            self.dirty();
+        }
+        ended.iter().map(|effect| format!("{} on {} has ended.", effect.name, self.character_name(effect.target))).collect()
+    }
+
+    /// Everything affecting a character, for showing to users. Empty if nothing is.
+    pub fn describe_effects(&self, target: u64) -> String {
+        let effects: Vec<String> = self.effects.iter()
+            .filter(|effect| effect.target == target)
+            .map(|effect| format!("{} ({})", effect.name, effect.describe_details()))
+            .collect();
+        if effects.is_empty() {
+            return String::new();
+        }
+        format!("Effects: {}", effects.join(", "))
+    }
+
+    /// The effects on a character, for working out what modifies their rolls.
+    pub fn effects_on(&self, target: u64) -> Vec<&Effect> {
+        self.effects.iter().filter(|effect| effect.target == target).collect()
+    }
+
+    /// Drop any effects on a character, and forget them as the source of any others, when they leave.
+    pub fn forget_character_effects(&mut self, character: u64) {
+        self.effects.retain(|effect| effect.target != character);
+        for effect in self.effects.iter_mut().filter(|effect| effect.source == Some(character)) {
+            effect.source = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::omni::character::{Character, CharacterKind};
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    const GM: Author = Author { id: 0, is_gm: true };
+
+    /// Plunk is 1 and Frank is 2, each owned by the user with the same ID.
+    fn omnidata() -> Omnidata {
+        let mut omnidata = Omnidata::new();
+        omnidata.insert_character(Character::new("Plunk", CharacterKind::Player, 1));
+        omnidata.insert_character(Character::new("Frank", CharacterKind::Player, 2));
+        omnidata
+    }
+
+    fn effect(name: &str, source: Option<u64>, duration: Duration) -> Effect {
+        Effect { name: name.to_string(), target: 1, source, duration, modifiers: Vec::new() }
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(Duration::parse(&[]).unwrap(), Duration::Unlimited);
+        assert_eq!(Duration::parse(&words("3 rounds")).unwrap(), Duration::Rounds(3));
+        assert_eq!(Duration::parse(&words("1 Minute")).unwrap(), Duration::Rounds(10));
+        assert_eq!(Duration::parse(&words("until the end of your next turn")).unwrap(), Duration::EndOfNextTurn { turn_started: false });
+        assert_eq!(Duration::parse(&words("sustained")).unwrap(), Duration::Sustained { turn_started: false });
+        assert!(Duration::parse(&words("0 rounds")).is_err());
+        assert!(Duration::parse(&words("2 hours")).is_err());
+        assert!(Duration::parse(&words("forever and ever")).is_err());
+        assert_eq!(Duration::Rounds(20).to_string(), "2 minutes");
+        assert_eq!(Duration::Rounds(1).to_string(), "1 round");
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut omnidata = omnidata();
+        assert_eq!(omnidata.add_effect(effect("Bless", Some(2), Duration::Rounds(10))), "Plunk now has Bless (1 minute, from Frank).");
+        omnidata.add_effect(effect("bless", Some(2), Duration::Rounds(3)));
+        assert_eq!(omnidata.describe_effects(1), "Effects: bless (3 rounds)");
+        assert!(omnidata.remove_effect(2, "Bless", &GM).is_err());
+        assert_eq!(omnidata.remove_effect(1, "BLESS", &GM).unwrap(), "Plunk no longer has bless.");
+        assert!(omnidata.effects.is_empty());
+    }
+
+    #[test]
+    fn who_can_change_effects() {
+        let mut omnidata = omnidata();
+        omnidata.add_effect(effect("Light", Some(2), Duration::Sustained { turn_started: false }));
+        omnidata.add_effect(effect("Frightened", None, Duration::Rounds(2)));
+        let stranger = Author { id: 3, is_gm: false };
+        assert_eq!(omnidata.remove_effect(1, "light", &stranger).unwrap_err().to_string(), "Only a GM or the owner of Plunk or whoever caused it can change Light.");
+        assert!(omnidata.sustain_effect(1, "light", &stranger).is_err());
+        assert!(omnidata.remove_effect(1, "frightened", &Author { id: 2, is_gm: false }).is_err());
+        assert!(omnidata.sustain_effect(1, "light", &Author { id: 2, is_gm: false }).is_ok());
+        assert!(omnidata.remove_effect(1, "frightened", &Author { id: 1, is_gm: false }).is_ok());
+        assert_eq!(omnidata.effects.len(), 1);
+    }
+
+    #[test]
+    fn rounds_count_on_the_source_turn() {
+        let mut omnidata = omnidata();
+        omnidata.add_effect(effect("Bless", Some(2), Duration::Rounds(2)));
+        omnidata.add_effect(effect("Frightened", None, Duration::Rounds(1)));
+        assert!(omnidata.start_turn(2).is_empty());
+        assert_eq!(omnidata.start_turn(1), vec!["Frightened on Plunk has ended."]);
+        assert_eq!(omnidata.start_turn(2), vec!["Bless on Plunk has ended."]);
+    }
+
+    #[test]
+    fn end_of_next_turn() {
+        let mut omnidata = omnidata();
+        // Made during Frank's turn, so it lasts through the end of his next one.
+        omnidata.start_turn(2);
+        omnidata.add_effect(effect("Shield", Some(2), Duration::EndOfNextTurn { turn_started: false }));
+        assert!(omnidata.end_turn(2).is_empty());
+        omnidata.start_turn(1);
+        assert!(omnidata.end_turn(1).is_empty());
+        omnidata.start_turn(2);
+        assert_eq!(omnidata.end_turn(2), vec!["Shield on Plunk has ended."]);
+    }
+
+    #[test]
+    fn conditions_wear_off() {
+        let mut omnidata = omnidata();
+        omnidata.characters[0].add_condition("frightened", Some("1")).unwrap();
+        assert!(omnidata.end_turn(2).is_empty());
+        assert_eq!(omnidata.end_turn(1), vec!["Plunk is no longer frightened."]);
+        assert!(omnidata.is_dirty);
+    }
+
+    #[test]
+    fn sustained() {
+        let mut omnidata = omnidata();
+        omnidata.add_effect(effect("Light", Some(2), Duration::Sustained { turn_started: false }));
+        omnidata.start_turn(2);
+        omnidata.sustain_effect(1, "light", &GM).unwrap();
+        assert!(omnidata.end_turn(2).is_empty());
+        omnidata.start_turn(2);
+        assert_eq!(omnidata.end_turn(2), vec!["Light on Plunk has ended."]);
+
+        omnidata.add_effect(effect("Bless", Some(2), Duration::Rounds(1)));
+        assert!(omnidata.sustain_effect(1, "bless", &GM).is_err());
+    }
+
+    #[test]
+    fn forget_characters() {
+        let mut omnidata = omnidata();
+        omnidata.add_effect(effect("Bless", Some(2), Duration::Rounds(1)));
+        omnidata.add_effect(Effect { name: String::from("Slowed"), target: 2, source: Some(1), duration: Duration::Unlimited, modifiers: Vec::new() });
+        omnidata.forget_character_effects(2);
+        assert_eq!(omnidata.effects, vec![effect("Bless", None, Duration::Rounds(1))]);
+    }
+
+    #[test]
+    fn parse_modifiers() {
+        let bless = words("+1 status to attack damage 1 minute");
+        let (modifier, rest) = EffectModifier::parse(&bless).unwrap();
+        assert_eq!(modifier, EffectModifier { kind: ModifierKind::Status, value: 1, stats: vec![String::from("attack"), String::from("damage")] });
+        assert_eq!(rest, &bless[5..]);
+        assert_eq!(modifier.to_string(), "+1 status to attack damage");
+
+        let penalty = words("-2");
+        let (modifier, rest) = EffectModifier::parse(&penalty).unwrap();
+        assert_eq!(modifier.to_string(), "-2 untyped to checks");
+        assert!(rest.is_empty());
+        assert!(EffectModifier::parse(&words("+1 circumstance to from Frank")).is_err());
+        assert!(EffectModifier::parse(&words("1 minute")).is_err());
+    }
+
+    #[test]
+    fn modifiers_apply_to_rolls() {
+        let mut omnidata = omnidata();
+        let mut bless = effect("Bless", Some(2), Duration::Rounds(10));
+        bless.modifiers.push(EffectModifier { kind: ModifierKind::Status, value: 1, stats: vec![String::from("attack")] });
+        assert_eq!(omnidata.add_effect(bless), "Plunk now has Bless (1 minute, +1 status to attack, from Frank).");
+        let mut slowed = effect("Clumsy", None, Duration::Unlimited);
+        slowed.modifiers.push(EffectModifier { kind: ModifierKind::Status, value: -1, stats: Vec::new() });
+        omnidata.add_effect(slowed);
+
+        let effects = omnidata.effects_on(1);
+        let attack = words("longsword attack");
+        assert_eq!(effects[0].roll_modifiers(&attack, false).len(), 1);
+        assert!(effects[0].roll_modifiers(&words("reflex"), true).is_empty());
+        assert_eq!(effects[1].roll_modifiers(&words("reflex"), true).len(), 1);
+        assert!(effects[1].roll_modifiers(&attack, false).is_empty());
+    }
+}
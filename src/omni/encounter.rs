@@ -0,0 +1,429 @@
+//! An encounter is a fight (or anything else) that happens in turns. Everyone in it has an initiative,
+//! and turns go from highest to lowest, starting again from the top each round.
+//! Moving from one turn to the next is what makes effects count down and conditions like frightened wear off.
+//! Combatants are always kept in turn order, and the current turn is kept by character ID so changes to the order don't lose track of it.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use super::Omnidata;
+use super::character::{CharacterKind, formula};
+use super::character::condition::ConditionKind;
+
+/// The stats that are rolled for initiative, in order of preference. Pathfinder usually has everyone roll perception.
+const INITIATIVE_STATS: [&str; 2] = ["initiative", "perception"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Combatant {
+    /// The ID of the character taking part.
+    pub character: u64,
+    /// Nobody can take a turn until everyone has one of these.
+    pub initiative: Option<i64>,
+    /// Delaying combatants are skipped until they choose to come back into the order.
+    pub delaying: bool,
+    /// The trigger for an action they've readied, if any. Lasts until the start of their next turn.
+    pub readied: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Encounter {
+    /// Everyone taking part, in turn order.
+    pub combatants: Vec<Combatant>,
+    /// Starts at 0, and becomes 1 when the first turn starts.
+    pub round: u32,
+    /// The ID of the character whose turn it is, if anyone's turn has started.
+    pub current: Option<u64>,
+    /// Whether the current turn has already ended, like when someone delays or leaves partway through it.
+    pub turn_over: bool,
+}
+
+impl Encounter {
+    fn position(&self, character: u64) -> Option<usize> {
+        self.combatants.iter().position(|combatant| combatant.character == character)
+    }
+
+    fn combatant_mut(&mut self, character: u64) -> Option<&mut Combatant> {
+        self.combatants.iter_mut().find(|combatant| combatant.character == character)
+    }
+}
+
+impl Omnidata {
+    /// Start an encounter with the given characters, rolling initiative for anyone who has a stat for it.
+    pub fn start_encounter(&mut self, participants: &[u64]) -> Result<String> {
+        if self.encounter.is_some() {
+            return Err(anyhow!("There's already an encounter going. Use `!end encounter` to finish it first."));
+        }
+        if participants.is_empty() {
+            return Err(anyhow!("There's nobody to start an encounter with. Add some characters with `!add player` or `!add enemy` first."));
+        }
+        self.encounter = Some(Encounter { combatants: Vec::new(), round: 0, current: None, turn_over: false });
+        let mut response = String::from("The encounter has started!");
+        for &character in participants {
+            let initiative = self.roll_initiative(character);
+            self.place_combatant(character, initiative);
+        }
+        response.push_str(&format!("\n{}", self.describe_encounter()));
+        response.push_str(&self.describe_missing_initiative().unwrap_or_else(|| String::from("\nUse `!next` to start the first turn.")));
+        self.dirty();
+        Ok(response)
+    }
+
+    /// Set a character's initiative, adding them to the encounter if they aren't in it yet. With no initiative given, it's rolled.
+    pub fn set_initiative(&mut self, character: u64, initiative: Option<i64>) -> Result<String> {
+        self.encounter.as_ref().ok_or_else(no_encounter)?;
+        let initiative = match initiative {
+            Some(initiative) => initiative,
+            None => self.roll_initiative(character)
+                .ok_or_else(|| anyhow!("{} doesn't have an Initiative or Perception stat to roll. Give a number instead, like `!init {} 17`.", self.character_name(character), self.character_name(character)))?,
+        };
+        self.place_combatant(character, Some(initiative));
+        self.dirty();
+        let mut response = format!("{}'s initiative is {}.\n{}", self.character_name(character), initiative, self.describe_encounter());
+        response.push_str(&self.describe_missing_initiative().unwrap_or_default());
+        Ok(response)
+    }
+
+    /// End the current turn and start the next one. The next turn normally goes to whoever is next in the order,
+    /// but a delaying combatant can step back in and take it instead, taking up that spot in the order from now on.
+    pub fn next_turn(&mut self, returning: Option<u64>) -> Result<String> {
+        let encounter = self.encounter.as_ref().ok_or_else(no_encounter)?;
+        if let Some(missing) = self.describe_missing_initiative() {
+            return Err(anyhow!("Everyone needs an initiative before the first turn.{}", missing));
+        }
+        let current = encounter.current;
+        let turn_over = encounter.turn_over;
+        let next = match returning {
+            Some(returning) => {
+                let combatant = encounter.combatants.iter().find(|combatant| combatant.character == returning)
+                    .ok_or_else(|| anyhow!("{} isn't in the encounter.", self.character_name(returning)))?;
+                if !combatant.delaying {
+                    return Err(anyhow!("{} isn't delaying, so they'll get their turn when it comes.", self.character_name(returning)));
+                }
+                returning
+            },
+            None => {
+                let start = current.and_then(|current| encounter.position(current)).map(|index| index + 1).unwrap_or(0);
+                let waiting = encounter.combatants[start..].iter().chain(encounter.combatants[..start].iter())
+                    .find(|combatant| !combatant.delaying)
+                    .ok_or_else(|| anyhow!("Everyone is delaying, so nobody can take the next turn. Bring someone back with `!next <name>`."))?;
+                waiting.character
+            },
+        };
+
+        let mut messages = Vec::new();
+        if let Some(current) = current.filter(|_| !turn_over) {
+            messages.extend(self.end_turn(current));
+        }
+        let encounter = self.encounter.as_mut().expect("The encounter went missing during a turn!");
+        let new_round = match returning {
+            Some(returning) => {
+                // Stepping back in puts them right after whoever's turn just ended, at the same initiative.
+                let index = encounter.position(returning).expect("Delaying combatant went missing!");
+                let mut combatant = encounter.combatants.remove(index);
+                combatant.delaying = false;
+                let (after, initiative) = match current.and_then(|current| encounter.position(current)) {
+                    Some(after) => (after + 1, encounter.combatants[after].initiative),
+                    None => (0, encounter.combatants.first().and_then(|first| first.initiative)),
+                };
+                combatant.initiative = initiative.or(combatant.initiative);
+                encounter.combatants.insert(after, combatant);
+                encounter.round == 0
+            },
+            None => match current.and_then(|current| encounter.position(current)) {
+                Some(current) => encounter.position(next).is_some_and(|next| next <= current),
+                None => encounter.round == 0,
+            },
+        };
+        if new_round {
+            encounter.round += 1;
+        }
+        encounter.current = Some(next);
+        encounter.turn_over = false;
+        let round = encounter.round;
+        let readied = encounter.combatant_mut(next).and_then(|combatant| combatant.readied.take());
+
+        let mut response = String::new();
+        if new_round {
+            response.push_str(&format!("**Round {}**\n", round));
+        }
+        response.push_str(&format!("It's {}'s turn.", self.character_name(next)));
+        if let Some(trigger) = readied {
+            messages.push(format!("{}'s readied action ({}) wasn't used.", self.character_name(next), trigger));
+        }
+        messages.extend(self.start_turn(next));
+        if let Some(dying) = self.find_character_by_id(next).and_then(|character| character.condition_value(ConditionKind::Dying)) {
+            messages.push(format!("{} is dying {} and needs to make a recovery check: a flat check against DC {}.", self.character_name(next), dying, 10 + dying));
+        }
+        for message in messages {
+            response.push_str(&format!("\n{}", message));
+        }
+        self.dirty();
+        Ok(response)
+    }
+
+    /// Hold off the current combatant's turn until they choose to come back in with `!next <name>`.
+    /// Only whoever's turn it is can delay, and their turn ends as they do.
+    pub fn delay_turn(&mut self, character: Option<u64>) -> Result<String> {
+        let encounter = self.encounter.as_ref().ok_or_else(no_encounter)?;
+        let current = encounter.current.ok_or_else(|| anyhow!("Nobody's turn has started yet. Use `!next` to start the first one."))?;
+        if character.is_some_and(|character| character != current) {
+            return Err(anyhow!("Only the combatant whose turn it is can delay, and it's {}'s turn.", self.character_name(current)));
+        }
+        let messages = self.end_turn(current);
+        let encounter = self.encounter.as_mut().expect("The encounter went missing during a turn!");
+        encounter.turn_over = true;
+        if let Some(combatant) = encounter.combatant_mut(current) {
+            combatant.delaying = true;
+        }
+        let name = self.character_name(current);
+        let mut response = format!("{} is delaying. Bring them back in with `!next {}`.", name, name);
+        for message in messages {
+            response.push_str(&format!("\n{}", message));
+        }
+        response.push('\n');
+        response.push_str(&self.next_turn(None)?);
+        Ok(response)
+    }
+
+    /// Note that a combatant has readied an action, or clear it if no trigger is given.
+    pub fn ready_action(&mut self, character: u64, trigger: Option<String>) -> Result<String> {
+        let name = self.character_name(character);
+        let encounter = self.encounter.as_mut().ok_or_else(no_encounter)?;
+        let combatant = encounter.combatant_mut(character).ok_or_else(|| anyhow!("{} isn't in the encounter.", name))?;
+        let response = match &trigger {
+            Some(trigger) => format!("{} has readied an action for when {}.", name, trigger),
+            None if combatant.readied.is_some() => format!("{} has used or dropped their readied action.", name),
+            None => return Err(anyhow!("{} doesn't have a readied action. Ready one with something like `!ready {} the door opens`.", name, name)),
+        };
+        combatant.readied = trigger;
+        self.dirty();
+        Ok(response)
+    }
+
+    pub fn end_encounter(&mut self) -> Result<String> {
+        let encounter = self.encounter.take().ok_or_else(no_encounter)?;
+        self.dirty();
+        Ok(format!("The encounter is over after {} round{}.", encounter.round, if encounter.round == 1 { "" } else { "s" }))
+    }
+
+    /// The character whose turn it is, if there's an encounter going and someone's turn has started.
+    pub fn active_combatant(&self) -> Option<u64> {
+        self.encounter.as_ref().and_then(|encounter| encounter.current)
+    }
+
+    /// The turn order, for showing to users. An arrow marks whose turn it is.
+    pub fn describe_encounter(&self) -> String {
+        let encounter = match &self.encounter {
+            Some(encounter) => encounter,
+            None => return String::from("There's no encounter going."),
+        };
+        let mut description = match encounter.round {
+            0 => String::from("**Initiative**"),
+            round => format!("**Round {}**", round),
+        };
+        for combatant in encounter.combatants.iter() {
+            let marker = if encounter.current == Some(combatant.character) { "➤" } else { "  " };
+            let initiative = combatant.initiative.map(|initiative| initiative.to_string()).unwrap_or_else(|| String::from("?"));
+            description.push_str(&format!("\n{} {} {}", marker, initiative, self.character_name(combatant.character)));
+            if combatant.delaying {
+                description.push_str(" (delaying)");
+            }
+            if let Some(trigger) = &combatant.readied {
+                description.push_str(&format!(" (readied: {})", trigger));
+            }
+        }
+        description
+    }
+
+    /// Take a character who has left out of the encounter. If it was their turn, the turn passes back to whoever went before them
+    /// (or to nobody, if they went first), so `!next` carries on from the right place.
+    pub fn forget_combatant(&mut self, character: u64) {
+        let encounter = match &mut self.encounter {
+            Some(encounter) => encounter,
+            None => return,
+        };
+        let index = match encounter.position(character) {
+            Some(index) => index,
+            None => return,
+        };
+        encounter.combatants.remove(index);
+        if encounter.current == Some(character) {
+            encounter.turn_over = true;
+            encounter.current = match index {
+                // They went first, so the round carries on from the top.
+                0 => None,
+                index => Some(encounter.combatants[index - 1].character),
+            };
+        }
+    }
+
+    /// Roll a character's initiative from the first stat they have for it.
+    fn roll_initiative(&self, character: u64) -> Option<i64> {
+        let found = self.find_character_by_id(character)?;
+        let stat = INITIATIVE_STATS.iter().find_map(|name| found.find_stat(name))?;
+        formula::evaluate_roll(found, stat.formula().unwrap_or(&stat.value), Some(&stat.display_name), &self.effects_on(character)).ok()
+            .map(|evaluation| evaluation.roll.total)
+    }
+
+    /// Put a combatant into their place in the turn order, replacing them if they were already in it.
+    /// Enemies go before players on a tie, and otherwise whoever was there first keeps their place.
+    fn place_combatant(&mut self, character: u64, initiative: Option<i64>) {
+        let is_npc = |id: u64| self.find_character_by_id(id).is_some_and(|found| found.kind == CharacterKind::Npc);
+        let npc = is_npc(character);
+        let encounter = match &self.encounter {
+            Some(encounter) => encounter,
+            None => return,
+        };
+        let mut combatant = encounter.combatants.iter().find(|combatant| combatant.character == character).cloned()
+            .unwrap_or(Combatant { character, initiative: None, delaying: false, readied: None });
+        combatant.initiative = initiative;
+        let others: Vec<(i64, bool)> = encounter.combatants.iter()
+            .filter(|other| other.character != character)
+            .map(|other| (other.initiative.unwrap_or(i64::MIN), is_npc(other.character)))
+            .collect();
+        let ours = initiative.unwrap_or(i64::MIN);
+        let index = others.iter().position(|&(theirs, their_npc)| theirs < ours || (theirs == ours && initiative.is_some() && npc && !their_npc))
+            .unwrap_or(others.len());
+
+        let encounter = self.encounter.as_mut().expect("Checked for the encounter above");
+        encounter.combatants.retain(|other| other.character != character);
+        encounter.combatants.insert(index, combatant);
+    }
+
+    /// A note listing anyone still without an initiative, if there's anyone.
+    fn describe_missing_initiative(&self) -> Option<String> {
+        let missing: Vec<String> = self.encounter.as_ref()?.combatants.iter()
+            .filter(|combatant| combatant.initiative.is_none())
+            .map(|combatant| self.character_name(combatant.character))
+            .collect();
+        let first = missing.first()?;
+        Some(format!("\nStill waiting on initiative for {}. Set it with something like `!init {} 17`.", missing.join(", "), first))
+    }
+}
+
+fn no_encounter() -> anyhow::Error {
+    anyhow!("There's no encounter going. Start one with `!start encounter`.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::omni::character::Character;
+    use crate::omni::effect::{Duration, Effect};
+
+    /// Plunk is 1, Frank is 2 and Slurk is 3.
+    fn omnidata() -> Omnidata {
+        let mut omnidata = Omnidata::new();
+        omnidata.insert_character(Character::new("Plunk", CharacterKind::Player, 1));
+        omnidata.insert_character(Character::new("Frank", CharacterKind::Player, 2));
+        omnidata.insert_character(Character::new("Slurk", CharacterKind::Npc, 3));
+        omnidata
+    }
+
+    fn order(omnidata: &Omnidata) -> Vec<u64> {
+        omnidata.encounter.as_ref().unwrap().combatants.iter().map(|combatant| combatant.character).collect()
+    }
+
+    fn started() -> Omnidata {
+        let mut omnidata = omnidata();
+        omnidata.start_encounter(&[1, 2, 3]).unwrap();
+        omnidata.set_initiative(1, Some(15)).unwrap();
+        omnidata.set_initiative(2, Some(20)).unwrap();
+        omnidata.set_initiative(3, Some(15)).unwrap();
+        omnidata
+    }
+
+    #[test]
+    fn initiative_order() {
+        let mut omnidata = omnidata();
+        assert!(omnidata.next_turn(None).is_err());
+        let response = omnidata.start_encounter(&[1, 2, 3]).unwrap();
+        assert!(response.ends_with("Still waiting on initiative for Plunk, Frank, Slurk. Set it with something like `!init Plunk 17`."), "{}", response);
+        assert!(omnidata.start_encounter(&[1]).is_err());
+        assert!(omnidata.next_turn(None).is_err());
+        omnidata.set_initiative(1, Some(15)).unwrap();
+        omnidata.set_initiative(2, Some(20)).unwrap();
+        // Enemies win ties.
+        let response = omnidata.set_initiative(3, Some(15)).unwrap();
+        assert_eq!(response, "Slurk's initiative is 15.\n**Initiative**\n   20 Frank\n   15 Slurk\n   15 Plunk");
+        assert_eq!(order(&omnidata), vec![2, 3, 1]);
+        assert!(omnidata.set_initiative(1, None).is_err());
+    }
+
+    #[test]
+    fn rolled_initiative() {
+        let mut omnidata = omnidata();
+        omnidata.characters[0].stats = crate::omni::character::parse_noun_target_stats_command("player Plunk Perception:=1d20+100").unwrap().stats;
+        omnidata.start_encounter(&[1, 2]).unwrap();
+        let initiative = omnidata.encounter.as_ref().unwrap().combatants[0].initiative.unwrap();
+        assert!((101..=120).contains(&initiative), "{}", initiative);
+    }
+
+    #[test]
+    fn turns_and_rounds() {
+        let mut omnidata = started();
+        omnidata.add_effect(Effect { name: String::from("Bless"), target: 1, source: Some(2), duration: Duration::Rounds(1), modifiers: Vec::new() });
+        assert_eq!(omnidata.next_turn(None).unwrap(), "**Round 1**\nIt's Frank's turn.\nBless on Plunk has ended.");
+        assert_eq!(omnidata.active_combatant(), Some(2));
+        assert_eq!(omnidata.next_turn(None).unwrap(), "It's Slurk's turn.");
+        assert_eq!(omnidata.next_turn(None).unwrap(), "It's Plunk's turn.");
+        assert_eq!(omnidata.next_turn(None).unwrap(), "**Round 2**\nIt's Frank's turn.");
+        assert!(omnidata.describe_encounter().starts_with("**Round 2**\n➤ 20 Frank"));
+        assert_eq!(omnidata.end_encounter().unwrap(), "The encounter is over after 2 rounds.");
+        assert_eq!(omnidata.active_combatant(), None);
+        assert!(omnidata.end_encounter().is_err());
+    }
+
+    #[test]
+    fn delaying() {
+        let mut omnidata = started();
+        omnidata.characters[1].add_condition("frightened", Some("2")).unwrap();
+        omnidata.next_turn(None).unwrap();
+        assert!(omnidata.delay_turn(Some(1)).is_err());
+        assert!(omnidata.next_turn(Some(1)).is_err());
+        let response = omnidata.delay_turn(None).unwrap();
+        assert_eq!(response, "Frank is delaying. Bring them back in with `!next Frank`.\nFrank is now frightened 1.\nIt's Slurk's turn.");
+        assert_eq!(omnidata.next_turn(None).unwrap(), "It's Plunk's turn.");
+        // Frank is skipped at the top of the round...
+        assert_eq!(omnidata.next_turn(None).unwrap(), "**Round 2**\nIt's Slurk's turn.");
+        // ...until he steps back in, taking the spot after Slurk.
+        assert_eq!(omnidata.next_turn(Some(2)).unwrap(), "It's Frank's turn.");
+        assert_eq!(order(&omnidata), vec![3, 2, 1]);
+        assert_eq!(omnidata.encounter.as_ref().unwrap().combatants[1].initiative, Some(15));
+        assert_eq!(omnidata.next_turn(None).unwrap(), "It's Plunk's turn.\nFrank is no longer frightened.");
+    }
+
+    #[test]
+    fn readied_actions() {
+        let mut omnidata = started();
+        omnidata.next_turn(None).unwrap();
+        omnidata.ready_action(2, Some(String::from("Slurk moves"))).unwrap();
+        assert!(omnidata.describe_encounter().contains("20 Frank (readied: Slurk moves)"));
+        omnidata.next_turn(None).unwrap();
+        omnidata.next_turn(None).unwrap();
+        assert_eq!(omnidata.next_turn(None).unwrap(), "**Round 2**\nIt's Frank's turn.\nFrank's readied action (Slurk moves) wasn't used.");
+        assert!(omnidata.ready_action(2, None).is_err());
+    }
+
+    #[test]
+    fn conditions_on_turns() {
+        let mut omnidata = started();
+        omnidata.characters[1].add_condition("frightened", Some("1")).unwrap();
+        omnidata.characters[0].add_condition("dying", Some("2")).unwrap();
+        omnidata.next_turn(None).unwrap();
+        assert_eq!(omnidata.next_turn(None).unwrap(), "It's Slurk's turn.\nFrank is no longer frightened.");
+        assert_eq!(omnidata.next_turn(None).unwrap(), "It's Plunk's turn.\nPlunk is dying 2 and needs to make a recovery check: a flat check against DC 12.");
+    }
+
+    #[test]
+    fn leaving_mid_turn() {
+        let mut omnidata = started();
+        omnidata.characters[1].add_condition("frightened", Some("1")).unwrap();
+        omnidata.next_turn(None).unwrap();
+        omnidata.forget_combatant(2);
+        assert_eq!(omnidata.next_turn(None).unwrap(), "It's Slurk's turn.");
+        omnidata.forget_combatant(3);
+        assert_eq!(omnidata.next_turn(None).unwrap(), "It's Plunk's turn.");
+        // Frank's turn wasn't ended for him, so his frightened didn't wear off.
+        assert_eq!(omnidata.characters[1].describe_conditions(), "frightened 1");
+    }
+}
@@ -0,0 +1,213 @@
+//! Each guild can choose a Discord role for its GMs. Once it has one, enemies' stats are hidden from everyone else:
+//! replies in the channel and the tracker only describe enemies roughly, and the exact values are sent to the GM privately.
+//! Until a GM role is chosen, nothing is hidden and everyone counts as a GM.
+//! GMs can also use the commands in `GM_ONLY`, and change characters they don't own, and are sent the results of secret rolls.
+//! Changing a character means anything done to them: their stats, HP, conditions and their place in the turn order all need
+//! the author to own them or be a GM. Anyone can add an effect, since they usually come from someone else, like a Bless spell,
+//! but only a GM or the owner of the character it's on or of whoever caused it can sustain or remove it.
+
+use std::pin::Pin;
+use anyhow::{Result, anyhow};
+use futures::Future;
+use twilight_model::id::GuildId;
+use crate::discord::DiscordReferences;
+use super::Omnidata;
+use super::character::{Character, CharacterKind};
+use super::storage::OmniStore;
+
+/// Commands only GMs can use, as a verb and the noun that has to follow it. Verbs without a noun are GM-only whatever follows them.
+const GM_ONLY: [(&str, &str); 7] = [("add", "enemy"), ("start", "encounter"), ("end", "encounter"), ("set", "gm"), ("rolls", "reveal"), ("undo", ""), ("redo", "")];
+
+/// Whoever sent a command, and whether they're a GM.
+#[derive(Debug, Clone, Copy)]
+pub struct Author {
+    pub id: u64,
+    pub is_gm: bool,
+}
+
+/// Refuse commands that only GMs can use, unless the author is one.
+pub fn ensure_allowed(author: &Author, command: &str, arguments: &str) -> Result<()> {
+    let noun = arguments.split_whitespace().next().unwrap_or_default().to_lowercase();
+    if author.is_gm {
+        return Ok(());
+    }
+    if GM_ONLY.contains(&(command, "")) {
+        return Err(anyhow!("Only GMs can use `!{}`.", command));
+    }
+    if GM_ONLY.contains(&(command, noun.as_str())) {
+        return Err(anyhow!("Only GMs can use `!{} {}`.", command, noun));
+    }
+    Ok(())
+}
+
+/// A reply that may have had to leave out things only GMs should see, like an enemy's exact HP.
+/// If the author is a GM, the full version is sent to them privately.
+#[derive(Debug)]
+pub struct Redacted {
+    pub public: String,
+    pub secret: Option<String>,
+}
+
+impl Redacted {
+    /// A reply with nothing to hide.
+    pub fn public(text: String) -> Self {
+        Redacted { public: text, secret: None }
+    }
+
+    /// Use the redacted version in public if the character's stats are hidden, and otherwise just show the full one.
+    pub fn about(omnidata: &Omnidata, character: &Character, full: String, redacted: String) -> Self {
+        match omnidata.hides(character) {
+            true => Redacted { public: redacted, secret: Some(full) },
+            false => Redacted::public(full),
+        }
+    }
+}
+
+/// Handle `!set gm <role>`, where the role is a mention or a role's name, and `!set gm off` to stop hiding anything.
+/// Like the tracker, this has to ask Discord about the guild's roles before it knows what to save.
+pub async fn handle_set_gm_command(
+    discord_refs: &DiscordReferences<'_>,
+    omnidata: &mut Omnidata,
+    store: &dyn OmniStore,
+    guild_id: GuildId,
+    arguments: &str,
+) -> Result<()> {
+    let role = arguments.trim().split_once(char::is_whitespace).map_or("", |(_, role)| role).trim().replace("\"", "");
+    let response = match role.to_lowercase().as_str() {
+        "" => String::from("Give the GM role, like `!set gm @GM`, or turn it off with `!set gm off`."),
+        "off" | "none" => {
+            omnidata.gm_role = None;
+            omnidata.dirty();
+            String::from("There's no GM role anymore, so enemy stats are shown to everyone.")
+        },
+        _ => match parse_role_mention(&role) {
+            Some(id) => set_gm_role(omnidata, id),
+            None => match discord_refs.find_role(guild_id, &role).await? {
+                Some(id) => set_gm_role(omnidata, id),
+                None => format!("Couldn't find a role named '{}'. Check your spelling, or mention the role instead.", role),
+            },
+        },
+    };
+    super::save_omnidata(store, guild_id, omnidata, &discord_refs.msg.content).await?;
+    omnidata.saved();
+    discord_refs.send_message_reply(response).await
+}
+
+/// Handle `!sroll`, for rolls like Recall Knowledge that players shouldn't see the result of. The full result is sent to every GM
+/// by DM, and the channel is only told that a secret roll was made. Without a GM role, it's sent to whoever rolled it instead.
+pub fn send_secret_roll<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'message>, guild_id: GuildId, gm_role: Option<u64>, rolled: Result<Redacted>) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
+    Box::pin(async move {
+        let rolled = match rolled {
+            Ok(rolled) => rolled,
+            Err(error) => return discord_refs.send_message_reply(error.to_string()).await,
+        };
+        let gms = match gm_role {
+            Some(role) => match discord_refs.members_with_role(guild_id, role).await {
+                Ok(gms) => gms,
+                Err(error) => return discord_refs.send_message_reply(error.to_string()).await,
+            },
+            None => vec![discord_refs.msg.author.id.0],
+        };
+        if gms.is_empty() {
+            return discord_refs.send_message_reply("Nobody has the GM role, so there's no one to send a secret roll to.").await;
+        }
+        discord_refs.send_dms(&gms, secret_roll_message(discord_refs.msg.author.id.0, rolled)).await?;
+        discord_refs.send_message_reply("A secret roll was made.").await
+    })
+}
+
+/// What GMs are sent for a secret roll: who asked for it, and everything about it, even for characters with hidden stats.
+fn secret_roll_message(author: u64, rolled: Redacted) -> String {
+    format!("Secret roll for <@{}>:\n{}", author, rolled.secret.unwrap_or(rolled.public))
+}
+
+fn set_gm_role(omnidata: &mut Omnidata, role: u64) -> String {
+    omnidata.gm_role = Some(role);
+    omnidata.dirty();
+    format!("<@&{}> is now the GM role. Enemy stats are hidden from everyone without it.", role)
+}
+
+/// Roles can be given as a mention, like `<@&1234>`, or just their ID.
+fn parse_role_mention(role: &str) -> Option<u64> {
+    role.strip_prefix("<@&").and_then(|rest| rest.strip_suffix('>')).unwrap_or(role).parse().ok()
+}
+
+/// Users can be given as a mention, like `<@1234>` or `<@!1234>` for someone with a nickname, or just their ID.
+pub fn parse_user_mention(user: &str) -> Option<u64> {
+    let user = user.trim();
+    user.strip_prefix("<@").and_then(|rest| rest.strip_suffix('>')).map(|id| id.trim_start_matches('!')).unwrap_or(user).parse().ok()
+}
+
+impl Omnidata {
+    /// Whether someone with these roles is a GM. Without a GM role, everyone is.
+    pub fn is_gm(&self, roles: &[u64]) -> bool {
+        match self.gm_role {
+            Some(gm_role) => roles.contains(&gm_role),
+            None => true,
+        }
+    }
+
+    /// Whether a character's stats are hidden from players.
+    pub fn hides(&self, character: &Character) -> bool {
+        self.gm_role.is_some() && character.kind == CharacterKind::Npc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gm_roles() {
+        let mut omnidata = Omnidata::new();
+        let slurk = Character::new("Slurk", CharacterKind::Npc, 1);
+        let plunk = Character::new("Plunk", CharacterKind::Player, 1);
+        assert!(omnidata.is_gm(&[]));
+        assert!(!omnidata.hides(&slurk));
+
+        assert_eq!(set_gm_role(&mut omnidata, 42), "<@&42> is now the GM role. Enemy stats are hidden from everyone without it.");
+        assert!(omnidata.is_gm(&[7, 42]));
+        assert!(!omnidata.is_gm(&[7]));
+        assert!(omnidata.hides(&slurk));
+        assert!(!omnidata.hides(&plunk));
+
+        let redacted = Redacted::about(&omnidata, &slurk, String::from("HP: 3/40"), String::from("Health: Near death"));
+        assert_eq!((redacted.public.as_str(), redacted.secret.as_deref()), ("Health: Near death", Some("HP: 3/40")));
+        let shown = Redacted::about(&omnidata, &plunk, String::from("HP: 3/40"), String::from("Health: Near death"));
+        assert_eq!((shown.public.as_str(), shown.secret), ("HP: 3/40", None));
+    }
+
+    #[test]
+    fn secret_rolls() {
+        let hidden = Redacted { public: String::from("**Slurk's Stealth**: 21"), secret: Some(String::from("**Slurk's Stealth** `=1d20+7`")) };
+        assert_eq!(secret_roll_message(42, hidden), "Secret roll for <@42>:\n**Slurk's Stealth** `=1d20+7`");
+        assert_eq!(secret_roll_message(42, Redacted::public(String::from("**Plunk's Society**: 12"))), "Secret roll for <@42>:\n**Plunk's Society**: 12");
+    }
+
+    #[test]
+    fn role_mentions() {
+        assert_eq!(parse_role_mention("<@&1234>"), Some(1234));
+        assert_eq!(parse_role_mention("1234"), Some(1234));
+        assert_eq!(parse_role_mention("Game Master"), None);
+        assert_eq!(parse_role_mention("<@!1234>"), None);
+        assert_eq!(parse_user_mention("<@1234>"), Some(1234));
+        assert_eq!(parse_user_mention("<@!1234>"), Some(1234));
+        assert_eq!(parse_user_mention("<@&1234>"), None);
+        assert_eq!(parse_user_mention("Bob"), None);
+    }
+
+    #[test]
+    fn gm_only_commands() {
+        let player = Author { id: 1, is_gm: false };
+        let gm = Author { id: 2, is_gm: true };
+        assert_eq!(ensure_allowed(&player, "add", "Enemy Slurk HP:20").unwrap_err().to_string(), "Only GMs can use `!add enemy`.");
+        assert!(ensure_allowed(&player, "start", "encounter").is_err());
+        assert!(ensure_allowed(&player, "set", "gm off").is_err());
+        assert!(ensure_allowed(&player, "add", "player Plunk").is_ok());
+        assert!(ensure_allowed(&player, "end", "").is_ok());
+        assert_eq!(ensure_allowed(&player, "undo", "").unwrap_err().to_string(), "Only GMs can use `!undo`.");
+        assert!(ensure_allowed(&player, "redo", "2").is_err());
+        assert!(ensure_allowed(&player, "history", "").is_ok());
+        assert!(ensure_allowed(&gm, "add", "enemy Slurk").is_ok());
+    }
+}
@@ -0,0 +1,204 @@
+//! Every change to the tracker is saved as a new revision in the store, along with the command that made it.
+//! These commands walk back and forth through those revisions, restoring whichever one the user asks for.
+
+use anyhow::{Result, anyhow};
+use twilight_model::id::GuildId;
+use crate::discord::DiscordReferences;
+use super::Omnidata;
+use super::storage::{OmniStore, Revision};
+
+/// How many revisions to fetch from the store. Undo and redo can't reach any further than this.
+const HISTORY_LIMIT: usize = 50;
+/// How many revisions to show for !history, to keep the reply a readable size.
+const HISTORY_DISPLAY_LIMIT: usize = 10;
+
+/// Handle the undo, redo and history commands. Unlike most commands these don't change the omnidata in place,
+/// they replace it wholesale with a revision from the store.
+pub async fn handle_history_command(
+    discord_refs: &DiscordReferences<'_>,
+    omnidata: &mut Option<Omnidata>,
+    store: &dyn OmniStore,
+    guild_id: GuildId,
+    command: &str,
+    arguments: &str,
+) -> Result<()> {
+    let revisions = store.history(guild_id, HISTORY_LIMIT).await?;
+    if command == "history" {
+        return discord_refs.send_message_reply(format_history(&revisions)).await;
+    }
+
+    let steps = match parse_steps(arguments) {
+        Ok(steps) => steps,
+        Err(error) => return discord_refs.send_message_reply(error.to_string()).await,
+    };
+    let (current, target) = match pick_revision(&revisions, command == "undo", steps) {
+        Ok(picked) => picked,
+        Err(error) => return discord_refs.send_message_reply(error.to_string()).await,
+    };
+
+    restore(omnidata, store, guild_id, revisions[current].id, revisions[target].id).await?;
+    discord_refs.send_message_reply(describe_restore(&revisions, current, target)).await
+}
+
+/// Make the target revision current and replace the omnidata with it. If it can't be loaded, the store is put back how it was.
+async fn restore(omnidata: &mut Option<Omnidata>, store: &dyn OmniStore, guild_id: GuildId, current: u64, target: u64) -> Result<()> {
+    let data = store.restore(guild_id, target).await?;
+    let mut restored = match Omnidata::load(&data) {
+        Ok(restored) => restored,
+        Err(error) => {
+            // Don't leave the store pointing at something that can't be loaded.
+            store.restore(guild_id, current).await?;
+            return Err(error);
+        }
+    };
+    // Even if the restored revision needed migrating, saving it now would throw away everything that could be redone.
+    restored.is_dirty = false;
+    // The roll log isn't something to undo, and is saved apart from the revisions. Going back to a seed that has already
+    // been used or revealed would let people know what the dice will roll before they're rolled.
+    if let Some(current) = omnidata.as_mut() {
+        restored.roll_log = std::mem::take(&mut current.roll_log);
+        restored.is_roll_log_dirty = current.is_roll_log_dirty;
+    }
+    *omnidata = Some(restored);
+    Ok(())
+}
+
+/// Undo and redo take an optional number of changes to step through, defaulting to one.
+fn parse_steps(arguments: &str) -> Result<usize> {
+    let arguments = arguments.trim();
+    if arguments.is_empty() {
+        return Ok(1);
+    }
+    match arguments.parse::<usize>() {
+        Ok(steps) if steps > 0 => Ok(steps),
+        _ => Err(anyhow!("'{}' isn't a number of changes. Try something like `!undo` or `!undo 2`.", arguments)),
+    }
+}
+
+/// Given revisions sorted newest first, find the index of the current revision and the one `steps` away from it.
+fn pick_revision(revisions: &[Revision], undo: bool, steps: usize) -> Result<(usize, usize)> {
+    let current = revisions.iter().position(|revision| revision.is_current)
+        .ok_or_else(|| anyhow!("Couldn't find the current version of the tracker in its recent history."))?;
+    let target = if undo {
+        Some(current + steps).filter(|&target| target < revisions.len())
+            .ok_or_else(|| anyhow!("There aren't {} changes to undo. Check `!history` to see what can be undone.", steps))?
+    } else {
+        current.checked_sub(steps)
+            .ok_or_else(|| anyhow!("There aren't {} changes to redo. Check `!history` to see what can be redone.", steps))?
+    };
+    Ok((current, target))
+}
+
+fn describe_restore(revisions: &[Revision], current: usize, target: usize) -> String {
+    let reasons = |range: &[Revision]| range.iter().map(|revision| format!("`{}`", revision.reason)).collect::<Vec<String>>().join(", ");
+    if target > current {
+        format!("Undid {}. The tracker is back to how it was after {}.", reasons(&revisions[current..target]), reasons(&revisions[target..=target]))
+    } else {
+        let mut redone = revisions[target..current].to_vec();
+        redone.reverse();
+        format!("Redid {}.", reasons(&redone))
+    }
+}
+
+/// List recent revisions newest first, along with the command that would restore each one.
+fn format_history(revisions: &[Revision]) -> String {
+    let current = match revisions.iter().position(|revision| revision.is_current) {
+        Some(current) => current,
+        None => return String::from("Nothing has been saved yet, so there's no history to show."),
+    };
+
+    let mut response = String::from("Recent changes to the tracker, newest first. Each is listed with the command that made it.\n");
+    for (index, revision) in revisions.iter().enumerate().take(HISTORY_DISPLAY_LIMIT.max(current + 1)) {
+        let label = if index < current {
+            format!("`!redo {}`", current - index)
+        } else if index == current {
+            String::from("**current**")
+        } else {
+            format!("`!undo {}`", index - current)
+        };
+        response.push_str(&format!("{} - {}\n", label, revision.reason));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revisions(current: usize) -> Vec<Revision> {
+        ["!add stat Plunk AC:18", "!add enemy Slurk", "!add player Plunk"].iter().enumerate().map(|(index, reason)| Revision {
+            id: 3 - index as u64,
+            reason: reason.to_string(),
+            is_current: index == current,
+        }).collect()
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(parse_steps("").unwrap(), 1);
+        assert_eq!(parse_steps(" 3 ").unwrap(), 3);
+        assert!(parse_steps("0").is_err());
+        assert!(parse_steps("plunk").is_err());
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        assert_eq!(pick_revision(&revisions(0), true, 1).unwrap(), (0, 1));
+        assert_eq!(pick_revision(&revisions(0), true, 2).unwrap(), (0, 2));
+        assert!(pick_revision(&revisions(0), true, 3).is_err());
+        assert!(pick_revision(&revisions(0), false, 1).is_err());
+        assert_eq!(pick_revision(&revisions(2), false, 2).unwrap(), (2, 0));
+    }
+
+    #[test]
+    fn describe() {
+        let history = revisions(0);
+        assert_eq!(describe_restore(&history, 0, 2), "Undid `!add stat Plunk AC:18`, `!add enemy Slurk`. The tracker is back to how it was after `!add player Plunk`.");
+        let history = revisions(2);
+        assert_eq!(describe_restore(&history, 2, 0), "Redid `!add enemy Slurk`, `!add stat Plunk AC:18`.");
+    }
+
+    #[test]
+    fn history_labels() {
+        let formatted = format_history(&revisions(1));
+        assert!(formatted.contains("`!redo 1` - !add stat Plunk AC:18"));
+        assert!(formatted.contains("**current** - !add enemy Slurk"));
+        assert!(formatted.contains("`!undo 1` - !add player Plunk"));
+        assert!(format_history(&[]).contains("no history"));
+    }
+
+    #[tokio::test]
+    async fn rolls_outlast_reloading_and_undo() {
+        use crate::omni::{load_omnidata, save_omnidata};
+        use crate::omni::character::{Character, CharacterKind};
+        use crate::omni::storage::FilesystemStore;
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = FilesystemStore::new(dir.path());
+        let guild_id = GuildId(1);
+        let mut omnidata = Omnidata::new();
+        omnidata.insert_character(Character::new("Plunk", CharacterKind::Player, 1));
+        omnidata.dirty();
+        save_omnidata(&store, guild_id, &omnidata, "!add player Plunk").await.unwrap();
+        omnidata.saved();
+        omnidata.insert_character(Character::new("Slurk", CharacterKind::Npc, 1));
+        omnidata.dirty();
+        save_omnidata(&store, guild_id, &omnidata, "!add enemy Slurk").await.unwrap();
+        omnidata.saved();
+
+        // Rolling doesn't change anything else, but the roll is still saved.
+        omnidata.log_rolls(guild_id, 100, 1, |_| crate::dice::roll("1d20+5").unwrap());
+        assert!(omnidata.is_roll_log_dirty && !omnidata.is_dirty);
+        save_omnidata(&store, guild_id, &omnidata, "!roll 1d20+5").await.unwrap();
+        let mut reloaded = Some(load_omnidata(&store, guild_id).await.unwrap());
+        assert_eq!(reloaded.as_ref().unwrap().roll_log, omnidata.roll_log);
+        assert_eq!(store.history(guild_id, 10).await.unwrap().len(), 2);
+
+        let revisions = store.history(guild_id, 10).await.unwrap();
+        restore(&mut reloaded, &store, guild_id, revisions[0].id, revisions[1].id).await.unwrap();
+        assert_eq!(reloaded.as_ref().unwrap().characters.len(), 1);
+        assert_eq!(reloaded.unwrap().roll_log.rolls.len(), 1);
+        let reloaded = load_omnidata(&store, guild_id).await.unwrap();
+        assert_eq!((reloaded.characters.len(), reloaded.roll_log), (1, omnidata.roll_log));
+    }
+}
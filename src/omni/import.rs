@@ -0,0 +1,229 @@
+//! Characters can be imported from the character builders and virtual tabletops players already use, instead of being typed
+//! in one stat at a time, and exported back out to them. Exports are usually too long for a Discord message, so they can be
+//! attached to the command instead. GMs can also import from and export to files in a directory on the bot's computer,
+//! if it's been given one with the `OMNI_FILES` environment variable.
+
+mod foundry;
+mod pathbuilder;
+
+use std::env;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use anyhow::{Result, anyhow};
+use crate::discord::DiscordReferences;
+use super::Omnidata;
+use super::character::{Character, CharacterKind, formula};
+use super::character::stat::Stat;
+use super::gm::Author;
+
+/// Handle `!import <format> <export>`, where the export is pasted after the format, attached to the message,
+/// or the name of a file in the bot's files directory. The imported character belongs to whoever imported them.
+pub fn import_character(omnidata: &mut Omnidata, author: &Author, arguments: &str, attachment: Option<String>) -> Result<String> {
+    let (format, rest) = super::split_first_word(arguments.trim());
+    let export = match strip_code_block(rest) {
+        "" => attachment.ok_or_else(|| anyhow!("Attach the exported file, or paste it after `!import {}`.", format))?,
+        pasted if pasted.starts_with('{') => pasted.to_string(),
+        file_name => std::fs::read_to_string(file_path(author, file_name)?)
+            .map_err(|error| anyhow!("Couldn't read {}: {}", file_name, error))?,
+    };
+    let (character, description) = match format.to_lowercase().as_str() {
+        "pathbuilder" => pathbuilder::parse(&export, author.id)?,
+        "foundry" => foundry::parse(&export, author.id)?,
+        "" => return Err(anyhow!("Say where the character is from, like `!import pathbuilder`, and attach the exported file.")),
+        unknown => return Err(anyhow!("Sorry, I don't know how to import from '{}'. For more help, consult `!help import`.", unknown)),
+    };
+    if character.kind == CharacterKind::Npc && !author.is_gm {
+        return Err(anyhow!("Only GMs can add enemies."));
+    }
+    if let Some(existing) = omnidata.find_character(&character.name) {
+        return Err(anyhow!("There's already a {} named {}. Remove or rename them before importing.", existing.kind.noun(), existing.name));
+    }
+
+    let response = format!("Imported {}, a {}, with {} stats. Use `!show {}` to see them.", character.name, description, character.stats.len(), super::quote_name(&character.name));
+    omnidata.insert_character(character);
+    omnidata.dirty();
+    Ok(response)
+}
+
+/// A character that has been exported, either to be sent back as an attachment or already saved to a file.
+#[derive(Debug)]
+enum Exported {
+    Attachment { file_name: String, contents: String, response: String },
+    Saved(String),
+}
+
+/// Handle `!export <format> <character>`, which replies with the exported character attached.
+/// GMs can give a file name after the character to save it in the bot's files directory instead.
+pub fn handle_export_command<'a, 'message:'a>(discord_refs: &'a DiscordReferences<'message>, omnidata: &Omnidata, author: &Author, arguments: &str) -> Pin<Box<dyn Future<Output=Result<()>> + Send + 'a>> {
+    match export_character(omnidata, author, arguments) {
+        Ok(Exported::Attachment { file_name, contents, response }) => Box::pin(discord_refs.send_file_reply(file_name, contents, response)),
+        Ok(Exported::Saved(response)) => super::reply(discord_refs, Ok(response)),
+        Err(error) => super::reply(discord_refs, Err(error)),
+    }
+}
+
+fn export_character(omnidata: &Omnidata, author: &Author, arguments: &str) -> Result<Exported> {
+    let (format, rest) = super::split_first_word(arguments.trim());
+    let (name, file_name) = super::split_first_word(rest);
+    if name.is_empty() {
+        return Err(anyhow!("Give the format and who to export, like `!export foundry Plunk`."));
+    }
+    let character = omnidata.find_character(name)
+        .ok_or_else(|| anyhow!("Couldn't find a character with '{}' for a name. Check your spelling.", name))?;
+    if omnidata.hides(character) && !author.is_gm {
+        return Err(anyhow!("Only GMs can export {}, since enemy stats are hidden.", character.name));
+    }
+    let (contents, left_out) = match format.to_lowercase().as_str() {
+        "foundry" => foundry::export(character)?,
+        unknown => return Err(anyhow!("Sorry, I don't know how to export to '{}'. For more help, consult `!help export`.", unknown)),
+    };
+
+    let mut response = format!("Exported {} for Foundry.", character.name);
+    if !left_out.is_empty() {
+        response.push_str(&format!(" Foundry has nowhere to put {}, so they were left out.", left_out.join(", ")));
+    }
+    if file_name.is_empty() {
+        return Ok(Exported::Attachment { file_name: format!("{}.json", identifier(&character.name)), contents, response });
+    }
+    let path = file_path(author, file_name)?;
+    std::fs::write(&path, contents).map_err(|error| anyhow!("Couldn't save {}: {}", file_name, error))?;
+    Ok(Exported::Saved(format!("{} Saved to {}.", response, file_name)))
+}
+
+/// Where a file in the bot's files directory is. Only GMs can use them, and only by name, so nothing outside the directory can be reached.
+fn file_path(author: &Author, file_name: &str) -> Result<PathBuf> {
+    if !author.is_gm {
+        return Err(anyhow!("Only GMs can use files on the bot's computer. Attach the file instead."));
+    }
+    let directory = env::var("OMNI_FILES").map_err(|_| anyhow!("The bot hasn't been given a directory for files. Attach the file instead."))?;
+    file_in(Path::new(&directory), file_name)
+}
+
+fn file_in(directory: &Path, file_name: &str) -> Result<PathBuf> {
+    match Path::new(file_name).file_name() {
+        Some(name) if name == file_name && !file_name.starts_with('.') => Ok(directory.join(name)),
+        _ => Err(anyhow!("'{}' isn't a file name. Give just the name of a file in the bot's files directory.", file_name)),
+    }
+}
+
+/// Pasted exports are often wrapped in a code block, like ```json ... ```, to stop Discord mangling them.
+fn strip_code_block(text: &str) -> &str {
+    let text = text.trim();
+    match text.strip_prefix("```").and_then(|inner| inner.strip_suffix("```")) {
+        Some(inner) => inner.strip_prefix("json").unwrap_or(inner).trim(),
+        None => text.trim_matches('`').trim(),
+    }
+}
+
+/// A stat with a maximum that's shown on the tracker, like HP.
+fn tracked(name: &str, value: i64, maximum: i64) -> Stat {
+    Stat {
+        display_name: name.to_string(),
+        display_on_tracker: true,
+        value: value.to_string(),
+        maximum_value: Some(maximum.to_string()),
+    }
+}
+
+/// Striking runes add weapon damage dice.
+fn damage_dice(rune: &str) -> i64 {
+    match rune.to_lowercase().as_str() {
+        "striking" => 2,
+        "greaterstriking" | "greater striking" => 3,
+        "majorstriking" | "major striking" => 4,
+        _ => 1,
+    }
+}
+
+/// What a stat adds up to without the d20, like 11 for `Athletics:=1d20+STR+Athletics_Prof`.
+/// Stats with any other dice in them don't add up to one number, so they have no fixed value.
+fn fixed_value(character: &Character, stat: &Stat) -> Option<i64> {
+    let resolved = formula::resolve(character, stat.formula().unwrap_or(&stat.value)).ok()?;
+    let without_d20 = resolved.to_lowercase().replacen("1d20", "0", 1);
+    if without_d20.contains('d') {
+        return None;
+    }
+    formula::evaluate(character, &without_d20).ok().map(|evaluation| evaluation.roll.total)
+}
+
+/// Stat names are used in formulas, so they can't have spaces or punctuation. `Composite Shortbow` becomes `Composite_Shortbow`.
+fn identifier(name: &str) -> String {
+    name.replace('\'', "").split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(capitalize)
+        .collect::<Vec<String>>()
+        .join("_")
+}
+
+fn capitalize(word: &str) -> String {
+    let mut letters = word.chars();
+    match letters.next() {
+        Some(first) => first.to_uppercase().chain(letters).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIGHTER: &str = include_str!("../../fixtures/pathbuilder/fighter.json");
+    const GOBLIN: &str = include_str!("../../fixtures/foundry/goblin-warrior.json");
+
+    #[test]
+    fn import_pathbuilder() {
+        let mut omnidata = Omnidata::new();
+        let author = Author { id: 7, is_gm: false };
+        assert!(import_character(&mut omnidata, &author, "pathbuilder", None).unwrap_err().to_string().contains("Attach"));
+        assert!(import_character(&mut omnidata, &author, "herolab", Some(FIGHTER.to_string())).is_err());
+
+        let response = import_character(&mut omnidata, &author, "Pathbuilder", Some(FIGHTER.to_string())).unwrap();
+        assert!(response.starts_with("Imported Plunk, a level 3 Goblin Fighter, with "), "{}", response);
+        assert_eq!(omnidata.characters[0].owner, 7);
+        assert!(omnidata.is_dirty);
+        let pasted = format!("pathbuilder ```json\n{}```", FIGHTER);
+        assert!(import_character(&mut omnidata, &author, &pasted, None).unwrap_err().to_string().contains("already a player named Plunk"));
+    }
+
+    #[test]
+    fn only_gms_import_enemies_and_use_files() {
+        let mut omnidata = Omnidata::new();
+        let player = Author { id: 7, is_gm: false };
+        assert_eq!(import_character(&mut omnidata, &player, "foundry", Some(GOBLIN.to_string())).unwrap_err().to_string(), "Only GMs can add enemies.");
+        assert!(import_character(&mut omnidata, &player, "foundry goblin.json", None).unwrap_err().to_string().contains("Only GMs can use files"));
+        let gm = Author { id: 8, is_gm: true };
+        assert!(import_character(&mut omnidata, &gm, "foundry", Some(GOBLIN.to_string())).unwrap().starts_with("Imported Goblin Warrior, a level -1 enemy"));
+
+        omnidata.gm_role = Some(42);
+        assert!(export_character(&omnidata, &player, "foundry \"goblin warrior\"").unwrap_err().to_string().contains("Only GMs can export"));
+        match export_character(&omnidata, &gm, "foundry \"goblin warrior\"").unwrap() {
+            Exported::Attachment { file_name, .. } => assert_eq!(file_name, "Goblin_Warrior.json"),
+            Exported::Saved(response) => panic!("Expected an attachment, got {}", response),
+        }
+        assert!(export_character(&omnidata, &gm, "herolab \"goblin warrior\"").is_err());
+    }
+
+    #[test]
+    fn file_names() {
+        let directory = Path::new("/srv/omni");
+        assert_eq!(file_in(directory, "plunk.json").unwrap(), Path::new("/srv/omni/plunk.json"));
+        assert!(file_in(directory, "../secrets.json").is_err());
+        assert!(file_in(directory, "/etc/passwd").is_err());
+        assert!(file_in(directory, ".env").is_err());
+    }
+
+    #[test]
+    fn code_blocks() {
+        assert_eq!(strip_code_block("```json\n{}\n```"), "{}");
+        assert_eq!(strip_code_block("```{}```"), "{}");
+        assert_eq!(strip_code_block("`{}`"), "{}");
+        assert_eq!(strip_code_block(" {} "), "{}");
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(identifier("Composite Shortbow"), "Composite_Shortbow");
+        assert_eq!(identifier("alchemist's fire (lesser)"), "Alchemists_Fire_Lesser");
+    }
+}
@@ -0,0 +1,444 @@
+//! Foundry VTT's pf2e system can export any actor as JSON, from the "Export Data" option on its sheet.
+//! Everyone's level and ability scores go on their sheet. NPCs are saved with their finished modifiers, like a +8 Dogslicer,
+//! and those are imported as stats of their own. PCs are mostly saved as proficiency ranks instead, which go on their sheet too.
+//!
+//! Exports go the other way with fixed values only, since that's all Foundry can hold for an NPC,
+//! and the same minimal layout is used for PCs so the bot can read its own exports back.
+
+use anyhow::{Result, anyhow};
+use serde_json::{Map, Value, json};
+use crate::omni::character::{Character, CharacterKind, formula};
+use crate::omni::character::sheet::{Ability, Rank, SAVES, SKILLS, Sheet};
+use crate::omni::character::stat::Stat;
+use super::{damage_dice, fixed_value, identifier, tracked};
+
+/// Turn a Foundry pf2e actor into a character owned by the importing user.
+/// Returns the character along with a short description of who they are, like `level 3 Goblin Fighter`.
+pub fn parse(json: &str, owner: u64) -> Result<(Character, String)> {
+    let actor: Value = serde_json::from_str(json).map_err(|error| anyhow!("That doesn't look like a Foundry actor: {}", error))?;
+    let name = actor.get("name").and_then(Value::as_str).ok_or_else(|| anyhow!("That doesn't look like a Foundry actor, since it has no name."))?;
+    let kind = match actor.get("type").and_then(Value::as_str) {
+        Some("character") => CharacterKind::Player,
+        Some("npc") => CharacterKind::Npc,
+        other => return Err(anyhow!("Only PCs and NPCs can be imported from Foundry, not a '{}'.", other.unwrap_or_default())),
+    };
+    // Older versions of Foundry kept everything under `data` instead.
+    let system = actor.get("system").or_else(|| actor.get("data")).ok_or_else(|| anyhow!("{} has no stats to import.", name))?;
+    let items = actor.get("items").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+
+    let mut character = Character::new(name, kind, owner);
+    let (sheet, stats) = stats(name, system, items)?;
+    character.set_sheet(sheet);
+    character.stats.extend(stats);
+    let level = number(system, "/details/level/value").unwrap_or_default();
+    let description = match kind {
+        CharacterKind::Player => {
+            let ancestry = item_names(items, "ancestry");
+            let class = item_names(items, "class");
+            format!("level {} {} {}", level, ancestry, class).trim().replace("  ", " ")
+        },
+        CharacterKind::Npc => format!("level {} enemy", level),
+    };
+    Ok((character, description))
+}
+
+/// The actor's sheet, and every stat that isn't on it in the order they're shown.
+/// Checks saved with a finished modifier are taken off the sheet and kept as the character's own stats instead.
+fn stats(name: &str, system: &Value, items: &[Value]) -> Result<(Sheet, Vec<Stat>)> {
+    let level = number(system, "/details/level/value").ok_or_else(|| anyhow!("{} has no level.", name))?;
+    let mut abilities = [10; 6];
+    for (index, ability) in Ability::ALL.iter().enumerate() {
+        abilities[index] = number(system, &format!("/abilities/{}/value", ability.key()))
+            .or_else(|| number(system, &format!("/abilities/{}/mod", ability.key())).map(|modifier| 10 + modifier * 2))
+            .ok_or_else(|| anyhow!("Foundry didn't save {}'s {}. Newer versions work out a PC's abilities from their ancestry, background and class, which the bot can't do, so try importing them from Pathbuilder instead.", name, ability.stat_name()))?;
+    }
+    let mut sheet = Sheet::new(level, abilities);
+    let mut stats = Vec::new();
+
+    if let Some(hit_points) = number(system, "/attributes/hp/value") {
+        stats.push(tracked("HP", hit_points, number(system, "/attributes/hp/max").unwrap_or(hit_points)));
+    }
+    if let Some(armor_class) = number(system, "/attributes/ac/value") {
+        stats.push(Stat::new("AC", armor_class.to_string()));
+    }
+
+    for (save, _) in SAVES.iter() {
+        let (explicit, rank) = match *save {
+            "Perception" => (
+                number_at(system, &["/perception/mod", "/attributes/perception/value"]),
+                number_at(system, &["/perception/rank", "/attributes/perception/rank"]),
+            ),
+            _ => {
+                let key = save.to_lowercase();
+                (
+                    number_at(system, &[&format!("/saves/{}/value", key), &format!("/saves/{}/mod", key)]),
+                    number(system, &format!("/saves/{}/rank", key)),
+                )
+            },
+        };
+        stats.extend(check(&mut sheet, save, explicit, rank)?);
+    }
+
+    let lores: Vec<&Value> = items.iter().filter(|item| item_type(item) == "lore").collect();
+    let lore_named = |name: &str| lores.iter().copied().find(|lore| item_name(lore).eq_ignore_ascii_case(name));
+    for (skill, _) in SKILLS.iter() {
+        let key = short_key(skill);
+        let explicit = lore_named(skill).and_then(|lore| number(lore, "/system/mod/value"))
+            .or_else(|| number_at(system, &[&format!("/skills/{}/value", key), &format!("/skills/{}/value", skill.to_lowercase())]));
+        let rank = number_at(system, &[&format!("/skills/{}/rank", key), &format!("/skills/{}/rank", skill.to_lowercase())]);
+        stats.extend(check(&mut sheet, skill, explicit, rank)?);
+    }
+    for lore in lores.iter().filter(|lore| !SKILLS.iter().any(|(skill, _)| item_name(lore).eq_ignore_ascii_case(skill))) {
+        let topic = item_name(lore).trim_end_matches(" Lore").trim_end_matches(" lore");
+        let name = format!("{}_Lore", identifier(topic));
+        stats.extend(check(&mut sheet, &name, number(lore, "/system/mod/value"), number(lore, "/system/proficient/value"))?);
+    }
+
+    stats.extend(strikes(&mut sheet, system, items)?);
+
+    for entry in items.iter().filter(|item| item_type(item) == "spellcastingEntry") {
+        let tradition = entry.pointer("/system/tradition/value").and_then(Value::as_str).map(super::capitalize).unwrap_or_default();
+        if tradition.is_empty() || stats.iter().any(|stat| stat.display_name == format!("{}_Spell_DC", tradition)) {
+            continue;
+        }
+        if let Some(attack) = number(entry, "/system/spelldc/value") {
+            stats.push(Stat::new(&format!("{}_Spell_Attack", tradition), format!("=1d20{:+}", attack)));
+        }
+        if let Some(dc) = number(entry, "/system/spelldc/dc") {
+            stats.push(Stat::new(&format!("{}_Spell_DC", tradition), dc.to_string()));
+        }
+    }
+    match (number(system, "/resources/focus/value"), number(system, "/resources/focus/max")) {
+        (Some(value), Some(maximum)) if maximum > 0 => stats.push(tracked("FP", value, maximum)),
+        _ => {},
+    }
+    Ok((sheet, stats))
+}
+
+/// NPC strikes are saved with their finished attack bonus and damage. PC weapons have to be worked out from
+/// the character's proficiency with them and their runes, using DEX for ranged and finesse weapons if it's better.
+fn strikes(sheet: &mut Sheet, system: &Value, items: &[Value]) -> Result<Vec<Stat>> {
+    let mut stats = Vec::new();
+    let mut names = Vec::new();
+    for item in items {
+        let name = identifier(item_name(item));
+        if names.contains(&name) {
+            continue;
+        }
+        match item_type(item) {
+            "melee" => {
+                stats.push(Stat::new(&format!("{}_Attack", name), format!("=1d20{:+}", number(item, "/system/bonus/value").unwrap_or_default())));
+                let damage: Vec<&str> = item.pointer("/system/damageRolls").and_then(Value::as_object)
+                    .map(|rolls| rolls.values().filter_map(|roll| roll.get("damage").and_then(Value::as_str)).collect())
+                    .unwrap_or_default();
+                if !damage.is_empty() {
+                    stats.push(Stat::new(&format!("{}_Damage", name), format!("={}", damage.join("+"))));
+                }
+            },
+            "weapon" => {
+                let category = item.pointer("/system/category").and_then(Value::as_str).unwrap_or("simple");
+                let rank = number_at(system, &[&format!("/proficiencies/attacks/{}/rank", category), &format!("/martial/{}/rank", category)]).unwrap_or_default();
+                let prof = format!("{}_Prof", super::capitalize(category));
+                if rank > 0 {
+                    sheet.set_rank(category, Rank::from_bonus(rank * 2))?;
+                }
+
+                let ranged = number(item, "/system/range").is_some_and(|range| range > 0);
+                let finesse = item.pointer("/system/traits/value").and_then(Value::as_array)
+                    .is_some_and(|traits| traits.iter().any(|item_trait| item_trait == "finesse"));
+                let ability = if ranged || (finesse && sheet.modifier(Ability::Dex) > sheet.modifier(Ability::Str)) { "DEX" } else { "STR" };
+                let potency = number_at(item, &["/system/runes/potency", "/system/potencyRune/value"]).unwrap_or_default();
+
+                let mut attack = format!("=1d20+{}", ability);
+                if rank > 0 {
+                    attack.push_str(&format!("+{}", prof));
+                }
+                if potency > 0 {
+                    attack.push_str(&format!("+{}", potency));
+                }
+                stats.push(Stat::new(&format!("{}_Attack", name), attack));
+
+                let striking = match item.pointer("/system/runes/striking").and_then(Value::as_i64) {
+                    Some(extra) => extra + 1,
+                    None => damage_dice(item.pointer("/system/strikingRune/value").and_then(Value::as_str).unwrap_or_default()),
+                };
+                let dice = number(item, "/system/damage/dice").unwrap_or(1) - 1 + striking;
+                let die = item.pointer("/system/damage/die").and_then(Value::as_str).unwrap_or("d4");
+                let damage = match ranged {
+                    true => format!("={}{}", dice, die),
+                    false => format!("={}{}+STR", dice, die),
+                };
+                stats.push(Stat::new(&format!("{}_Damage", name), damage));
+            },
+            _ => continue,
+        }
+        names.push(name);
+    }
+    Ok(stats)
+}
+
+/// A check with a finished modifier, like an NPC's, becomes a stat of the character's own. Otherwise its rank goes on the sheet.
+/// Foundry saves ranks as 0 for untrained up to 4 for legendary.
+fn check(sheet: &mut Sheet, name: &str, explicit: Option<i64>, rank: Option<i64>) -> Result<Option<Stat>> {
+    match explicit {
+        Some(value) => {
+            sheet.remove_proficiency(name);
+            Ok(Some(Stat::new(name, format!("=1d20{:+}", value))))
+        },
+        None => {
+            sheet.set_rank(name, Rank::from_bonus(rank.unwrap_or_default() * 2))?;
+            Ok(None)
+        },
+    }
+}
+
+/// Foundry's shortened names for skills, like `ath` for Athletics.
+fn short_key(skill: &str) -> String {
+    match skill {
+        "Intimidation" => String::from("itm"),
+        "Performance" => String::from("prf"),
+        skill => skill[..3].to_lowercase(),
+    }
+}
+
+/// Turn a character into a minimal Foundry actor. Returns the actor's JSON along with the names of stats Foundry has no place for.
+pub fn export(character: &Character) -> Result<(String, Vec<String>)> {
+    let mut system = json!({
+        "abilities": {},
+        "attributes": {},
+        "details": {},
+        "saves": {},
+    });
+    let mut items = Vec::new();
+    let mut left_out = Vec::new();
+    for stat in character.stats.iter() {
+        let name = stat.name();
+        let value = fixed_value(character, stat);
+        let path = match (name.as_str(), value) {
+            ("level", Some(_)) => vec!["details", "level", "value"],
+            (ability, Some(_)) if Ability::ALL.iter().any(|known| known.key() == ability) => vec!["abilities", ability, "mod"],
+            ("ac", Some(_)) => vec!["attributes", "ac", "value"],
+            ("perception", Some(_)) => vec!["perception", "mod"],
+            ("fortitude", Some(_)) | ("reflex", Some(_)) | ("will", Some(_)) => vec!["saves", name.as_str(), "value"],
+            ("hp", Some(value)) => {
+                let maximum = stat.maximum_value.as_ref().and_then(|maximum| maximum.parse::<i64>().ok()).unwrap_or(value);
+                insert(&mut system, &["attributes", "hp"], json!({ "value": value, "max": maximum, "temp": character.temp_hp }));
+                continue;
+            },
+            ("fp", Some(value)) => {
+                let maximum = stat.maximum_value.as_ref().and_then(|maximum| maximum.parse::<i64>().ok()).unwrap_or(value);
+                insert(&mut system, &["resources", "focus"], json!({ "value": value, "max": maximum }));
+                continue;
+            },
+            (name, Some(value)) if SKILLS.iter().any(|(skill, _)| skill.to_lowercase() == name) || name.ends_with("_lore") => {
+                items.push(json!({ "name": stat.display_name.replace('_', " "), "type": "lore", "system": { "mod": { "value": value } } }));
+                continue;
+            },
+            (name, _) if name.ends_with("_prof") => continue,
+            (name, _) if name.ends_with("_spell_attack") || name.ends_with("_spell_dc") => continue,
+            (name, _) if name.ends_with("_damage") && character.find_stat(&name.replace("_damage", "_attack")).is_some() => continue,
+            (name, Some(bonus)) if name.ends_with("_attack") => {
+                let weapon = &stat.display_name[..stat.display_name.len() - "_attack".len()];
+                let mut strike = json!({ "name": weapon.replace('_', " "), "type": "melee", "system": { "bonus": { "value": bonus } } });
+                if let Some(damage) = character.find_stat(&format!("{}_damage", weapon)).and_then(|damage| resolve(character, damage)) {
+                    strike["system"]["damageRolls"] = json!({ "0": { "damage": damage } });
+                }
+                items.push(strike);
+                continue;
+            },
+            _ => {
+                left_out.push(stat.display_name.clone());
+                continue;
+            },
+        };
+        insert(&mut system, &path, json!(value));
+    }
+    // Skills and lores can come from the sheet or be the character's own, so they're sorted to keep the order the same either way.
+    let lore_name = |item: &Value| if item["type"] == "lore" { item["name"].as_str().map(String::from) } else { None };
+    items.sort_by_key(|item| (lore_name(item).is_none(), lore_name(item)));
+
+    // Spellcasting goes together as one entry per tradition, since Foundry keeps the DC and attack bonus side by side.
+    for dc in character.stats.iter().filter(|stat| stat.name().ends_with("_spell_dc")) {
+        let tradition = &dc.display_name[..dc.display_name.len() - "_spell_dc".len()];
+        let attack = character.find_stat(&format!("{}_spell_attack", tradition)).and_then(|attack| fixed_value(character, attack));
+        match fixed_value(character, dc) {
+            Some(value) => items.push(json!({
+                "name": format!("{} Spells", tradition.replace('_', " ")),
+                "type": "spellcastingEntry",
+                "system": {
+                    "tradition": { "value": tradition.to_lowercase() },
+                    "prepared": { "value": "innate" },
+                    "spelldc": { "dc": value, "value": attack.unwrap_or(value - 10) },
+                },
+            })),
+            None => left_out.push(dc.display_name.clone()),
+        }
+    }
+
+    let actor = json!({
+        "name": character.name,
+        "type": match character.kind {
+            CharacterKind::Player => "character",
+            CharacterKind::Npc => "npc",
+        },
+        "system": system,
+        "items": items,
+    });
+    Ok((serde_json::to_string_pretty(&actor)?, left_out))
+}
+
+/// A stat's formula with every reference replaced, like `2d8+4` for `=2d8+STR`.
+fn resolve(character: &Character, stat: &Stat) -> Option<String> {
+    formula::resolve(character, stat.formula().unwrap_or(&stat.value)).ok()
+}
+
+/// Put a value deep inside some JSON, creating any objects on the way.
+fn insert(value: &mut Value, path: &[&str], inserted: Value) {
+    let (last, parents) = path.split_last().expect("Can't insert at an empty path!");
+    let mut object = value;
+    for key in parents {
+        object = object.as_object_mut().expect("Only objects can be inserted into!")
+            .entry(key.to_string()).or_insert_with(|| Value::Object(Map::new()));
+    }
+    object.as_object_mut().expect("Only objects can be inserted into!").insert(last.to_string(), inserted);
+}
+
+/// Foundry sometimes saves numbers as strings, like a speed of "25".
+fn number(value: &Value, path: &str) -> Option<i64> {
+    value.pointer(path).and_then(|number| number.as_i64().or_else(|| number.as_str()?.trim().parse().ok()))
+}
+
+/// The first number found at any of the paths, for things that have moved between versions of Foundry.
+fn number_at(value: &Value, paths: &[&str]) -> Option<i64> {
+    paths.iter().find_map(|path| number(value, path))
+}
+
+fn item_type(item: &Value) -> &str {
+    item.get("type").and_then(Value::as_str).unwrap_or_default()
+}
+
+fn item_name(item: &Value) -> &str {
+    item.get("name").and_then(Value::as_str).unwrap_or_default()
+}
+
+fn item_names(items: &[Value], kind: &str) -> String {
+    items.iter().filter(|item| item_type(item) == kind).map(item_name).collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOBLIN_WARRIOR: &str = include_str!("../../../fixtures/foundry/goblin-warrior.json");
+    const GOBLIN_PYRO: &str = include_str!("../../../fixtures/foundry/goblin-pyro.json");
+    const PLUNK: &str = include_str!("../../../fixtures/foundry/plunk.json");
+    const PATHBUILDER: &str = include_str!("../../../fixtures/pathbuilder/fighter.json");
+
+    fn total(character: &Character, name: &str) -> i64 {
+        fixed_value(character, character.find_stat(name).unwrap_or_else(|| panic!("{} has no {}", character.name, name))).unwrap()
+    }
+
+    fn value(character: &Character, name: &str) -> String {
+        character.find_stat(name).unwrap_or_else(|| panic!("{} has no {}", character.name, name)).to_string()
+    }
+
+    /// Everything that survives a round trip: each stat's fixed value, or its resolved formula for things like damage.
+    fn summary(character: &Character) -> Vec<(String, String)> {
+        let mut summary: Vec<(String, String)> = character.stats.iter()
+            .filter(|stat| !stat.name().ends_with("_prof") && stat.name() != "class_dc")
+            .map(|stat| (stat.name(), fixed_value(character, stat).map(|value| value.to_string()).or_else(|| resolve(character, stat)).unwrap()))
+            .collect();
+        summary.sort();
+        summary
+    }
+
+    #[test]
+    fn npc() {
+        let (goblin, description) = parse(GOBLIN_WARRIOR, 3).unwrap();
+        assert_eq!((goblin.name.as_str(), goblin.kind, goblin.owner), ("Goblin Warrior", CharacterKind::Npc, 3));
+        assert_eq!(description, "level -1 enemy");
+        assert_eq!(value(&goblin, "DEX"), "DEX: 3");
+        assert_eq!(value(&goblin, "HP"), "HP: 6/6");
+        assert_eq!(value(&goblin, "AC"), "AC: 16");
+        assert_eq!(value(&goblin, "Perception"), "Perception: =1d20+2");
+        assert_eq!(value(&goblin, "Reflex"), "Reflex: =1d20+7");
+        assert_eq!(value(&goblin, "Stealth"), "Stealth: =1d20+5");
+        assert_eq!(value(&goblin, "Arcana"), "Arcana: =1d20+INT");
+        assert_eq!(value(&goblin, "Dogslicer_Attack"), "Dogslicer_Attack: =1d20+8");
+        assert_eq!(value(&goblin, "Dogslicer_Damage"), "Dogslicer_Damage: =1d6");
+
+        let (pyro, _) = parse(GOBLIN_PYRO, 3).unwrap();
+        assert_eq!(value(&pyro, "Dagger_Damage"), "Dagger_Damage: =1d4+1d4");
+        assert_eq!(value(&pyro, "Arcane_Spell_DC"), "Arcane_Spell_DC: 17");
+        assert_eq!(value(&pyro, "Arcane_Spell_Attack"), "Arcane_Spell_Attack: =1d20+9");
+        assert_eq!(value(&pyro, "Alchemy_Lore"), "Alchemy_Lore: =1d20+4");
+        assert_eq!(value(&pyro, "FP"), "FP: 1/1");
+    }
+
+    #[test]
+    fn pc() {
+        let (plunk, description) = parse(PLUNK, 3).unwrap();
+        assert_eq!(plunk.kind, CharacterKind::Player);
+        assert_eq!(description, "level 3 Goblin Fighter");
+        assert_eq!(value(&plunk, "STR"), "STR: 4");
+        assert_eq!(value(&plunk, "HP"), "HP: 39/39");
+        assert!(plunk.find_stat("AC").is_none());
+        assert_eq!(value(&plunk, "Longsword_Attack"), "Longsword_Attack: =1d20+STR+Martial_Prof+1");
+        assert_eq!(value(&plunk, "Longsword_Damage"), "Longsword_Damage: =2d8+STR");
+        assert_eq!(value(&plunk, "Composite_Shortbow_Attack"), "Composite_Shortbow_Attack: =1d20+DEX+Martial_Prof");
+        assert_eq!(value(&plunk, "Composite_Shortbow_Damage"), "Composite_Shortbow_Damage: =1d6");
+        assert!(plunk.find_stat("FP").is_none());
+
+        // The same character from Pathbuilder comes out with the same numbers.
+        let (from_pathbuilder, _) = super::super::pathbuilder::parse(PATHBUILDER, 3).unwrap();
+        for name in ["Athletics", "Perception", "Fortitude", "Will", "Warfare_Lore", "Longsword_Attack", "Composite_Shortbow_Attack", "Arcana"].iter() {
+            assert_eq!(total(&plunk, name), total(&from_pathbuilder, name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for actor in [GOBLIN_WARRIOR, GOBLIN_PYRO, PLUNK, PATHBUILDER].iter() {
+            let original = match parse(actor, 3) {
+                Ok((character, _)) => character,
+                Err(_) => super::super::pathbuilder::parse(actor, 3).unwrap().0,
+            };
+            let (exported, _) = export(&original).unwrap();
+            let (imported, _) = parse(&exported, 3).unwrap();
+            assert_eq!((imported.name.as_str(), imported.kind), (original.name.as_str(), original.kind));
+            assert_eq!(summary(&imported), summary(&original), "{}", original.name);
+            // And exporting again gives exactly the same actor.
+            assert_eq!(export(&imported).unwrap().0, exported);
+        }
+    }
+
+    #[test]
+    fn exported_npcs_match_foundry() {
+        let (goblin, _) = parse(GOBLIN_WARRIOR, 3).unwrap();
+        let (exported, left_out) = export(&goblin).unwrap();
+        assert!(left_out.is_empty(), "{:?}", left_out);
+        let exported: Value = serde_json::from_str(&exported).unwrap();
+        let original: Value = serde_json::from_str(GOBLIN_WARRIOR).unwrap();
+        for path in ["/type", "/name", "/system/attributes/ac/value", "/system/attributes/hp/max", "/system/perception/mod", "/system/saves/reflex/value", "/system/abilities/wis/mod"].iter() {
+            assert_eq!(exported.pointer(path), original.pointer(path), "{}", path);
+        }
+        let dogslicer = &exported["items"].as_array().unwrap().iter().find(|item| item["name"] == "Dogslicer").unwrap()["system"];
+        assert_eq!((&dogslicer["bonus"]["value"], &dogslicer["damageRolls"]["0"]["damage"]), (&json!(8), &json!("1d6")));
+    }
+
+    #[test]
+    fn stats_foundry_cant_hold_are_left_out() {
+        let (mut plunk, _) = parse(PLUNK, 3).unwrap();
+        plunk.stats.push(Stat::new("Hero_Points", String::from("1")));
+        plunk.stats.push(Stat::new("Bomb", String::from("=2d6")));
+        assert_eq!(export(&plunk).unwrap().1, vec!["Hero_Points", "Bomb"]);
+    }
+
+    #[test]
+    fn bad_actors() {
+        assert!(parse("not json", 3).unwrap_err().to_string().contains("doesn't look like a Foundry actor"));
+        assert!(parse(r#"{"name": "Loot Pile", "type": "loot", "system": {}}"#, 3).unwrap_err().to_string().contains("not a 'loot'"));
+        let without_abilities = PLUNK.replace("\"abilities\"", "\"build\"");
+        assert!(parse(&without_abilities, 3).unwrap_err().to_string().contains("Pathbuilder"));
+    }
+}
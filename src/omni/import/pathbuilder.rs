@@ -0,0 +1,250 @@
+//! Pathbuilder 2e exports a character as JSON, from its "Export JSON" menu. The export has the raw ability scores and
+//! proficiency ranks rather than finished modifiers, so they go on the character's sheet, which makes stats like
+//! `Athletics:=1d20+STR+Athletics_Prof` out of them that stay correct if a score, rank or the character's level is changed later.
+
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use crate::omni::character::{Character, CharacterKind};
+use crate::omni::character::sheet::{Ability, Proficiency, ProficiencyKind, Rank, Sheet, modifier};
+use crate::omni::character::stat::Stat;
+use super::{capitalize, damage_dice, identifier, tracked};
+
+/// Pathbuilder's exports wrap the character in a little envelope.
+#[derive(Deserialize)]
+struct Export {
+    build: Build,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Build {
+    name: String,
+    class: String,
+    level: i64,
+    #[serde(default)]
+    ancestry: String,
+    #[serde(default)]
+    keyability: String,
+    attributes: Attributes,
+    abilities: HashMap<String, serde_json::Value>,
+    /// Proficiency ranks, as the bonus the rank gives before adding the character's level: 0 for untrained, then 2, 4, 6 and 8.
+    proficiencies: HashMap<String, i64>,
+    #[serde(default)]
+    lores: Vec<(String, i64)>,
+    #[serde(default)]
+    weapons: Vec<Weapon>,
+    #[serde(default)]
+    spell_casters: Vec<SpellCaster>,
+    #[serde(default)]
+    focus_points: i64,
+    ac_total: ArmorClass,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Attributes {
+    ancestryhp: i64,
+    classhp: i64,
+    #[serde(default)]
+    bonushp: i64,
+    #[serde(default)]
+    bonushp_per_level: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Weapon {
+    name: String,
+    die: String,
+    /// The striking rune, if any, which adds damage dice.
+    #[serde(default)]
+    str: String,
+    /// The total attack bonus, which Pathbuilder works out itself.
+    attack: i64,
+    #[serde(default)]
+    damage_bonus: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SpellCaster {
+    magic_tradition: String,
+    ability: String,
+    proficiency: i64,
+    #[serde(default)]
+    innate: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArmorClass {
+    ac_total: i64,
+}
+
+/// Turn a Pathbuilder export into a player character owned by the importing user.
+/// Returns the character along with a short description of who they are, like `level 3 Goblin Fighter`.
+pub fn parse(json: &str, owner: u64) -> Result<(Character, String)> {
+    let build = serde_json::from_str::<Export>(json).map(|export| export.build)
+        .or_else(|_| serde_json::from_str::<Build>(json))
+        .map_err(|error| anyhow!("That doesn't look like a Pathbuilder export: {}", error))?;
+    let mut character = Character::new(&build.name, CharacterKind::Player, owner);
+    character.set_sheet(sheet(&build)?);
+    character.stats.extend(stats(&build));
+    let description = format!("level {} {} {}", build.level, build.ancestry, build.class).replace("  ", " ");
+    Ok((character, description))
+}
+
+/// The character's level, ability scores and every proficiency Pathbuilder gives a rank for.
+fn sheet(build: &Build) -> Result<Sheet> {
+    let mut abilities = [10; 6];
+    for (index, ability) in Ability::ALL.iter().enumerate() {
+        abilities[index] = score(build, *ability).ok_or_else(|| anyhow!("The export is missing the {} score.", ability.stat_name()))?;
+    }
+    let mut sheet = Sheet::new(build.level, abilities);
+    for proficiency in sheet.proficiencies.iter_mut() {
+        proficiency.rank = Rank::from_bonus(rank(build, &proficiency.name.to_lowercase()));
+    }
+    for (topic, bonus) in build.lores.iter() {
+        sheet.set_rank(&format!("{}_Lore", identifier(topic)), Rank::from_bonus(*bonus))?;
+    }
+    for category in ["Unarmed", "Simple", "Martial", "Advanced"].iter().filter(|category| rank(build, &category.to_lowercase()) > 0) {
+        sheet.set_rank(category, Rank::from_bonus(rank(build, &category.to_lowercase())))?;
+    }
+    if let Some(key_ability) = Ability::parse(&build.keyability) {
+        sheet.proficiencies.push(Proficiency::new("Class", ProficiencyKind::Class(key_ability), Rank::from_bonus(rank(build, "classdc"))));
+    }
+
+    // Innate spells use whichever spellcasting proficiency is best, which isn't worth working out here.
+    for caster in build.spell_casters.iter().filter(|caster| !caster.innate && caster.proficiency > 0) {
+        let tradition = capitalize(&caster.magic_tradition);
+        if sheet.find_proficiency(&tradition).is_some() {
+            continue;
+        }
+        let ability = Ability::parse(&caster.ability)
+            .ok_or_else(|| anyhow!("{} casts {} spells with '{}', which isn't an ability.", build.name, caster.magic_tradition, caster.ability))?;
+        sheet.proficiencies.push(Proficiency::new(&tradition, ProficiencyKind::Spell(ability), Rank::from_bonus(caster.proficiency)));
+    }
+    Ok(sheet)
+}
+
+/// Everything that isn't on the sheet: HP, AC, strikes and focus points.
+fn stats(build: &Build) -> Vec<Stat> {
+    let constitution = modifier(score(build, Ability::Con).unwrap_or(10));
+    let hit_points = build.attributes.ancestryhp + build.attributes.bonushp
+        + (build.attributes.classhp + build.attributes.bonushp_per_level + constitution) * build.level;
+    let mut stats = vec![
+        tracked("HP", hit_points, hit_points),
+        Stat::new("AC", build.ac_total.ac_total.to_string()),
+    ];
+
+    let mut weapons = Vec::new();
+    for weapon in build.weapons.iter() {
+        let name = identifier(&weapon.name);
+        if weapons.contains(&name) {
+            continue;
+        }
+        stats.push(Stat::new(&format!("{}_Attack", name), format!("=1d20{:+}", weapon.attack)));
+        let damage = format!("={}{}", damage_dice(&weapon.str), weapon.die);
+        stats.push(Stat::new(&format!("{}_Damage", name), match weapon.damage_bonus {
+            0 => damage,
+            bonus => format!("{}{:+}", damage, bonus),
+        }));
+        weapons.push(name);
+    }
+    if build.focus_points > 0 {
+        stats.push(tracked("FP", build.focus_points, build.focus_points));
+    }
+    stats
+}
+
+fn score(build: &Build, ability: Ability) -> Option<i64> {
+    build.abilities.get(ability.key()).and_then(serde_json::Value::as_i64)
+}
+
+fn rank(build: &Build, name: &str) -> i64 {
+    build.proficiencies.iter().find(|(key, _)| key.to_lowercase() == name).map_or(0, |(_, rank)| *rank)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::omni::character::formula;
+    use crate::omni::import::fixed_value;
+
+    const FIGHTER: &str = include_str!("../../../fixtures/pathbuilder/fighter.json");
+    const CLERIC: &str = include_str!("../../../fixtures/pathbuilder/cleric.json");
+
+    fn value(character: &Character, name: &str) -> String {
+        character.find_stat(name).unwrap_or_else(|| panic!("{} has no {}", character.name, name)).to_string()
+    }
+
+    fn total(character: &Character, name: &str) -> i64 {
+        fixed_value(character, character.find_stat(name).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn fighter() {
+        let (plunk, description) = parse(FIGHTER, 7).unwrap();
+        assert_eq!((plunk.name.as_str(), plunk.owner, plunk.kind), ("Plunk", 7, CharacterKind::Player));
+        assert_eq!(description, "level 3 Goblin Fighter");
+        assert_eq!(value(&plunk, "STR"), "STR: 4");
+        assert_eq!(value(&plunk, "CHA"), "CHA: -1");
+        assert_eq!(value(&plunk, "HP"), "HP: 46/46");
+        assert_eq!(value(&plunk, "AC"), "AC: 20");
+        assert_eq!(value(&plunk, "Athletics"), "Athletics: =1d20+STR+Athletics_Prof");
+        assert_eq!(value(&plunk, "Athletics_Prof"), "Athletics_Prof: 7");
+        assert_eq!(value(&plunk, "Martial_Prof"), "Martial_Prof: 7");
+        assert_eq!(value(&plunk, "Arcana"), "Arcana: =1d20+INT");
+        assert!(plunk.find_stat("Arcana_Prof").is_none());
+        assert_eq!(value(&plunk, "Longsword_Attack"), "Longsword_Attack: =1d20+12");
+        assert_eq!(value(&plunk, "Longsword_Damage"), "Longsword_Damage: =2d8+4");
+        assert_eq!(value(&plunk, "Composite_Shortbow_Damage"), "Composite_Shortbow_Damage: =1d6+2");
+        assert!(plunk.find_stat("FP").is_none());
+
+        assert_eq!(total(&plunk, "Athletics"), 11);
+        assert_eq!(total(&plunk, "Fortitude"), 9);
+        assert_eq!(total(&plunk, "Will"), 6);
+        assert_eq!(total(&plunk, "Warfare_Lore"), 5);
+        assert_eq!(total(&plunk, "Class_DC"), 19);
+
+        let sheet = plunk.sheet.as_ref().unwrap();
+        assert_eq!((sheet.level, sheet.score(Ability::Str)), (3, 18));
+        assert_eq!(sheet.find_proficiency("Athletics").unwrap().rank, Rank::Expert);
+        assert_eq!(sheet.find_proficiency("Warfare_Lore").unwrap().rank, Rank::Trained);
+    }
+
+    #[test]
+    fn cleric() {
+        let (tilly, description) = parse(CLERIC, 7).unwrap();
+        assert_eq!(tilly.name, "Tilly Brightwater");
+        assert_eq!(description, "level 5 Human Cleric");
+        assert_eq!(value(&tilly, "HP"), "HP: 63/63");
+        assert_eq!(value(&tilly, "FP"), "FP: 1/1");
+        assert!(tilly.find_stat("FP").unwrap().display_on_tracker);
+        assert_eq!(total(&tilly, "Divine_Spell_DC"), 21);
+        assert_eq!(total(&tilly, "Divine_Spell_Attack"), 11);
+        assert_eq!(total(&tilly, "Religion"), 13);
+        assert!(tilly.find_stat("Arcane_Spell_DC").is_none());
+        assert!(tilly.find_stat("Sarenrae_Lore").is_some());
+        assert_eq!(tilly.sheet.as_ref().unwrap().find_proficiency("Divine").unwrap().kind, ProficiencyKind::Spell(Ability::Wis));
+    }
+
+    #[test]
+    fn every_stat_can_be_rolled() {
+        for export in [FIGHTER, CLERIC].iter() {
+            let (character, _) = parse(export, 7).unwrap();
+            for stat in character.stats.iter().filter(|stat| stat.formula().is_some()) {
+                assert!(formula::evaluate(&character, stat.formula().unwrap()).is_ok(), "{}", stat);
+            }
+        }
+    }
+
+    #[test]
+    fn bad_exports() {
+        assert!(parse("not json", 7).unwrap_err().to_string().contains("doesn't look like a Pathbuilder export"));
+        assert!(parse(r#"{"success": false}"#, 7).is_err());
+        let without_strength = FIGHTER.replace("\"str\": 18,", "");
+        assert!(parse(&without_strength, 7).unwrap_err().to_string().contains("STR"));
+    }
+}
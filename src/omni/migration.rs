@@ -0,0 +1,474 @@
+//! Saved omnidata outlives the version of the bot that wrote it. Every time the shape of `Omnidata`
+//! (or anything it contains) changes, OMNI_VERSION is bumped and a migration is added here that upgrades
+//! the previous version's JSON by exactly one step. On load, the steps are run in order until the data
+//! is current, and each one that ran is recorded in the omnidata itself.
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use super::{OMNI_VERSION, Omnidata};
+
+/// A record of a migration that was run against a guild's data.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MigrationRecord {
+    pub from: u16,
+    pub to: u16,
+    pub description: String,
+}
+
+/// A single upgrade step, taking serialized omnidata from version `from` to `from + 1`.
+struct Migration {
+    from: u16,
+    description: &'static str,
+    upgrade: fn(&mut Value) -> Result<()>,
+}
+
+/// Every migration ever written, in order. The index of each entry must match its `from` version.
+const MIGRATIONS: [Migration; 12] = [
+    Migration {
+        from: 0,
+        description: "Start recording which migrations have been applied",
+        upgrade: upgrade_v0_to_v1,
+    },
+    Migration {
+        from: 1,
+        description: "Track temporary HP on each character",
+        upgrade: upgrade_v1_to_v2,
+    },
+    Migration {
+        from: 2,
+        description: "Track immunities, weaknesses and resistances on each character",
+        upgrade: upgrade_v2_to_v3,
+    },
+    Migration {
+        from: 3,
+        description: "Give characters IDs and keep effects separately, referring to characters by ID",
+        upgrade: upgrade_v3_to_v4,
+    },
+    Migration {
+        from: 4,
+        description: "Track conditions on each character",
+        upgrade: upgrade_v4_to_v5,
+    },
+    Migration {
+        from: 5,
+        description: "Let effects give bonuses and penalties",
+        upgrade: upgrade_v5_to_v6,
+    },
+    Migration {
+        from: 6,
+        description: "Keep track of encounters",
+        upgrade: upgrade_v6_to_v7,
+    },
+    Migration {
+        from: 7,
+        description: "Remember the tracker message in each channel",
+        upgrade: upgrade_v7_to_v8,
+    },
+    Migration {
+        from: 8,
+        description: "Remember the GM role",
+        upgrade: upgrade_v8_to_v9,
+    },
+    Migration {
+        from: 9,
+        description: "Give characters an optional PF2e sheet",
+        upgrade: upgrade_v9_to_v10,
+    },
+    Migration {
+        from: 10,
+        description: "Log rolls so they can be checked once their seed is revealed",
+        upgrade: upgrade_v10_to_v11,
+    },
+    Migration {
+        from: 11,
+        description: "Keep the roll log apart from the revisions, so undo can't take it back",
+        upgrade: upgrade_v11_to_v12,
+    },
+];
+
+/// Deserialize omnidata saved by any version of the bot, upgrading it to the current version first.
+/// If any migrations had to run, the returned omnidata is marked dirty so the upgrade gets saved.
+pub fn load(data: &str) -> Result<Omnidata> {
+    let mut value: Value = serde_json::from_str(data).context("Saved bot data is not valid JSON")?;
+    let applied = upgrade(&mut value)?;
+    let mut omnidata: Omnidata = serde_json::from_value(value).context("Saved bot data doesn't match the current format")?;
+    if !applied.is_empty() {
+        omnidata.dirty();
+    }
+    Ok(omnidata)
+}
+
+/// Run every migration needed to bring the JSON up to OMNI_VERSION, one version at a time.
+/// Returns the records of the migrations that ran, which are also appended to the data's own log.
+fn upgrade(value: &mut Value) -> Result<Vec<MigrationRecord>> {
+    let mut version = saved_version(value)?;
+    if version > OMNI_VERSION {
+        return Err(anyhow!("Saved bot data is version {}, but this bot only understands up to version {}. Is an older bot running?", version, OMNI_VERSION));
+    }
+
+    let mut applied = Vec::new();
+    while version < OMNI_VERSION {
+        let migration = MIGRATIONS.get(version as usize)
+            .filter(|migration| migration.from == version)
+            .ok_or_else(|| anyhow!("No migration found to upgrade bot data from version {}", version))?;
+        (migration.upgrade)(value).with_context(|| format!("Migration from version {} failed", version))?;
+        version += 1;
+
+        let record = MigrationRecord {
+            from: migration.from,
+            to: version,
+            description: migration.description.to_string(),
+        };
+        record_migration(value, &record)?;
+        applied.push(record);
+    }
+    Ok(applied)
+}
+
+/// Reads the version out of the saved data. Really old data may not have one, which means version 0.
+fn saved_version(value: &Value) -> Result<u16> {
+    match value.get("version") {
+        None => Ok(0),
+        Some(version) => version.as_u64()
+            .filter(|&version| version <= u16::MAX as u64)
+            .map(|version| version as u16)
+            .ok_or_else(|| anyhow!("Saved bot data has an invalid version: {}", version)),
+    }
+}
+
+fn record_migration(value: &mut Value, record: &MigrationRecord) -> Result<()> {
+    let object = value.as_object_mut().ok_or_else(|| anyhow!("Saved bot data is not a JSON object"))?;
+    object.insert("version".to_string(), json!(record.to));
+    object.entry("migrations")
+        .or_insert_with(|| json!([]))
+        .as_array_mut()
+        .ok_or_else(|| anyhow!("Saved migration log is not a list"))?
+        .push(serde_json::to_value(record)?);
+    Ok(())
+}
+
+/// Version 1 added the migration log.
+fn upgrade_v0_to_v1(value: &mut Value) -> Result<()> {
+    let object = value.as_object_mut().ok_or_else(|| anyhow!("Saved bot data is not a JSON object"))?;
+    object.insert("migrations".to_string(), json!([]));
+    Ok(())
+}
+
+/// Version 2 added temporary HP to characters, which nobody had any of yet.
+fn upgrade_v1_to_v2(value: &mut Value) -> Result<()> {
+    for character in characters_mut(value)? {
+        let character = character.as_object_mut().ok_or_else(|| anyhow!("Saved character is not a JSON object"))?;
+        character.insert("temp_hp".to_string(), json!(0));
+    }
+    Ok(())
+}
+
+/// Version 3 added immunities, weaknesses and resistances to characters.
+fn upgrade_v2_to_v3(value: &mut Value) -> Result<()> {
+    for character in characters_mut(value)? {
+        let character = character.as_object_mut().ok_or_else(|| anyhow!("Saved character is not a JSON object"))?;
+        character.insert("defenses".to_string(), json!([]));
+    }
+    Ok(())
+}
+
+/// Version 4 gave every character an ID, and moved effects off the characters into their own list.
+/// Effects used to hold whole copies of the characters involved and a real-world duration, so they're matched up
+/// with characters by name and their durations turned into rounds of 6 seconds each.
+fn upgrade_v3_to_v4(value: &mut Value) -> Result<()> {
+    let mut old_effects = Vec::new();
+    let mut ids = Vec::new();
+    for (index, character) in characters_mut(value)?.iter_mut().enumerate() {
+        let character = character.as_object_mut().ok_or_else(|| anyhow!("Saved character is not a JSON object"))?;
+        let id = index as u64 + 1;
+        character.insert("id".to_string(), json!(id));
+        let name = character.get("name").and_then(Value::as_str).unwrap_or_default().to_lowercase();
+        ids.push((name, id));
+        if let Some(Value::Array(effects)) = character.remove("effects") {
+            old_effects.extend(effects.into_iter().map(|effect| (id, effect)));
+        }
+    }
+
+    let id_of = |character: &Value| character.get("name").and_then(Value::as_str)
+        .and_then(|name| ids.iter().find(|(known, _)| *known == name.to_lowercase()))
+        .map(|(_, id)| *id);
+    let mut effects = Vec::new();
+    for (holder, effect) in old_effects {
+        let seconds = effect["duration"][0].as_i64().unwrap_or(0);
+        let duration = if seconds > 0 { json!({ "Rounds": (seconds + 5) / 6 }) } else { json!("Unlimited") };
+        effects.push(json!({
+            "name": effect["name"].as_str().unwrap_or("Unnamed effect"),
+            "target": id_of(&effect["target"]).unwrap_or(holder),
+            "source": id_of(&effect["owner"]),
+            "duration": duration,
+        }));
+    }
+
+    let object = value.as_object_mut().ok_or_else(|| anyhow!("Saved bot data is not a JSON object"))?;
+    object.insert("next_character_id".to_string(), json!(ids.len() + 1));
+    object.insert("effects".to_string(), Value::Array(effects));
+    Ok(())
+}
+
+/// Version 5 added conditions to characters.
+fn upgrade_v4_to_v5(value: &mut Value) -> Result<()> {
+    for character in characters_mut(value)? {
+        let character = character.as_object_mut().ok_or_else(|| anyhow!("Saved character is not a JSON object"))?;
+        character.insert("conditions".to_string(), json!([]));
+    }
+    Ok(())
+}
+
+/// Version 6 let effects give bonuses and penalties. Nothing saved before then had any.
+fn upgrade_v5_to_v6(value: &mut Value) -> Result<()> {
+    let effects = value.get_mut("effects")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| anyhow!("Saved bot data has no list of effects"))?;
+    for effect in effects {
+        let effect = effect.as_object_mut().ok_or_else(|| anyhow!("Saved effect is not a JSON object"))?;
+        effect.insert("modifiers".to_string(), json!([]));
+    }
+    Ok(())
+}
+
+/// Version 7 added encounters. Nothing saved before then was in one.
+fn upgrade_v6_to_v7(value: &mut Value) -> Result<()> {
+    let object = value.as_object_mut().ok_or_else(|| anyhow!("Saved bot data is not a JSON object"))?;
+    object.insert("encounter".to_string(), Value::Null);
+    Ok(())
+}
+
+/// Version 8 added tracker messages. There weren't any before then.
+fn upgrade_v7_to_v8(value: &mut Value) -> Result<()> {
+    let object = value.as_object_mut().ok_or_else(|| anyhow!("Saved bot data is not a JSON object"))?;
+    object.insert("trackers".to_string(), json!([]));
+    Ok(())
+}
+
+/// Version 9 added a GM role. Until one is chosen, nothing is hidden from anyone, just like before.
+fn upgrade_v8_to_v9(value: &mut Value) -> Result<()> {
+    let object = value.as_object_mut().ok_or_else(|| anyhow!("Saved bot data is not a JSON object"))?;
+    object.insert("gm_role".to_string(), Value::Null);
+    Ok(())
+}
+
+/// Version 10 added PF2e sheets to characters. Nobody had one yet, so all of their stats stay their own.
+fn upgrade_v9_to_v10(value: &mut Value) -> Result<()> {
+    for character in characters_mut(value)? {
+        let character = character.as_object_mut().ok_or_else(|| anyhow!("Saved character is not a JSON object"))?;
+        character.insert("sheet".to_string(), Value::Null);
+    }
+    Ok(())
+}
+
+/// Version 11 started logging rolls, each guild starting on its first seed.
+fn upgrade_v10_to_v11(value: &mut Value) -> Result<()> {
+    let object = value.as_object_mut().ok_or_else(|| anyhow!("Saved bot data is not a JSON object"))?;
+    object.insert("roll_log".to_string(), json!({"epoch": 0, "commitment": null, "rolls": [], "revealed": []}));
+    Ok(())
+}
+
+/// Version 12 stopped saving the roll log with everything else, since going back to an older revision would take it back
+/// too. It's left where it is, so it can still be read once and moved to its own place in the store.
+fn upgrade_v11_to_v12(value: &mut Value) -> Result<()> {
+    value.as_object().ok_or_else(|| anyhow!("Saved bot data is not a JSON object"))?;
+    Ok(())
+}
+
+/// The saved list of characters, for migrations that change every character.
+fn characters_mut(value: &mut Value) -> Result<&mut Vec<Value>> {
+    value.get_mut("characters")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| anyhow!("Saved bot data has no list of characters"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V0: &str = include_str!("../../fixtures/omnidata/v0.json");
+    const V1: &str = include_str!("../../fixtures/omnidata/v1.json");
+    const V2: &str = include_str!("../../fixtures/omnidata/v2.json");
+    const V3: &str = include_str!("../../fixtures/omnidata/v3.json");
+    const V4: &str = include_str!("../../fixtures/omnidata/v4.json");
+    const V5: &str = include_str!("../../fixtures/omnidata/v5.json");
+    const V6: &str = include_str!("../../fixtures/omnidata/v6.json");
+    const V7: &str = include_str!("../../fixtures/omnidata/v7.json");
+    const V8: &str = include_str!("../../fixtures/omnidata/v8.json");
+    const V9: &str = include_str!("../../fixtures/omnidata/v9.json");
+    const V10: &str = include_str!("../../fixtures/omnidata/v10.json");
+    const V11: &str = include_str!("../../fixtures/omnidata/v11.json");
+
+    /// Every saved fixture, indexed by version. Add the new fixture here when adding a migration.
+    const FIXTURES: [&str; 12] = [V0, V1, V2, V3, V4, V5, V6, V7, V8, V9, V10, V11];
+
+    fn fixture(data: &str) -> Value {
+        serde_json::from_str(data).unwrap()
+    }
+
+    #[test]
+    fn migrations_are_contiguous() {
+        assert_eq!(MIGRATIONS.len(), OMNI_VERSION as usize);
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.from as usize, index);
+        }
+    }
+
+    #[test]
+    fn every_fixture_loads() {
+        for data in FIXTURES.iter() {
+            let omnidata = load(data).unwrap();
+            assert_eq!(omnidata.version, OMNI_VERSION);
+        }
+    }
+
+    #[test]
+    fn current_data_is_untouched() {
+        let mut omnidata = Omnidata::new();
+        omnidata.characters = load(V0).unwrap().characters;
+        let serialized = serde_json::to_string(&omnidata).unwrap();
+        let loaded = load(&serialized).unwrap();
+        assert!(!loaded.is_dirty);
+        assert!(loaded.migrations.is_empty());
+    }
+
+    #[test]
+    fn newer_data_is_rejected() {
+        let data = format!("{{\"version\": {}, \"is_dirty\": false, \"characters\": []}}", OMNI_VERSION + 1);
+        assert!(load(&data).is_err());
+    }
+
+    #[test]
+    fn missing_version_is_v0() {
+        let mut value = fixture(V0);
+        value.as_object_mut().unwrap().remove("version");
+        assert_eq!(saved_version(&value).unwrap(), 0);
+    }
+
+    #[test]
+    fn upgrade_v0() {
+        let mut value = fixture(V0);
+        let applied = upgrade(&mut value).unwrap();
+        assert_eq!(applied.len(), OMNI_VERSION as usize);
+        assert_eq!(applied[0], MigrationRecord { from: 0, to: 1, description: MIGRATIONS[0].description.to_string() });
+
+        let omnidata: Omnidata = serde_json::from_value(value).unwrap();
+        assert_eq!(omnidata.characters.len(), 2);
+        assert_eq!(omnidata.characters[0].name, "Plunk");
+        assert_eq!(omnidata.characters[0].stats[0].maximum_value, Some(String::from("30")));
+        assert_eq!(omnidata.migrations, applied);
+    }
+
+    #[test]
+    fn upgrade_v0_to_v1_step() {
+        let mut value = fixture(V0);
+        upgrade_v0_to_v1(&mut value).unwrap();
+        assert_eq!(value["migrations"], json!([]));
+        assert_eq!(value["characters"], fixture(V0)["characters"]);
+    }
+
+    #[test]
+    fn upgrade_v1_to_v2_step() {
+        let mut value = fixture(V1);
+        upgrade_v1_to_v2(&mut value).unwrap();
+        for character in value["characters"].as_array().unwrap() {
+            assert_eq!(character["temp_hp"], json!(0));
+        }
+        assert_eq!(value["characters"][0]["stats"], fixture(V1)["characters"][0]["stats"]);
+    }
+
+    #[test]
+    fn upgrade_v2_to_v3_step() {
+        let mut value = fixture(V2);
+        upgrade_v2_to_v3(&mut value).unwrap();
+        for character in value["characters"].as_array().unwrap() {
+            assert_eq!(character["defenses"], json!([]));
+        }
+        assert_eq!(value["characters"][0]["temp_hp"], json!(4));
+    }
+
+    #[test]
+    fn upgrade_v3_to_v4_step() {
+        let mut value = fixture(V3);
+        upgrade_v3_to_v4(&mut value).unwrap();
+        assert_eq!(value["characters"][0]["id"], json!(1));
+        assert_eq!(value["characters"][1]["id"], json!(2));
+        assert_eq!(value["next_character_id"], json!(3));
+        assert!(value["characters"][0].get("effects").is_none());
+        assert_eq!(value["effects"], json!([{ "name": "Bless", "target": 1, "source": 2, "duration": { "Rounds": 10 } }]));
+    }
+
+    #[test]
+    fn upgrade_v4_to_v5_step() {
+        let mut value = fixture(V4);
+        upgrade_v4_to_v5(&mut value).unwrap();
+        for character in value["characters"].as_array().unwrap() {
+            assert_eq!(character["conditions"], json!([]));
+        }
+        assert_eq!(value["effects"], fixture(V4)["effects"]);
+    }
+
+    #[test]
+    fn upgrade_v5_to_v6_step() {
+        let mut value = fixture(V5);
+        upgrade_v5_to_v6(&mut value).unwrap();
+        for effect in value["effects"].as_array().unwrap() {
+            assert_eq!(effect["modifiers"], json!([]));
+        }
+        assert_eq!(value["characters"], fixture(V5)["characters"]);
+    }
+
+    #[test]
+    fn upgrade_v6_to_v7_step() {
+        let mut value = fixture(V6);
+        upgrade_v6_to_v7(&mut value).unwrap();
+        assert_eq!(value["encounter"], Value::Null);
+        assert_eq!(value["effects"], fixture(V6)["effects"]);
+    }
+
+    #[test]
+    fn upgrade_v7_to_v8_step() {
+        let mut value = fixture(V7);
+        upgrade_v7_to_v8(&mut value).unwrap();
+        assert_eq!(value["trackers"], json!([]));
+        assert_eq!(value["encounter"], fixture(V7)["encounter"]);
+    }
+
+    #[test]
+    fn upgrade_v8_to_v9_step() {
+        let mut value = fixture(V8);
+        upgrade_v8_to_v9(&mut value).unwrap();
+        assert_eq!(value["gm_role"], Value::Null);
+        assert_eq!(value["trackers"], fixture(V8)["trackers"]);
+    }
+
+    #[test]
+    fn upgrade_v9_to_v10_step() {
+        let mut value = fixture(V9);
+        upgrade_v9_to_v10(&mut value).unwrap();
+        for character in value["characters"].as_array().unwrap() {
+            assert_eq!(character["sheet"], Value::Null);
+        }
+        assert_eq!(value["characters"][0]["stats"], fixture(V9)["characters"][0]["stats"]);
+        assert_eq!(value["gm_role"], fixture(V9)["gm_role"]);
+    }
+
+    #[test]
+    fn upgrade_v10_to_v11_step() {
+        let mut value = fixture(V10);
+        upgrade_v10_to_v11(&mut value).unwrap();
+        let omnidata: Omnidata = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(omnidata.roll_log, Default::default());
+        assert_eq!(value["characters"], fixture(V10)["characters"]);
+    }
+
+    #[test]
+    fn upgrade_v11_to_v12_step() {
+        let mut value = fixture(V11);
+        upgrade_v11_to_v12(&mut value).unwrap();
+        let omnidata: Omnidata = serde_json::from_value(value).unwrap();
+        assert_eq!((omnidata.roll_log.epoch, omnidata.roll_log.rolls.len()), (1, 1));
+        assert!(serde_json::to_value(&omnidata).unwrap().get("roll_log").is_none());
+    }
+}
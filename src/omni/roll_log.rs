@@ -0,0 +1,331 @@
+//! Every guild's rolls come from a secret seed, so they can be checked afterwards. Before a seed is used, its SHA-1 is shown by
+//! `!rolls`, and once a GM reveals it with `!rolls reveal`, anyone can check that it matches and roll the logged expressions
+//! again to get the same dice. Each command rolls from its own stream of the seed, numbered by the ID of the command's
+//! Discord message, so no two commands ever share dice and each one can be rolled again on its own.
+//!
+//! Seeds are worked out from the bot's secret, the guild and how many seeds it has used before, so nothing secret is saved
+//! with the guild's data. DICE_SECRET keeps the same secret across restarts. Without it, a new secret is made each time
+//! the bot starts, and a seed that was in use when it stopped can never be revealed.
+
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use crate::dice::{self, DiceRoll, Seeded, Source};
+use super::Omnidata;
+use super::gm::Redacted;
+
+/// Older commands are forgotten once the log holds more rolls than this.
+const MAX_LOGGED: usize = 200;
+const MAX_REVEALED: usize = 10;
+/// How many of the latest rolls `!rolls` shows.
+const SHOWN_ROLLS: usize = 15;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct RollLog {
+    /// How many seeds the guild has used before the current one.
+    pub epoch: u64,
+    /// The SHA-1 of the current seed, in hex. It's worked out the first time the seed is needed, and kept so the seed can't
+    /// be swapped for another later.
+    pub commitment: Option<String>,
+    /// The latest rolls, oldest first.
+    pub rolls: Vec<LoggedRoll>,
+    /// Seeds that are no longer used, newest last.
+    pub revealed: Vec<RevealedSeed>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoggedRoll {
+    /// Which seed the roll came from.
+    pub epoch: u64,
+    /// The ID of the message that asked for the roll, which is also the stream of the seed it came from.
+    pub message_id: u64,
+    pub author: u64,
+    pub expression: String,
+    pub breakdown: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RevealedSeed {
+    pub epoch: u64,
+    pub commitment: String,
+    /// The seed in hex, or None if it was lost because the bot's secret changed before it could be revealed.
+    pub seed: Option<String>,
+}
+
+impl RollLog {
+    /// Where a command's dice come from: the guild's current seed, on the stream for the command's message.
+    pub fn source(&mut self, guild_id: u64, message_id: u64) -> Box<dyn Source> {
+        Box::new(Seeded::new(self.current_seed(&secret(), guild_id), message_id))
+    }
+
+    /// Add a command's rolls to the log.
+    pub fn record(&mut self, message_id: u64, author: u64, rolled: Vec<DiceRoll>) {
+        let epoch = self.epoch;
+        self.rolls.extend(rolled.into_iter().map(|roll| LoggedRoll { epoch, message_id, author, expression: roll.expression, breakdown: roll.breakdown }));
+        // Whole commands are forgotten at once, so every command left in the log can still be rolled again from its start.
+        while self.rolls.len() > MAX_LOGGED {
+            let oldest = self.rolls[0].message_id;
+            self.rolls.retain(|roll| roll.message_id != oldest);
+        }
+    }
+
+    /// The current seed, noting its commitment if this is the first time it's been used. If it doesn't match the commitment
+    /// noted before, the bot's secret has changed since then, so that seed is lost and the next one is used instead.
+    fn current_seed(&mut self, secret: &[u8; 32], guild_id: u64) -> [u8; 32] {
+        let seed = derive_seed(secret, guild_id, self.epoch);
+        let commitment = commit(&seed);
+        match self.commitment.clone() {
+            Some(noted) if noted != commitment => {
+                self.retire(RevealedSeed { epoch: self.epoch, commitment: noted, seed: None });
+                self.current_seed(secret, guild_id)
+            },
+            _ => {
+                self.commitment = Some(commitment);
+                seed
+            },
+        }
+    }
+
+    fn retire(&mut self, seed: RevealedSeed) {
+        self.revealed.push(seed);
+        if self.revealed.len() > MAX_REVEALED {
+            self.revealed.remove(0);
+        }
+        self.epoch += 1;
+        self.commitment = None;
+    }
+
+    /// Reveal the current seed and move on to the next one. Returns the revealed seed, and how many of the logged rolls
+    /// made with it came out the same when rolled again, out of how many there were.
+    fn reveal(&mut self, secret: &[u8; 32], guild_id: u64) -> (RevealedSeed, usize, usize) {
+        let seed = self.current_seed(secret, guild_id);
+        let rolls: Vec<&LoggedRoll> = self.rolls.iter().filter(|roll| roll.epoch == self.epoch).collect();
+        let matched = replay(&seed, &rolls);
+        let revealed = RevealedSeed {
+            epoch: self.epoch,
+            commitment: self.commitment.clone().unwrap_or_default(),
+            seed: Some(hex(&seed)),
+        };
+        let checked = rolls.len();
+        self.retire(revealed.clone());
+        self.current_seed(secret, guild_id);
+        (revealed, matched, checked)
+    }
+
+    /// The current seed's commitment and the seeds that have been revealed, which anyone can see.
+    fn describe(&self) -> String {
+        let used = self.rolls.iter().filter(|roll| roll.epoch == self.epoch).count();
+        let mut description = format!("**Seed #{}** has SHA-1 `{}`, and has been used for {} logged roll(s).",
+            self.epoch + 1, self.commitment.as_deref().unwrap_or("not worked out yet"), used);
+        if !self.revealed.is_empty() {
+            description.push_str("\n**Revealed seeds**");
+        }
+        for revealed in self.revealed.iter().rev() {
+            match &revealed.seed {
+                Some(seed) => description.push_str(&format!("\n#{}: `{}` (SHA-1 `{}`)", revealed.epoch + 1, seed, revealed.commitment)),
+                None => description.push_str(&format!("\n#{}: lost when the bot restarted with a new secret (SHA-1 `{}`)", revealed.epoch + 1, revealed.commitment)),
+            }
+        }
+        description
+    }
+
+    /// The latest rolls, which can give away hidden stats and secret rolls, so only GMs see them.
+    fn describe_rolls(&self) -> String {
+        let mut description = String::from("**Latest rolls**");
+        if self.rolls.is_empty() {
+            description.push_str("\nNothing has been rolled yet.");
+        }
+        for roll in self.rolls.iter().skip(self.rolls.len().saturating_sub(SHOWN_ROLLS)) {
+            description.push_str(&format!("\n#{} `{}` <@{}>: `{}`", roll.epoch + 1, roll.message_id, roll.author, roll.breakdown));
+        }
+        description
+    }
+}
+
+/// Handle `!rolls`, which shows the current seed's SHA-1 and the seeds revealed so far, and `!rolls reveal`.
+/// GMs are also sent the latest rolls.
+pub fn handle_rolls_command(omnidata: &mut Omnidata, guild_id: u64, arguments: &str) -> Result<Redacted> {
+    let log = &mut omnidata.roll_log;
+    let response = match arguments.trim().to_lowercase().as_str() {
+        "" => {
+            log.current_seed(&secret(), guild_id);
+            log.describe()
+        },
+        "reveal" => {
+            let (revealed, matched, checked) = log.reveal(&secret(), guild_id);
+            // Losing the reveal would mean rolling from a seed everyone knows, so it's saved as a change of its own
+            // rather than waiting for the next one, which some stores do with the roll log.
+            omnidata.is_roll_log_dirty = true;
+            omnidata.dirty();
+            format!("Seed #{} was `{}`, and its SHA-1 is `{}` as promised. {} of the {} logged roll(s) made with it came out the same when rolled again.\nSeed #{} is next, with SHA-1 `{}`.",
+                revealed.epoch + 1, revealed.seed.unwrap_or_default(), revealed.commitment, matched, checked,
+                omnidata.roll_log.epoch + 1, omnidata.roll_log.commitment.as_deref().unwrap_or_default())
+        },
+        _ => return Err(anyhow!("Use `!rolls` to see the current seed, or `!rolls reveal` to reveal it and start a new one.")),
+    };
+    let full = format!("{}\n{}", response, omnidata.roll_log.describe_rolls());
+    match omnidata.gm_role {
+        Some(_) => Ok(Redacted { public: response, secret: Some(full) }),
+        None => Ok(Redacted::public(full)),
+    }
+}
+
+/// Roll each command's logged expressions again from the seed, returning how many came out the same.
+fn replay(seed: &[u8; 32], rolls: &[&LoggedRoll]) -> usize {
+    let mut matched = 0;
+    for command in rolls.chunk_by(|first, second| first.message_id == second.message_id) {
+        let (again, _) = dice::with_source(Box::new(Seeded::new(*seed, command[0].message_id)), || {
+            command.iter().map(|roll| dice::roll(&roll.expression).map(|again| again.breakdown).unwrap_or_default()).collect::<Vec<String>>()
+        });
+        matched += command.iter().zip(again.iter()).filter(|(roll, again)| roll.breakdown == **again).count();
+    }
+    matched
+}
+
+/// The bot's secret, from DICE_SECRET if it's 64 hex digits, and otherwise made up the first time it's needed.
+fn secret() -> [u8; 32] {
+    static SECRET: OnceLock<[u8; 32]> = OnceLock::new();
+    *SECRET.get_or_init(|| match std::env::var("DICE_SECRET").ok().and_then(|secret| parse_hex(&secret)) {
+        Some(secret) => secret,
+        None => {
+            println!("DICE_SECRET isn't set to 64 hex digits, so seeds in use when the bot stops can't be revealed.");
+            let mut secret = [0; 32];
+            rand::thread_rng().fill_bytes(&mut secret);
+            secret
+        },
+    })
+}
+
+/// ChaCha20 keyed with the secret makes a different seed for each guild and epoch, and revealing one of them says nothing
+/// about the secret or any other seed.
+fn derive_seed(secret: &[u8; 32], guild_id: u64, epoch: u64) -> [u8; 32] {
+    let mut rng = ChaCha20Rng::from_seed(*secret);
+    rng.set_stream(guild_id);
+    // Each seed takes 8 of ChaCha20's 32 bit words.
+    rng.set_word_pos(u128::from(epoch) * 8);
+    let mut seed = [0; 32];
+    rng.fill_bytes(&mut seed);
+    seed
+}
+
+fn commit(seed: &[u8; 32]) -> String {
+    hex(&Sha1::digest(seed))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<[u8; 32]> {
+    let text = text.trim();
+    if text.len() != 64 || !text.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; 32];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; 32] = [9; 32];
+    const GUILD: u64 = 1234;
+
+    /// Roll some expressions like a command would, logging them.
+    fn command(log: &mut RollLog, secret: &[u8; 32], message_id: u64, expressions: &[&str]) -> Vec<String> {
+        let source = Box::new(Seeded::new(log.current_seed(secret, GUILD), message_id));
+        let (breakdowns, rolled) = dice::with_source(source, || expressions.iter().map(|expression| dice::roll(expression).unwrap().breakdown).collect());
+        log.record(message_id, 1, rolled);
+        breakdowns
+    }
+
+    #[test]
+    fn seeds() {
+        assert_eq!(derive_seed(&SECRET, GUILD, 0), derive_seed(&SECRET, GUILD, 0));
+        assert_ne!(derive_seed(&SECRET, GUILD, 0), derive_seed(&SECRET, GUILD, 1));
+        assert_ne!(derive_seed(&SECRET, GUILD, 0), derive_seed(&SECRET, GUILD + 1, 0));
+        assert_ne!(derive_seed(&SECRET, GUILD, 0), derive_seed(&[8; 32], GUILD, 0));
+        assert_eq!(commit(&[0; 32]), "de8a847bff8c343d69b853a215e6ee775ef2ef96");
+        assert_eq!(parse_hex(&hex(&SECRET)), Some(SECRET));
+        assert_eq!(parse_hex("12"), None);
+        assert_eq!(parse_hex(&"zz".repeat(32)), None);
+    }
+
+    #[test]
+    fn revealing() {
+        let mut log = RollLog::default();
+        let first = command(&mut log, &SECRET, 100, &["1d20+5", "2d6"]);
+        assert_eq!(command(&mut log, &SECRET, 101, &["4d6kh3"]).len(), 1);
+        assert_eq!(log.rolls.len(), 3);
+        assert_eq!(log.rolls[0].breakdown, first[0]);
+        let commitment = log.commitment.clone().unwrap();
+
+        let (revealed, matched, checked) = log.reveal(&SECRET, GUILD);
+        assert_eq!((matched, checked), (3, 3));
+        assert_eq!(revealed.commitment, commitment);
+        assert_eq!(commit(&parse_hex(&revealed.seed.unwrap()).unwrap()), commitment);
+        assert_eq!(log.epoch, 1);
+        assert_ne!(log.commitment.as_ref(), Some(&commitment));
+
+        // The same message rolls differently with the next seed.
+        assert_ne!(command(&mut log, &SECRET, 100, &["1d20+5", "2d6"]), first);
+        assert_eq!(log.rolls.iter().filter(|roll| roll.epoch == 1).count(), 2);
+    }
+
+    #[test]
+    fn tampering_is_caught() {
+        let mut log = RollLog::default();
+        command(&mut log, &SECRET, 100, &["1d20+5", "1d100"]);
+        log.rolls[1].breakdown = String::from("1d100 = [100] = 100");
+        assert_eq!(log.reveal(&SECRET, GUILD).1, 1);
+    }
+
+    #[test]
+    fn lost_seeds() {
+        let mut log = RollLog::default();
+        command(&mut log, &SECRET, 100, &["1d20"]);
+        let commitment = log.commitment.clone();
+        // The bot restarted with a different secret, so seed #1 can't be worked out anymore.
+        command(&mut log, &[1; 32], 101, &["1d20"]);
+        assert_eq!(log.epoch, 1);
+        assert_eq!(log.revealed, vec![RevealedSeed { epoch: 0, commitment: commitment.unwrap(), seed: None }]);
+        assert_eq!(log.rolls[1].epoch, 1);
+        assert!(log.describe().contains("#1: lost when the bot restarted"));
+    }
+
+    #[test]
+    fn old_commands_are_forgotten_whole() {
+        let mut log = RollLog::default();
+        for message_id in 0..(MAX_LOGGED as u64 / 3 + 1) {
+            command(&mut log, &SECRET, message_id, &["1d4", "1d6", "1d8"]);
+        }
+        assert!(log.rolls.len() <= MAX_LOGGED);
+        assert_eq!(log.rolls.len() % 3, 0);
+        assert_eq!(log.reveal(&SECRET, GUILD).1, log.rolls.len());
+    }
+
+    #[test]
+    fn rolls_command() {
+        let mut omnidata = Omnidata::new();
+        assert!(handle_rolls_command(&mut omnidata, GUILD, "").unwrap().public.contains("**Latest rolls**\nNothing has been rolled yet."));
+        assert!(handle_rolls_command(&mut omnidata, GUILD, "peek").is_err());
+
+        omnidata.gm_role = Some(42);
+        let shown = handle_rolls_command(&mut omnidata, GUILD, "").unwrap();
+        assert!(shown.public.starts_with("**Seed #1** has SHA-1 `"));
+        assert!(shown.secret.unwrap().contains("**Latest rolls**"));
+
+        let revealed = handle_rolls_command(&mut omnidata, GUILD, "REVEAL").unwrap();
+        assert!(revealed.public.starts_with("Seed #1 was `"));
+        assert!(revealed.public.contains("0 of the 0 logged roll(s)"));
+        assert!(omnidata.is_roll_log_dirty && omnidata.is_dirty);
+        assert!(handle_rolls_command(&mut omnidata, GUILD, "").unwrap().public.contains("**Revealed seeds**\n#1: `"));
+    }
+}
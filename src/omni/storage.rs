@@ -0,0 +1,64 @@
+//! Omnidata needs to be saved somewhere between commands. Where that is depends on who is hosting the bot,
+//! so everything that reads or writes it goes through the OmniStore trait. Stores only deal with serialized
+//! data; turning it back into an Omnidata (and migrating it) is left to the caller.
+
+mod filesystem;
+mod sqlite;
+
+use std::{pin::Pin, sync::Arc};
+use anyhow::{Result, anyhow};
+use futures::Future;
+use twilight_http::Client as HttpClient;
+use twilight_model::id::GuildId;
+use crate::discord::DiscordPinStore;
+pub use self::filesystem::FilesystemStore;
+pub use self::sqlite::SqliteStore;
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output=Result<T>> + Send + 'a>>;
+
+/// A single saved version of a guild's omnidata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Revision {
+    pub id: u64,
+    /// Why the revision was saved, usually the command that made the change.
+    pub reason: String,
+    /// True for the revision that is loaded as the guild's current state.
+    pub is_current: bool,
+}
+
+/// Somewhere to keep each guild's omnidata. Every save is kept as a revision, and one of them is current.
+/// Usually that's the newest one, but after an undo there can be newer revisions waiting to be redone.
+/// The guild's roll log is kept on its own, outside the revisions.
+pub trait OmniStore: Send + Sync {
+    /// Load the current omnidata for the guild, or None if nothing has been saved yet.
+    fn load(&self, guild_id: GuildId) -> StoreFuture<'_, Option<String>>;
+
+    /// Save a new revision of the guild's omnidata and make it current. The reason is usually the command that changed it.
+    /// Any revisions newer than the current one are discarded, the same way typing after an undo clears the redo history.
+    fn save(&self, guild_id: GuildId, data: String, reason: String) -> StoreFuture<'_, ()>;
+
+    /// List up to `limit` of the guild's newest revisions, newest first.
+    fn history(&self, guild_id: GuildId, limit: usize) -> StoreFuture<'_, Vec<Revision>>;
+
+    /// Make the given revision current and return its data. If this fails, the current revision is left alone.
+    fn restore(&self, guild_id: GuildId, id: u64) -> StoreFuture<'_, String>;
+
+    /// Load the guild's roll log, or None if it has never been saved. It's kept apart from the revisions, so going back
+    /// to an older one never takes back what was rolled.
+    fn load_roll_log(&self, guild_id: GuildId) -> StoreFuture<'_, Option<String>>;
+
+    /// Replace the guild's roll log. Only the latest one is kept, and undo and redo leave it alone. Stores that can't afford
+    /// to save it on every roll may hold on to it and save it along with the guild's next revision.
+    fn save_roll_log(&self, guild_id: GuildId, data: String) -> StoreFuture<'_, ()>;
+}
+
+/// Open the store described by a config string, usually from the OMNI_STORAGE environment variable.
+/// Understands `discord` (the default), `file:<directory>` and `sqlite:<database file>`.
+pub fn open(config: &str, http: &HttpClient) -> Result<Arc<dyn OmniStore>> {
+    match config.split_once(':') {
+        None if config.is_empty() || config == "discord" => Ok(Arc::new(DiscordPinStore::new(http.clone()))),
+        Some(("file", path)) => Ok(Arc::new(FilesystemStore::new(path))),
+        Some(("sqlite", path)) => Ok(Arc::new(SqliteStore::open(path)?)),
+        _ => Err(anyhow!("Unknown storage '{}'. Expected discord, file:<directory> or sqlite:<database file>.", config)),
+    }
+}
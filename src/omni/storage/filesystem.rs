@@ -0,0 +1,224 @@
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use twilight_model::id::GuildId;
+use super::{OmniStore, Revision, StoreFuture};
+
+const INDEX_FILE_NAME: &str = "index.json";
+const ROLL_LOG_FILE_NAME: &str = "rolls.json";
+
+/// Keeps omnidata in a local directory, with one subdirectory per guild.
+/// Each save is written as its own numbered file, and an index file keeps track of which one is current.
+/// The roll log has a file of its own, which is simply replaced each time.
+pub struct FilesystemStore {
+    root: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Index {
+    head: Option<u64>,
+    revisions: Vec<IndexEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct IndexEntry {
+    id: u64,
+    reason: String,
+}
+
+impl FilesystemStore {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        FilesystemStore { root: root.as_ref().to_path_buf() }
+    }
+
+    fn guild_dir(&self, guild_id: GuildId) -> PathBuf {
+        self.root.join(guild_id.0.to_string())
+    }
+
+    fn revision_path(&self, guild_id: GuildId, id: u64) -> PathBuf {
+        self.guild_dir(guild_id).join(format!("{}.json", id))
+    }
+
+    async fn read_index(&self, guild_id: GuildId) -> Result<Index> {
+        let path = self.guild_dir(guild_id).join(INDEX_FILE_NAME);
+        if !path.exists() {
+            return Ok(Index::default());
+        }
+        let data = fs::read_to_string(&path).await.with_context(|| format!("Could not read {}", path.display()))?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    async fn write_index(&self, guild_id: GuildId, index: &Index) -> Result<()> {
+        let path = self.guild_dir(guild_id).join(INDEX_FILE_NAME);
+        write_atomically(&path, serde_json::to_string_pretty(index)?).await
+    }
+
+    async fn load_head(&self, guild_id: GuildId) -> Result<Option<String>> {
+        match self.read_index(guild_id).await?.head {
+            None => Ok(None),
+            Some(head) => {
+                let path = self.revision_path(guild_id, head);
+                Ok(Some(fs::read_to_string(&path).await.with_context(|| format!("Could not read {}", path.display()))?))
+            }
+        }
+    }
+
+    async fn save_revision(&self, guild_id: GuildId, data: String, reason: String) -> Result<()> {
+        fs::create_dir_all(self.guild_dir(guild_id)).await?;
+        let mut index = self.read_index(guild_id).await?;
+        let id = index.revisions.iter().map(|revision| revision.id + 1).max().unwrap_or(0);
+
+        // Anything newer than the head was undone, and saving over it means it can't be redone anymore.
+        let discarded: Vec<u64> = match index.head {
+            Some(head) => index.revisions.iter().map(|revision| revision.id).filter(|&id| id > head).collect(),
+            None => Vec::new(),
+        };
+        index.revisions.retain(|revision| !discarded.contains(&revision.id));
+
+        // The revision is written before the index points at it, so a crash part way through never leaves the index dangling.
+        write_atomically(&self.revision_path(guild_id, id), data).await?;
+        index.revisions.push(IndexEntry { id, reason });
+        index.head = Some(id);
+        self.write_index(guild_id, &index).await?;
+
+        for discarded_id in discarded {
+            fs::remove_file(self.revision_path(guild_id, discarded_id)).await?;
+        }
+        Ok(())
+    }
+
+    async fn list_revisions(&self, guild_id: GuildId, limit: usize) -> Result<Vec<Revision>> {
+        let index = self.read_index(guild_id).await?;
+        Ok(index.revisions.iter().rev().take(limit).map(|revision| Revision {
+            id: revision.id,
+            reason: revision.reason.clone(),
+            is_current: index.head == Some(revision.id),
+        }).collect())
+    }
+
+    async fn restore_revision(&self, guild_id: GuildId, id: u64) -> Result<String> {
+        let mut index = self.read_index(guild_id).await?;
+        if !index.revisions.iter().any(|revision| revision.id == id) {
+            return Err(anyhow!("There is no revision {} to restore", id));
+        }
+        let path = self.revision_path(guild_id, id);
+        let data = fs::read_to_string(&path).await.with_context(|| format!("Could not read {}", path.display()))?;
+
+        // Moving the head is a single atomic index write, so the restore either fully happens or doesn't at all.
+        index.head = Some(id);
+        self.write_index(guild_id, &index).await?;
+        Ok(data)
+    }
+
+    async fn read_roll_log(&self, guild_id: GuildId) -> Result<Option<String>> {
+        let path = self.guild_dir(guild_id).join(ROLL_LOG_FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(&path).await.with_context(|| format!("Could not read {}", path.display()))?))
+    }
+
+    async fn write_roll_log(&self, guild_id: GuildId, data: String) -> Result<()> {
+        fs::create_dir_all(self.guild_dir(guild_id)).await?;
+        write_atomically(&self.guild_dir(guild_id).join(ROLL_LOG_FILE_NAME), data).await
+    }
+}
+
+impl OmniStore for FilesystemStore {
+    fn load(&self, guild_id: GuildId) -> StoreFuture<'_, Option<String>> {
+        Box::pin(self.load_head(guild_id))
+    }
+
+    fn save(&self, guild_id: GuildId, data: String, reason: String) -> StoreFuture<'_, ()> {
+        Box::pin(self.save_revision(guild_id, data, reason))
+    }
+
+    fn history(&self, guild_id: GuildId, limit: usize) -> StoreFuture<'_, Vec<Revision>> {
+        Box::pin(self.list_revisions(guild_id, limit))
+    }
+
+    fn restore(&self, guild_id: GuildId, id: u64) -> StoreFuture<'_, String> {
+        Box::pin(self.restore_revision(guild_id, id))
+    }
+
+    fn load_roll_log(&self, guild_id: GuildId) -> StoreFuture<'_, Option<String>> {
+        Box::pin(self.read_roll_log(guild_id))
+    }
+
+    fn save_roll_log(&self, guild_id: GuildId, data: String) -> StoreFuture<'_, ()> {
+        Box::pin(self.write_roll_log(guild_id, data))
+    }
+}
+
+/// Write to a temporary file first and then rename it over the real one, so readers never see half a file.
+async fn write_atomically(path: &Path, data: String) -> Result<()> {
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, data).await.with_context(|| format!("Could not write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path).await.with_context(|| format!("Could not replace {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn load_before_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FilesystemStore::new(dir.path());
+        assert_eq!(store.load(GuildId(1)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FilesystemStore::new(dir.path());
+        store.save(GuildId(1), String::from("first"), String::from("!add player Plunk")).await.unwrap();
+        store.save(GuildId(1), String::from("second"), String::from("!add enemy Slurk")).await.unwrap();
+        store.save(GuildId(2), String::from("other guild"), String::from("!add player Bob")).await.unwrap();
+
+        assert_eq!(store.load(GuildId(1)).await.unwrap(), Some(String::from("second")));
+        assert_eq!(store.load(GuildId(2)).await.unwrap(), Some(String::from("other guild")));
+        assert!(dir.path().join("1").join("0.json").exists());
+    }
+
+    #[tokio::test]
+    async fn restore_and_discard_redo() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FilesystemStore::new(dir.path());
+        for data in ["zero", "one", "two"].iter() {
+            store.save(GuildId(1), data.to_string(), format!("!add player {}", data)).await.unwrap();
+        }
+
+        assert_eq!(store.restore(GuildId(1), 0).await.unwrap(), "zero");
+        assert_eq!(store.load(GuildId(1)).await.unwrap(), Some(String::from("zero")));
+        let history = store.history(GuildId(1), 10).await.unwrap();
+        assert_eq!(history.iter().map(|revision| revision.id).collect::<Vec<u64>>(), vec![2, 1, 0]);
+        assert!(history[2].is_current);
+        assert_eq!(history[0].reason, "!add player two");
+
+        store.save(GuildId(1), String::from("three"), String::from("!add player three")).await.unwrap();
+        let history = store.history(GuildId(1), 10).await.unwrap();
+        assert_eq!(history.iter().map(|revision| revision.id).collect::<Vec<u64>>(), vec![3, 0]);
+        assert!(!dir.path().join("1").join("1.json").exists());
+        assert!(store.restore(GuildId(1), 1).await.is_err());
+        assert_eq!(store.load(GuildId(1)).await.unwrap(), Some(String::from("three")));
+    }
+
+    #[tokio::test]
+    async fn roll_log_outlasts_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FilesystemStore::new(dir.path());
+        assert_eq!(store.load_roll_log(GuildId(1)).await.unwrap(), None);
+        store.save(GuildId(1), String::from("zero"), String::from("!add player Plunk")).await.unwrap();
+        store.save(GuildId(1), String::from("one"), String::from("!add enemy Slurk")).await.unwrap();
+        store.save_roll_log(GuildId(1), String::from("first rolls")).await.unwrap();
+        store.save_roll_log(GuildId(1), String::from("more rolls")).await.unwrap();
+
+        store.restore(GuildId(1), 0).await.unwrap();
+        assert_eq!(store.load_roll_log(GuildId(1)).await.unwrap(), Some(String::from("more rolls")));
+        assert_eq!(store.history(GuildId(1), 10).await.unwrap().len(), 2);
+        assert_eq!(store.load_roll_log(GuildId(2)).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,220 @@
+use std::{path::Path, sync::{Arc, Mutex}};
+use anyhow::{Result, anyhow};
+use rusqlite::{Connection, OptionalExtension, params};
+use twilight_model::id::GuildId;
+use super::{OmniStore, Revision, StoreFuture};
+
+/// Keeps omnidata in an SQLite database. Every save is kept as its own row, and the heads table points at each guild's current one.
+/// Each guild's roll log is a single row of the roll_logs table, replaced whenever it's saved.
+/// SQLite blocks while it reads and writes, so that's done on tokio's blocking threads rather than holding up every other guild's commands.
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at the given path and make sure the tables exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS revisions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                guild_id INTEGER NOT NULL,
+                reason TEXT NOT NULL,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS revisions_by_guild ON revisions (guild_id, id);
+            CREATE TABLE IF NOT EXISTS heads (
+                guild_id INTEGER PRIMARY KEY,
+                revision_id INTEGER NOT NULL REFERENCES revisions (id)
+            );
+            CREATE TABLE IF NOT EXISTS roll_logs (
+                guild_id INTEGER PRIMARY KEY,
+                data TEXT NOT NULL
+            );"
+        )?;
+        Ok(SqliteStore { connection: Arc::new(Mutex::new(connection)) })
+    }
+
+    /// Do some work with the connection on a blocking thread.
+    fn blocking<T: Send + 'static>(&self, work: impl FnOnce(&mut Connection) -> Result<T> + Send + 'static) -> StoreFuture<'_, T> {
+        let connection = Arc::clone(&self.connection);
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let mut connection = connection.lock().map_err(|_| anyhow!("SQLite connection was poisoned by an earlier panic"))?;
+                work(&mut connection)
+            }).await?
+        })
+    }
+
+    /// The id of the guild's current revision. Guilds without a head (saved before heads existed) use their newest revision.
+    fn head(connection: &Connection, guild_id: GuildId) -> Result<Option<i64>> {
+        Ok(connection.query_row(
+            "SELECT COALESCE(
+                (SELECT revision_id FROM heads WHERE guild_id = ?1),
+                (SELECT MAX(id) FROM revisions WHERE guild_id = ?1)
+            )",
+            params![guild_id.0 as i64],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn set_head(connection: &Connection, guild_id: GuildId, revision_id: i64) -> Result<()> {
+        connection.execute(
+            "INSERT INTO heads (guild_id, revision_id) VALUES (?1, ?2)
+            ON CONFLICT (guild_id) DO UPDATE SET revision_id = excluded.revision_id",
+            params![guild_id.0 as i64, revision_id],
+        )?;
+        Ok(())
+    }
+
+    fn load_head(connection: &mut Connection, guild_id: GuildId) -> Result<Option<String>> {
+        match Self::head(connection, guild_id)? {
+            None => Ok(None),
+            Some(head) => Ok(Some(connection.query_row("SELECT data FROM revisions WHERE id = ?1", params![head], |row| row.get(0))?)),
+        }
+    }
+
+    fn insert(connection: &mut Connection, guild_id: GuildId, data: String, reason: String) -> Result<()> {
+        let transaction = connection.transaction()?;
+        if let Some(head) = Self::head(&transaction, guild_id)? {
+            // Anything newer than the head was undone, and saving over it means it can't be redone anymore.
+            transaction.execute("DELETE FROM revisions WHERE guild_id = ?1 AND id > ?2", params![guild_id.0 as i64, head])?;
+        }
+        transaction.execute(
+            "INSERT INTO revisions (guild_id, reason, data) VALUES (?1, ?2, ?3)",
+            params![guild_id.0 as i64, reason, data],
+        )?;
+        Self::set_head(&transaction, guild_id, transaction.last_insert_rowid())?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn list(connection: &mut Connection, guild_id: GuildId, limit: usize) -> Result<Vec<Revision>> {
+        let head = Self::head(connection, guild_id)?;
+        let mut statement = connection.prepare("SELECT id, reason FROM revisions WHERE guild_id = ?1 ORDER BY id DESC LIMIT ?2")?;
+        let revisions = statement.query_map(params![guild_id.0 as i64, limit as i64], |row| {
+            let id: i64 = row.get(0)?;
+            Ok(Revision { id: id as u64, reason: row.get(1)?, is_current: head == Some(id) })
+        })?;
+        Ok(revisions.collect::<rusqlite::Result<Vec<Revision>>>()?)
+    }
+
+    fn restore_revision(connection: &mut Connection, guild_id: GuildId, id: u64) -> Result<String> {
+        let transaction = connection.transaction()?;
+        let data: String = transaction.query_row(
+            "SELECT data FROM revisions WHERE guild_id = ?1 AND id = ?2",
+            params![guild_id.0 as i64, id as i64],
+            |row| row.get(0),
+        ).optional()?.ok_or_else(|| anyhow!("There is no revision {} to restore", id))?;
+        Self::set_head(&transaction, guild_id, id as i64)?;
+        transaction.commit()?;
+        Ok(data)
+    }
+
+    fn load_rolls(connection: &mut Connection, guild_id: GuildId) -> Result<Option<String>> {
+        Ok(connection.query_row("SELECT data FROM roll_logs WHERE guild_id = ?1", params![guild_id.0 as i64], |row| row.get(0)).optional()?)
+    }
+
+    fn replace_rolls(connection: &mut Connection, guild_id: GuildId, data: String) -> Result<()> {
+        connection.execute(
+            "INSERT INTO roll_logs (guild_id, data) VALUES (?1, ?2)
+            ON CONFLICT (guild_id) DO UPDATE SET data = excluded.data",
+            params![guild_id.0 as i64, data],
+        )?;
+        Ok(())
+    }
+}
+
+impl OmniStore for SqliteStore {
+    fn load(&self, guild_id: GuildId) -> StoreFuture<'_, Option<String>> {
+        self.blocking(move |connection| Self::load_head(connection, guild_id))
+    }
+
+    fn save(&self, guild_id: GuildId, data: String, reason: String) -> StoreFuture<'_, ()> {
+        self.blocking(move |connection| Self::insert(connection, guild_id, data, reason))
+    }
+
+    fn history(&self, guild_id: GuildId, limit: usize) -> StoreFuture<'_, Vec<Revision>> {
+        self.blocking(move |connection| Self::list(connection, guild_id, limit))
+    }
+
+    fn restore(&self, guild_id: GuildId, id: u64) -> StoreFuture<'_, String> {
+        self.blocking(move |connection| Self::restore_revision(connection, guild_id, id))
+    }
+
+    fn load_roll_log(&self, guild_id: GuildId) -> StoreFuture<'_, Option<String>> {
+        self.blocking(move |connection| Self::load_rolls(connection, guild_id))
+    }
+
+    fn save_roll_log(&self, guild_id: GuildId, data: String) -> StoreFuture<'_, ()> {
+        self.blocking(move |connection| Self::replace_rolls(connection, guild_id, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::open(dir.path().join("omni.db")).unwrap();
+        assert_eq!(store.load(GuildId(1)).await.unwrap(), None);
+
+        store.save(GuildId(1), String::from("first"), String::from("!add player Plunk")).await.unwrap();
+        store.save(GuildId(1), String::from("second"), String::from("!add enemy Slurk")).await.unwrap();
+        store.save(GuildId(2), String::from("other guild"), String::from("!add player Bob")).await.unwrap();
+
+        assert_eq!(store.load(GuildId(1)).await.unwrap(), Some(String::from("second")));
+        assert_eq!(store.load(GuildId(2)).await.unwrap(), Some(String::from("other guild")));
+    }
+
+    #[tokio::test]
+    async fn survives_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("omni.db");
+        SqliteStore::open(&path).unwrap().save(GuildId(1), String::from("saved"), String::from("!add player Plunk")).await.unwrap();
+        assert_eq!(SqliteStore::open(&path).unwrap().load(GuildId(1)).await.unwrap(), Some(String::from("saved")));
+    }
+
+    #[tokio::test]
+    async fn restore_and_discard_redo() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::open(dir.path().join("omni.db")).unwrap();
+        for data in ["one", "two", "three"].iter() {
+            store.save(GuildId(1), data.to_string(), format!("!add player {}", data)).await.unwrap();
+        }
+        store.save(GuildId(2), String::from("other guild"), String::from("!add player Bob")).await.unwrap();
+
+        assert_eq!(store.restore(GuildId(1), 1).await.unwrap(), "one");
+        assert_eq!(store.load(GuildId(1)).await.unwrap(), Some(String::from("one")));
+        assert!(store.restore(GuildId(1), 4).await.is_err());
+        let history = store.history(GuildId(1), 10).await.unwrap();
+        assert_eq!(history.iter().map(|revision| revision.id).collect::<Vec<u64>>(), vec![3, 2, 1]);
+        assert!(history[2].is_current);
+        assert_eq!(history[0].reason, "!add player three");
+
+        store.save(GuildId(1), String::from("five"), String::from("!add player five")).await.unwrap();
+        let history = store.history(GuildId(1), 10).await.unwrap();
+        assert_eq!(history.iter().map(|revision| revision.id).collect::<Vec<u64>>(), vec![5, 1]);
+        assert!(history[0].is_current);
+        assert_eq!(store.load(GuildId(2)).await.unwrap(), Some(String::from("other guild")));
+    }
+
+    #[tokio::test]
+    async fn roll_log_outlasts_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("omni.db");
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.load_roll_log(GuildId(1)).await.unwrap(), None);
+        store.save(GuildId(1), String::from("one"), String::from("!add player Plunk")).await.unwrap();
+        store.save(GuildId(1), String::from("two"), String::from("!add enemy Slurk")).await.unwrap();
+        store.save_roll_log(GuildId(1), String::from("first rolls")).await.unwrap();
+        store.save_roll_log(GuildId(1), String::from("more rolls")).await.unwrap();
+
+        store.restore(GuildId(1), 1).await.unwrap();
+        let reopened = SqliteStore::open(&path).unwrap();
+        assert_eq!(reopened.load_roll_log(GuildId(1)).await.unwrap(), Some(String::from("more rolls")));
+        assert_eq!(reopened.load_roll_log(GuildId(2)).await.unwrap(), None);
+    }
+}
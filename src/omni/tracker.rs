@@ -0,0 +1,212 @@
+//! The tracker is a message the bot keeps up to date with the state of the game: the turn order during an encounter,
+//! and each character's tracker stats (the ones added with a `!` in front) and conditions.
+//! Each channel can have one. Rather than posting a new one after every change, the bot edits the same message in place,
+//! so it can be pinned or just left at the top of the channel.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use twilight_model::id::GuildId;
+use crate::discord::DiscordReferences;
+use super::Omnidata;
+use super::character::Character;
+use super::storage::OmniStore;
+
+/// Discord won't send messages longer than this.
+const MESSAGE_LIMIT: usize = 2000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tracker {
+    pub channel: u64,
+    pub message: u64,
+}
+
+/// Handle the tracker command. `!tracker` posts a tracker in the channel, replacing any it already had,
+/// and `!tracker off` removes it. Like history, this needs to talk to Discord before it knows what to save.
+pub async fn handle_tracker_command(
+    discord_refs: &DiscordReferences<'_>,
+    omnidata: &mut Omnidata,
+    store: &dyn OmniStore,
+    guild_id: GuildId,
+    arguments: &str,
+) -> Result<()> {
+    let channel = discord_refs.msg.channel_id.0;
+    let turning_off = match arguments.trim().to_lowercase().as_str() {
+        "" => false,
+        "off" => true,
+        _ => return discord_refs.send_message_reply("Use `!tracker` to show a tracker in this channel, or `!tracker off` to remove it.").await,
+    };
+
+    if let Some(old) = omnidata.forget_tracker(channel) {
+        discord_refs.delete_message(old.channel, old.message).await?;
+    }
+    if turning_off {
+        super::save_omnidata(store, guild_id, omnidata, &discord_refs.msg.content).await?;
+        omnidata.saved();
+        return discord_refs.send_message_reply("Removed the tracker from this channel.").await;
+    }
+    let message = discord_refs.send_tracked_message(omnidata.render_tracker()).await?;
+    omnidata.trackers.push(Tracker { channel, message });
+    omnidata.dirty();
+    super::save_omnidata(store, guild_id, omnidata, &discord_refs.msg.content).await?;
+    omnidata.saved();
+    Ok(())
+}
+
+/// Bring every tracker up to date after a change. Trackers whose messages have been deleted are forgotten,
+/// which gets saved along with the next change.
+pub async fn refresh_trackers(discord_refs: &DiscordReferences<'_>, omnidata: &mut Omnidata) {
+    if omnidata.trackers.is_empty() {
+        return;
+    }
+    let text = omnidata.render_tracker();
+    let edits = omnidata.trackers.iter().map(|tracker| discord_refs.edit_message(tracker.channel, tracker.message, &text));
+    let results = futures::future::join_all(edits).await;
+    let mut gone = Vec::new();
+    for (tracker, result) in omnidata.trackers.iter().zip(results) {
+        match result {
+            Ok(true) => {},
+            Ok(false) => gone.push(tracker.channel),
+            Err(error) => println!("Couldn't update the tracker in channel {}: {:?}", tracker.channel, error),
+        }
+    }
+    for channel in gone {
+        omnidata.forget_tracker(channel);
+    }
+}
+
+impl Omnidata {
+    /// Stop keeping track of a channel's tracker, returning it if there was one.
+    fn forget_tracker(&mut self, channel: u64) -> Option<Tracker> {
+        let index = self.trackers.iter().position(|tracker| tracker.channel == channel)?;
+        self.dirty();
+        Some(self.trackers.remove(index))
+    }
+
+    /// The text of the tracker. During an encounter, characters are listed in turn order with an arrow marking whose turn it is.
+    /// Otherwise everyone is listed.
+    pub fn render_tracker(&self) -> String {
+        let mut text = String::new();
+        match &self.encounter {
+            Some(encounter) => {
+                text.push_str(&match encounter.round {
+                    0 => String::from("**Initiative**"),
+                    round => format!("**Round {}**", round),
+                });
+                for combatant in encounter.combatants.iter() {
+                    let marker = if encounter.current == Some(combatant.character) { "➤" } else { "  " };
+                    let initiative = combatant.initiative.map(|initiative| initiative.to_string()).unwrap_or_else(|| String::from("?"));
+                    let line = match self.find_character_by_id(combatant.character) {
+                        Some(character) => describe_for_tracker(character, self.hides(character)),
+                        None => self.character_name(combatant.character),
+                    };
+                    text.push_str(&format!("\n{} {} {}", marker, initiative, line));
+                    if combatant.delaying {
+                        text.push_str(" · delaying");
+                    }
+                    if let Some(trigger) = &combatant.readied {
+                        text.push_str(&format!(" · readied: {}", trigger));
+                    }
+                }
+            },
+            None => {
+                text.push_str("**Tracker**");
+                if self.characters.is_empty() {
+                    text.push_str("\nNo characters yet.");
+                }
+                for character in self.characters.iter() {
+                    text.push_str(&format!("\n{}", describe_for_tracker(character, self.hides(character))));
+                }
+            },
+        }
+        if text.chars().count() > MESSAGE_LIMIT {
+            text = text.chars().take(MESSAGE_LIMIT - 1).collect();
+            text.push('…');
+        }
+        text
+    }
+}
+
+/// One line about a character: their name, tracker stats, temporary HP and conditions.
+/// If their stats are hidden, only a rough idea of their health is shown instead of any stats.
+fn describe_for_tracker(character: &Character, hidden: bool) -> String {
+    let mut parts = vec![format!("**{}**", character.name)];
+    if hidden {
+        parts.extend(character.describe_health());
+    } else {
+        parts.extend(character.stats.iter().filter(|stat| stat.display_on_tracker).map(|stat| stat.to_string()));
+        if character.temp_hp > 0 {
+            parts.push(format!("+{} temp HP", character.temp_hp));
+        }
+    }
+    let conditions = character.describe_conditions();
+    if !conditions.is_empty() {
+        parts.push(conditions);
+    }
+    parts.join(" · ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::omni::character::{CharacterKind, parse_noun_target_stats_command};
+
+    fn omnidata() -> Omnidata {
+        let mut omnidata = Omnidata::new();
+        let mut plunk = Character::new("Plunk", CharacterKind::Player, 1);
+        plunk.stats = parse_noun_target_stats_command("player Plunk !HP:22/30 AC:18").unwrap().stats;
+        plunk.temp_hp = 4;
+        plunk.add_condition("frightened", Some("1")).unwrap();
+        omnidata.insert_character(plunk);
+        let mut slurk = Character::new("Slurk", CharacterKind::Npc, 2);
+        slurk.stats = parse_noun_target_stats_command("enemy Slurk !HP:40/40").unwrap().stats;
+        omnidata.insert_character(slurk);
+        omnidata
+    }
+
+    #[test]
+    fn outside_encounters() {
+        assert_eq!(Omnidata::new().render_tracker(), "**Tracker**\nNo characters yet.");
+        assert_eq!(omnidata().render_tracker(), "**Tracker**\n**Plunk** · HP: 22/30 · +4 temp HP · frightened 1\n**Slurk** · HP: 40/40");
+    }
+
+    #[test]
+    fn during_encounters() {
+        let mut omnidata = omnidata();
+        omnidata.start_encounter(&[1, 2]).unwrap();
+        omnidata.set_initiative(1, Some(12)).unwrap();
+        assert_eq!(omnidata.render_tracker(), "**Initiative**\n   12 **Plunk** · HP: 22/30 · +4 temp HP · frightened 1\n   ? **Slurk** · HP: 40/40");
+        omnidata.set_initiative(2, Some(20)).unwrap();
+        omnidata.next_turn(None).unwrap();
+        omnidata.ready_action(1, Some(String::from("Slurk moves"))).unwrap();
+        assert_eq!(omnidata.render_tracker(), "**Round 1**\n➤ 20 **Slurk** · HP: 40/40\n   12 **Plunk** · HP: 22/30 · +4 temp HP · frightened 1 · readied: Slurk moves");
+    }
+
+    #[test]
+    fn enemies_are_hidden_from_players() {
+        let mut omnidata = omnidata();
+        omnidata.gm_role = Some(42);
+        assert_eq!(omnidata.render_tracker(), "**Tracker**\n**Plunk** · HP: 22/30 · +4 temp HP · frightened 1\n**Slurk** · Health: Unharmed");
+    }
+
+    #[test]
+    fn long_trackers_are_cut_short() {
+        let mut omnidata = Omnidata::new();
+        for number in 0..200 {
+            omnidata.insert_character(Character::new(&format!("Goblin {}", number), CharacterKind::Npc, 2));
+        }
+        let text = omnidata.render_tracker();
+        assert_eq!(text.chars().count(), MESSAGE_LIMIT);
+        assert!(text.ends_with('…'));
+    }
+
+    #[test]
+    fn forgetting() {
+        let mut omnidata = Omnidata::new();
+        omnidata.trackers.push(Tracker { channel: 1, message: 10 });
+        omnidata.trackers.push(Tracker { channel: 2, message: 20 });
+        assert_eq!(omnidata.forget_tracker(1), Some(Tracker { channel: 1, message: 10 }));
+        assert_eq!(omnidata.forget_tracker(1), None);
+        assert_eq!(omnidata.trackers.len(), 1);
+        assert!(omnidata.is_dirty);
+    }
+}